@@ -20,6 +20,11 @@ struct Args {
 
     #[clap(long, default_value = "10")]
     recv_timeout: u64,
+
+    /// directory to persist backend data in. Each backend uses its own
+    /// sub-directory. If unset, backends only keep their data in memory.
+    #[clap(short, long)]
+    data_dir: Option<String>,
 }
 
 #[tokio::main]
@@ -32,6 +37,7 @@ async fn main() -> TribResult<()> {
         args.cfg,
         args.ready_addrs,
         args.recv_timeout,
+        args.data_dir,
     )
     .await
 }
@@ -28,8 +28,12 @@ async fn main() -> TribResult<()> {
     let args = Options::parse();
     env_logger::builder().filter_level(args.log).init();
     let cfg = Config::read(Some(&args.config))?;
-    let addrs = cfg.backs;
-    let bc = lab2::new_bin_client(addrs).await?;
+    let bc = match cfg.quorum {
+        Some(quorum) => {
+            lab2::new_quorum_bin_client(cfg.backs.clone(), &cfg.client_config(), quorum).await?
+        }
+        None => lab2::new_bin_client_with(cfg.backs.clone(), &cfg.client_config()).await?,
+    };
     let app = Command::new("bin-client")
         .subcommands(app_commands())
         .subcommands(bin_cmd());
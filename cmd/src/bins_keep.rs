@@ -20,6 +20,11 @@ struct Args {
 
     #[clap(long, default_value = "10")]
     recv_timeout: u64,
+
+    /// directory to persist the keepers' replicated log in. Each keeper uses
+    /// its own sub-directory. If unset, keepers only keep it in memory.
+    #[clap(short, long)]
+    data_dir: Option<String>,
 }
 
 #[tokio::main]
@@ -32,6 +37,7 @@ async fn main() -> TribResult<()> {
         args.config,
         args.ready_addrs,
         args.recv_timeout,
+        args.data_dir,
     )
     .await
 }
@@ -3,7 +3,7 @@ use std::process;
 use clap::Parser;
 use tribbler::{
     addr,
-    config::{self, DEFAULT_CONFIG_LOCATION},
+    config::{self, AuthConfig, Compression, QuorumConfig, TlsConfig, DEFAULT_CONFIG_LOCATION},
     err::TribResult,
 };
 
@@ -28,6 +28,50 @@ struct Options {
     /// whether or not to used fixed versus random port numbers
     #[clap(short, long)]
     fix: bool,
+    /// whether the backends should use hybrid logical clocks
+    #[clap(long)]
+    hybrid_clock: bool,
+    /// PEM file of the certificate authority to trust. Setting this turns
+    /// on TLS between clients and backends
+    #[clap(long)]
+    ca_cert: Option<String>,
+    /// PEM certificate file the backends and clients present
+    #[clap(long, default_value = "")]
+    cert: String,
+    /// PEM private key file for --cert
+    #[clap(long, default_value = "")]
+    key: String,
+    /// whether backends should also require clients to present a certificate
+    #[clap(long)]
+    mutual_tls: bool,
+    /// the host name clients expect in the backends' certificate
+    #[clap(long, default_value = "")]
+    tls_domain: String,
+    /// token allowed to make every call to the backends. Specify this flag
+    /// multiple times to allow more than one token
+    #[clap(long)]
+    read_write_token: Vec<String>,
+    /// token only allowed to read from the backends. Specify this flag
+    /// multiple times to allow more than one token
+    #[clap(long)]
+    read_only_token: Vec<String>,
+    /// token front-ends and keepers present to the backends. Defaults to the
+    /// first --read-write-token
+    #[clap(long)]
+    token: Option<String>,
+    /// whether calls to the backends should be compressed with gzip
+    #[clap(long)]
+    gzip: bool,
+    /// number of backends each bin is replicated on. Setting this turns on
+    /// quorum reads and writes
+    #[clap(long)]
+    replicas: Option<usize>,
+    /// number of replicas a read must hear from. Defaults to a majority
+    #[clap(long)]
+    read_quorum: Option<usize>,
+    /// number of replicas a write must reach. Defaults to a majority
+    #[clap(long)]
+    write_quorum: Option<usize>,
 }
 
 fn main() -> TribResult<()> {
@@ -59,7 +103,44 @@ fn main() -> TribResult<()> {
         p += 1;
     }
 
-    let cfg = config::Config { backs, keepers };
+    let cfg = config::Config {
+        backs,
+        keepers,
+        hybrid_clock: args.hybrid_clock,
+        tls: args.ca_cert.map(|ca_cert| TlsConfig {
+            ca_cert,
+            cert: args.cert,
+            key: args.key,
+            mutual: args.mutual_tls,
+            domain: args.tls_domain,
+        }),
+        auth: match args.read_write_token.is_empty() && args.read_only_token.is_empty() {
+            true => None,
+            false => Some(AuthConfig {
+                token: args
+                    .token
+                    .or_else(|| args.read_write_token.first().cloned())
+                    .unwrap_or_default(),
+                read_write: args.read_write_token,
+                read_only: args.read_only_token,
+            }),
+        },
+        compression: match args.gzip {
+            true => Compression::Gzip,
+            false => Compression::None,
+        },
+        quorum: args.replicas.map(|n| QuorumConfig {
+            n,
+            r: args.read_quorum.unwrap_or(n / 2 + 1),
+            w: args.write_quorum.unwrap_or(n / 2 + 1),
+        }),
+    };
+    if let Some(quorum) = &cfg.quorum {
+        if let Err(e) = quorum.validate(cfg.backs.len()) {
+            eprintln!("{}", e);
+            process::exit(1)
+        }
+    }
 
     cfg.write(Some(&args.file))
 }
@@ -1,4 +1,5 @@
 use std::{
+    path::Path,
     process,
     sync::{
         mpsc::{self, Sender},
@@ -10,7 +11,13 @@ use std::{
 use lab::{lab1, lab2};
 use log::{error, info, warn, LevelFilter};
 use tokio::join;
-use tribbler::{addr, config::Config, err::TribResult, storage::MemStorage};
+use tribbler::{
+    addr,
+    config::Config,
+    disk::DiskStorage,
+    err::TribResult,
+    storage::{MemStorage, Storage},
+};
 
 #[derive(Debug, Clone)]
 pub enum ProcessType {
@@ -24,6 +31,7 @@ pub async fn main(
     cfg: String,
     _ready_addrs: Vec<String>,
     recv_timeout: u64,
+    data_dir: Option<String>,
 ) -> TribResult<()> {
     env_logger::builder()
         .default_format()
@@ -46,6 +54,7 @@ pub async fn main(
                 i,
                 config.clone(),
                 Some(tx.clone()),
+                data_dir.clone(),
             )));
         }
     }
@@ -83,15 +92,52 @@ pub async fn main(
 }
 
 #[allow(unused_must_use)]
-async fn run_srv(t: ProcessType, idx: usize, config: Arc<Config>, tx: Option<Sender<bool>>) {
+async fn run_srv(
+    t: ProcessType,
+    idx: usize,
+    config: Arc<Config>,
+    tx: Option<Sender<bool>>,
+    data_dir: Option<String>,
+) {
     match t {
         ProcessType::Back => {
-            let cfg = config.back_config(idx, Box::new(MemStorage::default()), tx, None);
+            let store: Box<dyn Storage> = match data_dir {
+                Some(dir) => {
+                    let dir = Path::new(&dir).join(format!("back-{}", idx));
+                    match DiskStorage::open(&dir).await {
+                        Ok(s) if config.hybrid_clock => Box::new(s.with_hybrid_clock()),
+                        Ok(s) => Box::new(s),
+                        Err(e) => {
+                            error!("failed to open storage in {}: {}", dir.display(), e);
+                            if let Some(tx) = tx {
+                                tx.send(false);
+                            }
+                            return;
+                        }
+                    }
+                }
+                None if config.hybrid_clock => Box::new(MemStorage::new().with_hybrid_clock()),
+                None => Box::new(MemStorage::default()),
+            };
+            let cfg = config.back_config(idx, store, tx, None);
             info!("starting backend on {}", cfg.addr);
             lab1::serve_back(cfg).await;
         }
         ProcessType::Keep => {
-            let cfg = config.keeper_config(idx, tx, None).unwrap();
+            let mut cfg = config.keeper_config(idx, tx, None).unwrap();
+            if let Some(dir) = data_dir {
+                let dir = Path::new(&dir).join(format!("keeper-{}", idx));
+                match DiskStorage::open(&dir).await {
+                    Ok(s) => cfg.state = Some(Box::new(s)),
+                    Err(e) => {
+                        error!("failed to open keeper state in {}: {}", dir.display(), e);
+                        if let Some(tx) = cfg.ready {
+                            tx.send(false);
+                        }
+                        return;
+                    }
+                }
+            }
             info!("starting keeper on {}", cfg.addr());
             lab2::serve_keeper(cfg).await;
         }
@@ -8,25 +8,54 @@ use tribbler::{
     storage::{KeyValue, Pattern, Storage},
 };
 
-pub fn app_commands() -> [Command<'static>; 9] {
+pub fn app_commands() -> [Command<'static>; 13] {
     let k = &[Arg::new("key").required(true)];
     let kv = &[
         Arg::new("key").required(true),
         Arg::new("value").required(true),
     ];
+    let cas = &[
+        Arg::new("key").required(true),
+        Arg::new("old").required(true),
+        Arg::new("value").required(true),
+    ];
+    let incr = &[
+        Arg::new("key").required(true),
+        Arg::new("delta")
+            .required(false)
+            .default_value("1")
+            .allow_hyphen_values(true),
+    ];
+    let range = &[
+        Arg::new("key").required(true),
+        Arg::new("start").required(true).allow_hyphen_values(true),
+        Arg::new("end").required(true).allow_hyphen_values(true),
+    ];
     let patt = &[
         Arg::new("prefix").required(false).default_value(""),
         Arg::new("suffix").required(false).default_value(""),
+        Arg::new("glob")
+            .long("glob")
+            .takes_value(true)
+            .default_value(""),
+        Arg::new("regex")
+            .long("regex")
+            .takes_value(true)
+            .default_value(""),
     ];
     let clk = &[Arg::new("clock").required(false).default_value("0")];
     [
         Command::new("get").args(k),
         Command::new("set").args(kv),
+        Command::new("cas").args(cas),
+        Command::new("incr").args(incr),
         Command::new("keys").args(patt),
         Command::new("list-get").args(k),
         Command::new("list-append").args(kv),
         Command::new("list-remove").args(kv),
         Command::new("list-keys").args(patt),
+        Command::new("list-range").args(range),
+        Command::new("list-len").args(k),
         Command::new("clock").args(clk),
         Command::new("exit"),
     ]
@@ -71,6 +100,14 @@ pub async fn match_storage_cmds(client: &dyn Storage, subcmd: Option<(&str, &Arg
             let kv = get_kv(v);
             print_result(client.set(&kv).await);
         }
+        Some(("cas", v)) => {
+            let kv = get_kv(v);
+            print_result(client.cas(&kv, v.value_of("old").unwrap()).await);
+        }
+        Some(("incr", v)) => match v.value_of("delta").unwrap().parse::<i64>() {
+            Ok(delta) => print_result(client.incr(v.value_of("key").unwrap(), delta).await),
+            Err(e) => println!("{:?}", e),
+        },
         Some(("keys", v)) => {
             let pattern = get_pattern(v);
             print_result(client.keys(&pattern).await);
@@ -88,6 +125,19 @@ pub async fn match_storage_cmds(client: &dyn Storage, subcmd: Option<(&str, &Arg
             let pattern = get_pattern(v);
             print_result(client.list_keys(&pattern).await);
         }
+        Some(("list-range", v)) => {
+            let start = v.value_of("start").unwrap().parse::<i64>();
+            let end = v.value_of("end").unwrap().parse::<i64>();
+            match (start, end) {
+                (Ok(start), Ok(end)) => print_result(
+                    client
+                        .list_range(v.value_of("key").unwrap(), start, end)
+                        .await,
+                ),
+                (Err(e), _) | (_, Err(e)) => println!("{:?}", e),
+            }
+        }
+        Some(("list-len", v)) => print_result(client.list_len(v.value_of("key").unwrap()).await),
         Some(("clock", v)) => match v.value_of("clock").unwrap().parse::<u64>() {
             Ok(clk) => print_result(client.clock(clk).await),
             Err(e) => println!("{:?}", e),
@@ -109,6 +159,8 @@ fn get_pattern(matches: &ArgMatches) -> Pattern {
     Pattern {
         prefix: matches.value_of("prefix").unwrap().to_string(),
         suffix: matches.value_of("suffix").unwrap().to_string(),
+        glob: matches.value_of("glob").unwrap().to_string(),
+        regex: matches.value_of("regex").unwrap().to_string(),
     }
 }
 
@@ -1,9 +1,13 @@
 use clap::{Command, Parser};
 use cmd::client_cmds::{app_commands, match_storage_cmds, repl};
-use lab::lab1::new_client;
-use tribbler::err::TribResult;
+use lab::lab1::new_client_with;
 #[allow(unused_imports)]
 use tribbler::storage::{KeyList, KeyString, KeyValue, Pattern};
+use tribbler::{
+    auth::TOKEN_ENV,
+    config::{ClientConfig, Compression},
+    err::TribResult,
+};
 
 #[derive(Parser, Debug)]
 #[clap(name = "kv-client")]
@@ -13,12 +17,29 @@ struct Options {
 
     #[clap(short, long)]
     log: bool,
+
+    /// token to present to the server. Defaults to the TRIB_TOKEN
+    /// environment variable
+    #[clap(short, long)]
+    token: Option<String>,
+
+    /// whether to compress calls with gzip
+    #[clap(long)]
+    gzip: bool,
 }
 
 #[tokio::main]
 async fn main() -> TribResult<()> {
     let options = Options::parse();
-    let client = new_client(&format!("http://{}", &options.address)).await?;
+    let config = ClientConfig {
+        token: options.token.or_else(|| std::env::var(TOKEN_ENV).ok()),
+        compression: match options.gzip {
+            true => Compression::Gzip,
+            false => Compression::None,
+        },
+        ..Default::default()
+    };
+    let client = new_client_with(&format!("http://{}", &options.address), &config).await?;
     let app = Command::new("kv-client").subcommands(app_commands());
 
     loop {
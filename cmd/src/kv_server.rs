@@ -3,7 +3,12 @@
 use clap::Parser;
 use lab::lab1::serve_back;
 use log::{info, LevelFilter};
-use tribbler::{config::BackConfig, err::TribResult};
+use tribbler::{
+    config::{BackConfig, Compression},
+    disk::DiskStorage,
+    err::TribResult,
+    storage::{MemStorage, Storage},
+};
 
 #[derive(Parser, Debug)]
 #[clap(name = "kv-server")]
@@ -13,6 +18,10 @@ struct Options {
 
     #[clap(short, long, default_value = "INFO")]
     log_level: LevelFilter,
+
+    /// directory to persist data in. If unset, data is only kept in memory.
+    #[clap(short, long)]
+    data_dir: Option<String>,
 }
 
 #[tokio::main]
@@ -22,13 +31,19 @@ async fn main() -> TribResult<()> {
         .default_format()
         .filter_level(options.log_level)
         .init();
-    let memstorage = Box::new(tribbler::storage::MemStorage::new());
+    let storage: Box<dyn Storage> = match &options.data_dir {
+        Some(dir) => Box::new(DiskStorage::open(dir).await?),
+        None => Box::new(MemStorage::new()),
+    };
     let addr = options.address.clone();
     let config = BackConfig {
         addr: options.address,
-        storage: memstorage,
+        storage,
         ready: None,
         shutdown: None,
+        tls: None,
+        auth: None,
+        compression: Compression::None,
     };
     let x = serve_back(config);
     info!("============================================");
@@ -1,10 +1,16 @@
 use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
 
 use actix_files::Files;
-use actix_web::{web, App, HttpServer};
+use actix_web::{dev::Service, web, App, HttpServer};
 use clap::Parser;
 use lab::lab2;
 use log::{info, warn, LevelFilter};
+use tribbler::call::CallOptions;
 use tribbler::config::Config;
 use tribbler::config::DEFAULT_CONFIG_LOCATION;
 use tribbler::err::{TribResult, TribblerError};
@@ -56,6 +62,10 @@ struct Cfg {
     /// the host port to bind
     #[clap(long, default_value = "8080")]
     port: u16,
+
+    /// milliseconds a request may spend on storage calls before it fails
+    #[clap(long)]
+    deadline_ms: Option<u64>,
 }
 
 #[tokio::main]
@@ -70,7 +80,13 @@ async fn main() -> TribResult<()> {
         ServerType::Ref => Box::new(RefServer::new()),
         ServerType::Lab => {
             let cfg = Config::read(Some(&args.config))?;
-            let bc = lab2::new_bin_client(cfg.backs).await?;
+            let bc = match cfg.quorum {
+                Some(quorum) => {
+                    lab2::new_quorum_bin_client(cfg.backs.clone(), &cfg.client_config(), quorum)
+                        .await?
+                }
+                None => lab2::new_bin_client_with(cfg.backs.clone(), &cfg.client_config()).await?,
+            };
             lab2::new_front(bc).await?
         }
     };
@@ -79,9 +95,21 @@ async fn main() -> TribResult<()> {
         Ok(_) => info!("Pre-populated test-server successfully"),
         Err(e) => warn!("Failed to pre-populate test server: {}", e),
     }
+    let deadline = args.deadline_ms.map(Duration::from_millis);
+    let requests = Arc::new(AtomicU64::new(0));
     let srv = HttpServer::new(move || {
+        let requests = requests.clone();
         App::new()
             .app_data(server.clone())
+            // every storage call made for a request shares its deadline and id
+            .wrap_fn(move |req, srv| {
+                let id = requests.fetch_add(1, Ordering::Relaxed);
+                let mut options = CallOptions::new().request_id(format!("front-{}", id));
+                if let Some(deadline) = deadline {
+                    options = options.timeout(deadline);
+                }
+                options.scope(srv.call(req))
+            })
             .service(
                 web::scope("/api")
                     .service(api::add_user)
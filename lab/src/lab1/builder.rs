@@ -0,0 +1,176 @@
+//! [ClientBuilder], which makes [StorageClient]s with deadlines and retries
+//! that share their connections
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tonic::transport::{Channel, Endpoint};
+use tribbler::{
+    config::ClientConfig,
+    err::{TribResult, TribblerError},
+};
+
+use crate::lab1::client::StorageClient;
+
+/// How a [StorageClient] retries calls which failed because the back-end
+/// could not be reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// number of times a call is retried before giving up
+    pub retries: u32,
+    /// time to wait before the first retry. The wait doubles on every retry
+    /// after that.
+    pub backoff: Duration,
+    /// the longest time to wait between two retries
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// a policy which never retries
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Returns how long to wait before retry number `attempt`, counting from
+    /// 0.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// retries 5 times, over about 3 seconds, which is enough to ride out a
+    /// back-end restart
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 5,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A set of connections to back-ends, one per address, shared by every
+/// client made from it. Cloning a pool yields a handle to the same set.
+///
+/// Connections are made when first used, and made again whenever they
+/// break, e.g. because their back-end restarted.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelPool {
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
+}
+
+impl ChannelPool {
+    pub fn new() -> ChannelPool {
+        ChannelPool::default()
+    }
+
+    /// Returns the connection to `addr`, making it with `config` if there is
+    /// none yet.
+    fn channel(&self, addr: &str, config: &ClientConfig) -> TribResult<Channel> {
+        let mut channels = self.channels.lock().map_err(|e| e.to_string())?;
+        if let Some(channel) = channels.get(addr) {
+            return Ok(channel.clone());
+        }
+        let mut endpoint = Endpoint::from_shared(addr.to_string())?;
+        if let Some(tls) = &config.tls {
+            endpoint = endpoint.tls_config(tls.client_config()?)?;
+        }
+        let channel = endpoint.connect_lazy();
+        channels.insert(addr.to_string(), channel.clone());
+        Ok(channel)
+    }
+
+    /// number of back-ends with a connection in the pool
+    pub fn len(&self) -> usize {
+        self.channels.lock().map(|c| c.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Makes [StorageClient]s, which are returned by [crate::lab1::new_client]
+/// too, with settings beyond the address of the back-end:
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use lab::lab1::builder::{ClientBuilder, RetryPolicy};
+/// # fn main() -> tribbler::err::TribResult<()> {
+/// let builder = ClientBuilder::new()
+///     .deadline(Duration::from_secs(2))
+///     .retry(RetryPolicy {
+///         retries: 3,
+///         ..Default::default()
+///     });
+/// let a = builder.build("http://127.0.0.1:3000")?;
+/// // shares the connection of `a`
+/// let b = builder.build("http://127.0.0.1:3000")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// All clients made by a builder, or by its clones, share one connection per
+/// back-end.
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    config: ClientConfig,
+    deadline: Option<Duration>,
+    retry: RetryPolicy,
+    pool: ChannelPool,
+}
+
+impl ClientBuilder {
+    /// a builder with no deadline, the default [RetryPolicy] and a pool of
+    /// its own
+    pub fn new() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Sets the TLS, token and compression settings to connect with.
+    pub fn config(mut self, config: ClientConfig) -> ClientBuilder {
+        self.config = config;
+        self
+    }
+
+    /// Sets the time after which a call fails, retries included.
+    pub fn deadline(mut self, deadline: Duration) -> ClientBuilder {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets how failed calls are retried.
+    pub fn retry(mut self, retry: RetryPolicy) -> ClientBuilder {
+        self.retry = retry;
+        self
+    }
+
+    /// Makes clients share connections with the clients of every other
+    /// builder using `pool`.
+    pub fn pool(mut self, pool: ChannelPool) -> ClientBuilder {
+        self.pool = pool;
+        self
+    }
+
+    /// Makes a client for the back-end at `addr` (e.g.
+    /// `http://127.0.0.1:3000`).
+    pub fn build(&self, addr: &str) -> TribResult<StorageClient> {
+        if self.deadline == Some(Duration::ZERO) {
+            return Err(Box::new(TribblerError::Unknown(
+                "deadline must be longer than zero".to_string(),
+            )));
+        }
+        let channel = self.pool.channel(addr, &self.config)?;
+        StorageClient::new(channel, &self.config, self.deadline, self.retry)
+    }
+}
@@ -0,0 +1,386 @@
+//! the RPC client returned by [crate::lab1::new_client]
+use std::{error::Error, future::Future, io, time::Duration};
+
+use async_trait::async_trait;
+use tokio::time::{self, Instant};
+use tokio_stream::StreamExt;
+use tonic::{
+    codegen::InterceptedService, metadata::MetadataValue, transport::Channel, Request, Response,
+    Status,
+};
+use tribbler::{
+    auth::TokenInterceptor,
+    call::{CallOptions, REQUEST_ID_METADATA},
+    config::{ClientConfig, Compression},
+    digest::{HashRange, RangeDigest},
+    err::{ErrorCode, TribResult, TribblerError},
+    rpc::{self, trib_storage_client::TribStorageClient},
+    storage::{
+        ChangeStream, KeyList, KeyString, KeyValue, List, Op, OpResult, Pattern, Scan, Storage,
+    },
+};
+
+use crate::lab1::{
+    builder::RetryPolicy,
+    convert::{
+        change_from_rpc, digest_from_rpc, kv_to_rpc, op_to_rpc, pattern_to_rpc, result_from_rpc,
+        value_from_rpc,
+    },
+};
+
+type Client = TribStorageClient<InterceptedService<Channel, TokenInterceptor>>;
+
+/// A [Storage] which relays every call to a back-end started with
+/// [crate::lab1::serve_back]. Clients are made by
+/// [crate::lab1::builder::ClientBuilder].
+///
+/// The connection is made on the first call and shared by every call after
+/// that. If the back-end goes away, the client reconnects on its own, and
+/// calls are retried with backoff until it is back, as set by the
+/// [RetryPolicy]. Calls which are not safe to repeat, such as
+/// [KeyList::list_append], are only retried if they could not be sent at
+/// all.
+///
+/// Calls honour the [CallOptions] of the scope they are made in: the earlier
+/// of its deadline and the client's own is sent along as `grpc-timeout`, so
+/// the back-end refuses the call if it arrives too late, and so is the
+/// request id.
+#[derive(Debug, Clone)]
+pub struct StorageClient {
+    client: Client,
+    deadline: Option<Duration>,
+    retry: RetryPolicy,
+}
+
+/// returns whether a call failed before it reached the back-end, because no
+/// connection could be made
+fn unsent(status: &Status) -> bool {
+    let mut source = status.source();
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return e.kind() == io::ErrorKind::ConnectionRefused;
+        }
+        source = e.source();
+    }
+    false
+}
+
+impl StorageClient {
+    pub(crate) fn new(
+        channel: Channel,
+        config: &ClientConfig,
+        deadline: Option<Duration>,
+        retry: RetryPolicy,
+    ) -> TribResult<StorageClient> {
+        let interceptor = TokenInterceptor::new(config.token.as_deref())?;
+        let mut client = TribStorageClient::with_interceptor(channel, interceptor);
+        if config.compression == Compression::Gzip {
+            client = client.send_gzip().accept_gzip();
+        }
+        Ok(StorageClient {
+            client,
+            deadline,
+            retry,
+        })
+    }
+
+    /// Makes a call with `f`, retrying it according to the [RetryPolicy]
+    /// and within the deadline. `idempotent` tells whether the call may be
+    /// repeated after it could have reached the back-end.
+    async fn call<T, R, F, Fut>(&self, message: T, idempotent: bool, f: F) -> TribResult<R>
+    where
+        T: Clone,
+        F: Fn(Client, Request<T>) -> Fut,
+        Fut: Future<Output = Result<Response<R>, Status>>,
+    {
+        let options = CallOptions::current();
+        let deadline = match (self.deadline, options.deadline) {
+            (Some(d), Some(at)) => Some(at.min(std::time::Instant::now() + d)),
+            (Some(d), None) => Some(std::time::Instant::now() + d),
+            (None, at) => at,
+        }
+        .map(Instant::from_std);
+        let request_id = match &options.request_id {
+            Some(id) => Some(MetadataValue::from_str(id).map_err(|_| {
+                TribblerError::Unknown(format!("request id {:?} is not valid metadata", id))
+            })?),
+            None => None,
+        };
+        let mut attempt = 0;
+        loop {
+            let mut request = Request::new(message.clone());
+            if let Some(id) = &request_id {
+                request
+                    .metadata_mut()
+                    .insert(REQUEST_ID_METADATA, id.clone());
+            }
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if deadline <= now {
+                    return Err(Box::new(TribblerError::Timeout(
+                        "deadline passed before the call was made".to_string(),
+                    )));
+                }
+                request.set_timeout(deadline - now);
+            }
+            let status = match f(self.client.clone(), request).await {
+                Ok(response) => return Ok(response.into_inner()),
+                Err(status) => status,
+            };
+            // the channel itself cancels calls once their timeout runs out
+            if deadline.is_some_and(|d| d <= Instant::now()) {
+                return Err(Box::new(TribblerError::Timeout(
+                    status.message().to_string(),
+                )));
+            }
+            let retry = attempt < self.retry.retries
+                && (unsent(&status)
+                    || (idempotent && ErrorCode::from(&status) == ErrorCode::Unavailable));
+            let wake = Instant::now() + self.retry.backoff(attempt);
+            if !retry || deadline.is_some_and(|d| wake >= d) {
+                return Err(Box::new(TribblerError::from(status)));
+            }
+            time::sleep_until(wake).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn scan_from_rpc(r: rpc::ScanResponse) -> Scan {
+    Scan {
+        keys: List(r.keys),
+        cursor: r.cursor,
+    }
+}
+
+#[async_trait]
+impl KeyString for StorageClient {
+    async fn get(&self, key: &str) -> TribResult<Option<String>> {
+        let key = rpc::Key {
+            key: key.to_string(),
+        };
+        let r = self
+            .call(key, true, |mut c, r| async move { c.get(r).await })
+            .await?;
+        Ok(value_from_rpc(r.value))
+    }
+
+    async fn set(&self, kv: &KeyValue) -> TribResult<bool> {
+        let r = self
+            .call(
+                kv_to_rpc(kv),
+                true,
+                |mut c, r| async move { c.set(r).await },
+            )
+            .await?;
+        Ok(r.value)
+    }
+
+    async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        let kv = rpc::TtlKeyValue {
+            key: kv.key.clone(),
+            value: kv.value.clone(),
+            ttl_ms: ttl.as_millis() as u64,
+        };
+        let r = self
+            .call(kv, true, |mut c, r| async move { c.set_ttl(r).await })
+            .await?;
+        Ok(r.value)
+    }
+
+    async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)> {
+        let cas = rpc::CasRequest {
+            key: kv.key.clone(),
+            old: old.to_string(),
+            value: kv.value.clone(),
+        };
+        let r = self
+            .call(cas, false, |mut c, r| async move { c.cas(r).await })
+            .await?;
+        Ok((r.swapped, value_from_rpc(r.current)))
+    }
+
+    async fn incr(&self, key: &str, delta: i64) -> TribResult<i64> {
+        let incr = rpc::IncrRequest {
+            key: key.to_string(),
+            delta,
+        };
+        let r = self
+            .call(incr, false, |mut c, r| async move { c.incr(r).await })
+            .await?;
+        Ok(r.value)
+    }
+
+    async fn keys(&self, p: &Pattern) -> TribResult<List> {
+        let r = self
+            .call(pattern_to_rpc(p), true, |mut c, r| async move {
+                c.keys(r).await
+            })
+            .await?;
+        Ok(List(r.list))
+    }
+
+    async fn scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        let scan = rpc::ScanRequest {
+            pattern: Some(pattern_to_rpc(p)),
+            cursor: cursor.to_string(),
+            limit,
+        };
+        let r = self
+            .call(scan, true, |mut c, r| async move { c.scan(r).await })
+            .await?;
+        Ok(scan_from_rpc(r))
+    }
+}
+
+#[async_trait]
+impl KeyList for StorageClient {
+    async fn list_get(&self, key: &str) -> TribResult<List> {
+        let key = rpc::Key {
+            key: key.to_string(),
+        };
+        let r = self
+            .call(key, true, |mut c, r| async move { c.list_get(r).await })
+            .await?;
+        Ok(List(r.list))
+    }
+
+    async fn list_append(&self, kv: &KeyValue) -> TribResult<bool> {
+        let r = self
+            .call(kv_to_rpc(kv), false, |mut c, r| async move {
+                c.list_append(r).await
+            })
+            .await?;
+        Ok(r.value)
+    }
+
+    async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        let kv = rpc::TtlKeyValue {
+            key: kv.key.clone(),
+            value: kv.value.clone(),
+            ttl_ms: ttl.as_millis() as u64,
+        };
+        let r = self
+            .call(
+                kv,
+                false,
+                |mut c, r| async move { c.list_append_ttl(r).await },
+            )
+            .await?;
+        Ok(r.value)
+    }
+
+    async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32> {
+        let r = self
+            .call(kv_to_rpc(kv), false, |mut c, r| async move {
+                c.list_remove(r).await
+            })
+            .await?;
+        Ok(r.removed)
+    }
+
+    async fn list_keys(&self, p: &Pattern) -> TribResult<List> {
+        let r = self
+            .call(pattern_to_rpc(p), true, |mut c, r| async move {
+                c.list_keys(r).await
+            })
+            .await?;
+        Ok(List(r.list))
+    }
+
+    async fn list_scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        let scan = rpc::ScanRequest {
+            pattern: Some(pattern_to_rpc(p)),
+            cursor: cursor.to_string(),
+            limit,
+        };
+        let r = self
+            .call(scan, true, |mut c, r| async move { c.list_scan(r).await })
+            .await?;
+        Ok(scan_from_rpc(r))
+    }
+
+    async fn list_range(&self, key: &str, start: i64, end: i64) -> TribResult<List> {
+        let range = rpc::ListRangeRequest {
+            key: key.to_string(),
+            start,
+            end,
+        };
+        let r = self
+            .call(range, true, |mut c, r| async move { c.list_range(r).await })
+            .await?;
+        Ok(List(r.list))
+    }
+
+    async fn list_len(&self, key: &str) -> TribResult<u64> {
+        let key = rpc::Key {
+            key: key.to_string(),
+        };
+        let r = self
+            .call(key, true, |mut c, r| async move { c.list_len(r).await })
+            .await?;
+        Ok(r.len)
+    }
+}
+
+#[async_trait]
+impl Storage for StorageClient {
+    async fn clock(&self, at_least: u64) -> TribResult<u64> {
+        let clock = rpc::Clock {
+            timestamp: at_least,
+        };
+        let r = self
+            .call(clock, true, |mut c, r| async move { c.clock(r).await })
+            .await?;
+        Ok(r.timestamp)
+    }
+
+    async fn batch(&self, ops: &[Op]) -> TribResult<Vec<OpResult>> {
+        let batch = rpc::BatchRequest {
+            ops: ops.iter().map(op_to_rpc).collect(),
+        };
+        let idempotent = !ops.iter().any(Op::is_write);
+        let r = self
+            .call(
+                batch,
+                idempotent,
+                |mut c, r| async move { c.batch(r).await },
+            )
+            .await?;
+        r.results.into_iter().map(result_from_rpc).collect()
+    }
+
+    async fn digest(
+        &self,
+        range: HashRange,
+        parts: u32,
+        max_keys: u32,
+    ) -> TribResult<Vec<RangeDigest>> {
+        let digest = rpc::DigestRequest {
+            first: range.first,
+            last: range.last,
+            parts,
+            max_keys,
+        };
+        let r = self
+            .call(digest, true, |mut c, r| async move { c.digest(r).await })
+            .await?;
+        Ok(r.ranges.into_iter().map(digest_from_rpc).collect())
+    }
+
+    async fn watch(&self, p: &Pattern, resume: u64) -> TribResult<ChangeStream> {
+        let watch = rpc::WatchRequest {
+            pattern: Some(pattern_to_rpc(p)),
+            resume,
+        };
+        // only establishing the stream is subject to the deadline and
+        // retries, the stream itself lasts until either side drops it
+        let changes = self
+            .call(watch, true, |mut c, r| async move { c.watch(r).await })
+            .await?;
+        Ok(Box::pin(changes.map(|c| match c {
+            Ok(c) => change_from_rpc(c),
+            Err(status) => Err(status.into()),
+        })))
+    }
+}
@@ -0,0 +1,184 @@
+//! conversions between the types of [tribbler::storage] and the messages of
+//! [tribbler::rpc] they travel in
+use tribbler::{
+    digest::{HashRange, KeyDigest, RangeDigest},
+    err::{ErrorCode, TribResult, TribblerError},
+    rpc,
+    storage::{Change, ChangeKind, KeyValue, List, Op, OpResult, Pattern},
+};
+
+pub(crate) fn kv_to_rpc(kv: &KeyValue) -> rpc::KeyValue {
+    rpc::KeyValue {
+        key: kv.key.clone(),
+        value: kv.value.clone(),
+    }
+}
+
+pub(crate) fn kv_from_rpc(kv: rpc::KeyValue) -> KeyValue {
+    KeyValue {
+        key: kv.key,
+        value: kv.value,
+    }
+}
+
+pub(crate) fn pattern_to_rpc(p: &Pattern) -> rpc::Pattern {
+    rpc::Pattern {
+        prefix: p.prefix.clone(),
+        suffix: p.suffix.clone(),
+        glob: p.glob.clone(),
+        regex: p.regex.clone(),
+    }
+}
+
+/// a missing pattern matches everything, like [Pattern::default]
+pub(crate) fn pattern_from_rpc(p: Option<rpc::Pattern>) -> Pattern {
+    match p {
+        Some(p) => Pattern {
+            prefix: p.prefix,
+            suffix: p.suffix,
+            glob: p.glob,
+            regex: p.regex,
+        },
+        None => Pattern::default(),
+    }
+}
+
+/// the empty string stands for an unset value on the wire
+pub(crate) fn value_from_rpc(value: String) -> Option<String> {
+    match value.is_empty() {
+        true => None,
+        false => Some(value),
+    }
+}
+
+pub(crate) fn op_to_rpc(op: &Op) -> rpc::Op {
+    let key = |key: &String| rpc::Key { key: key.clone() };
+    rpc::Op {
+        op: Some(match op {
+            Op::Get(k) => rpc::op::Op::Get(key(k)),
+            Op::Set(kv) => rpc::op::Op::Set(kv_to_rpc(kv)),
+            Op::Keys(p) => rpc::op::Op::Keys(pattern_to_rpc(p)),
+            Op::ListGet(k) => rpc::op::Op::ListGet(key(k)),
+            Op::ListAppend(kv) => rpc::op::Op::ListAppend(kv_to_rpc(kv)),
+            Op::ListRemove(kv) => rpc::op::Op::ListRemove(kv_to_rpc(kv)),
+            Op::ListKeys(p) => rpc::op::Op::ListKeys(pattern_to_rpc(p)),
+        }),
+    }
+}
+
+pub(crate) fn op_from_rpc(op: rpc::Op) -> TribResult<Op> {
+    Ok(match op.op {
+        Some(rpc::op::Op::Get(k)) => Op::Get(k.key),
+        Some(rpc::op::Op::Set(kv)) => Op::Set(kv_from_rpc(kv)),
+        Some(rpc::op::Op::Keys(p)) => Op::Keys(pattern_from_rpc(Some(p))),
+        Some(rpc::op::Op::ListGet(k)) => Op::ListGet(k.key),
+        Some(rpc::op::Op::ListAppend(kv)) => Op::ListAppend(kv_from_rpc(kv)),
+        Some(rpc::op::Op::ListRemove(kv)) => Op::ListRemove(kv_from_rpc(kv)),
+        Some(rpc::op::Op::ListKeys(p)) => Op::ListKeys(pattern_from_rpc(Some(p))),
+        None => {
+            return Err(Box::new(TribblerError::Status(
+                ErrorCode::Invalid,
+                "batch operation is missing".to_string(),
+            )))
+        }
+    })
+}
+
+pub(crate) fn result_to_rpc(r: OpResult) -> rpc::OpResult {
+    rpc::OpResult {
+        result: Some(match r {
+            OpResult::Value(v) => rpc::op_result::Result::Value(rpc::Value {
+                value: v.unwrap_or_default(),
+            }),
+            OpResult::Bool(value) => rpc::op_result::Result::Bool(rpc::Bool { value }),
+            OpResult::List(l) => rpc::op_result::Result::List(rpc::StringList { list: l.0 }),
+            OpResult::Removed(removed) => {
+                rpc::op_result::Result::Removed(rpc::ListRemoveResponse { removed })
+            }
+        }),
+    }
+}
+
+pub(crate) fn result_from_rpc(r: rpc::OpResult) -> TribResult<OpResult> {
+    Ok(match r.result {
+        Some(rpc::op_result::Result::Value(v)) => OpResult::Value(value_from_rpc(v.value)),
+        Some(rpc::op_result::Result::Bool(b)) => OpResult::Bool(b.value),
+        Some(rpc::op_result::Result::List(l)) => OpResult::List(List(l.list)),
+        Some(rpc::op_result::Result::Removed(r)) => OpResult::Removed(r.removed),
+        None => {
+            return Err(Box::new(TribblerError::Status(
+                ErrorCode::Invalid,
+                "batch result is missing".to_string(),
+            )))
+        }
+    })
+}
+
+pub(crate) fn change_to_rpc(c: Change) -> rpc::Change {
+    let kind = match c.kind {
+        ChangeKind::Set => rpc::ChangeKind::Set,
+        ChangeKind::ListAppend => rpc::ChangeKind::ListAppend,
+        ChangeKind::ListRemove => rpc::ChangeKind::ListRemove,
+    };
+    rpc::Change {
+        seq: c.seq,
+        kind: kind as i32,
+        key: c.key,
+        value: c.value,
+    }
+}
+
+pub(crate) fn change_from_rpc(c: rpc::Change) -> TribResult<Change> {
+    let kind = match rpc::ChangeKind::from_i32(c.kind) {
+        Some(rpc::ChangeKind::Set) => ChangeKind::Set,
+        Some(rpc::ChangeKind::ListAppend) => ChangeKind::ListAppend,
+        Some(rpc::ChangeKind::ListRemove) => ChangeKind::ListRemove,
+        None => {
+            return Err(Box::new(TribblerError::Status(
+                ErrorCode::Invalid,
+                format!("unknown change kind {}", c.kind),
+            )))
+        }
+    };
+    Ok(Change {
+        seq: c.seq,
+        kind,
+        key: c.key,
+        value: c.value,
+    })
+}
+
+pub(crate) fn digest_to_rpc(d: RangeDigest) -> rpc::RangeDigest {
+    rpc::RangeDigest {
+        first: d.range.first,
+        last: d.range.last,
+        hash: d.hash,
+        count: d.count,
+        keys: d
+            .keys
+            .into_iter()
+            .map(|k| rpc::KeyDigest {
+                key: k.key,
+                list: k.list,
+                hash: k.hash,
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn digest_from_rpc(d: rpc::RangeDigest) -> RangeDigest {
+    RangeDigest {
+        range: HashRange::new(d.first, d.last),
+        hash: d.hash,
+        count: d.count,
+        keys: d
+            .keys
+            .into_iter()
+            .map(|k| KeyDigest {
+                key: k.key,
+                list: k.list,
+                hash: k.hash,
+            })
+            .collect(),
+    }
+}
@@ -1,14 +1,83 @@
-use tribbler::{config::BackConfig, err::TribResult, storage::Storage};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{codegen::InterceptedService, transport::Server};
+use tribbler::{
+    auth::AuthInterceptor,
+    config::{BackConfig, ClientConfig, Compression},
+    err::TribResult,
+    rpc::trib_storage_server::TribStorageServer,
+    storage::Storage,
+};
+
+use crate::lab1::{builder::ClientBuilder, server::StorageServer};
 
 /// an async function which blocks indefinitely until interrupted serving on
 /// the host and port specified in the [BackConfig] parameter.
 pub async fn serve_back(config: BackConfig) -> TribResult<()> {
-    todo!();
+    let BackConfig {
+        addr,
+        storage,
+        ready,
+        shutdown,
+        tls,
+        auth,
+        compression,
+    } = config;
+    let started = async {
+        let mut server = Server::builder();
+        if let Some(tls) = &tls {
+            server = server.tls_config(tls.server_config()?)?;
+        }
+        let listener = TcpListener::bind(&addr).await?;
+        TribResult::Ok((server, listener))
+    };
+    let (mut server, listener) = match started.await {
+        Ok(s) => s,
+        Err(e) => {
+            if let Some(tx) = ready {
+                let _ = tx.send(false);
+            }
+            return Err(e);
+        }
+    };
+    if let Some(tx) = ready {
+        let _ = tx.send(true);
+    }
+
+    let mut service = TribStorageServer::new(StorageServer::new(storage)).accept_gzip();
+    if compression == Compression::Gzip {
+        service = service.send_gzip();
+    }
+    let router = server.add_service(InterceptedService::new(service, AuthInterceptor::new(auth)));
+    let incoming = TcpListenerStream::new(listener);
+    match shutdown {
+        Some(mut rx) => {
+            router
+                .serve_with_incoming_shutdown(incoming, async move {
+                    rx.recv().await;
+                })
+                .await?
+        }
+        None => router.serve_with_incoming(incoming).await?,
+    }
+    Ok(())
 }
 
 /// This function should create a new client which implements the [Storage]
 /// trait. It should communicate with the backend that is started in the
 /// [serve_back] function.
+///
+/// Use a [ClientBuilder] for deadlines, retry settings and connections shared
+/// between clients.
 pub async fn new_client(addr: &str) -> TribResult<Box<dyn Storage>> {
-    todo!();
+    new_client_with(addr, &ClientConfig::default()).await
+}
+
+/// Same as [new_client], but connects with the given settings, which should
+/// match the [BackConfig] of the back-end: TLS settings as in
+/// [BackConfig::tls], a token known to [BackConfig::auth], and compression.
+pub async fn new_client_with(addr: &str, config: &ClientConfig) -> TribResult<Box<dyn Storage>> {
+    Ok(Box::new(
+        ClientBuilder::new().config(config.clone()).build(addr)?,
+    ))
 }
@@ -563,6 +563,11 @@
 //!
 //! ## Happy Lab 1!
 //!
+pub mod builder;
+pub mod client;
+mod convert;
 mod lab;
+pub mod server;
 pub use crate::lab1::lab::new_client;
+pub use crate::lab1::lab::new_client_with;
 pub use crate::lab1::lab::serve_back;
@@ -0,0 +1,397 @@
+//! the RPC server which [crate::lab1::serve_back] runs in front of a
+//! back-end's [Storage]
+
+// [Status] is the error type tonic requires of every RPC
+#![allow(clippy::result_large_err)]
+
+use std::{
+    error::Error,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+use tribbler::{
+    auth::require_write,
+    call::{parse_grpc_timeout, CallOptions, REQUEST_ID_METADATA},
+    digest::HashRange,
+    err::{TribResult, TribblerError},
+    rpc::{self, trib_storage_server::TribStorage},
+    storage::{Op, Scan, Storage},
+};
+
+use crate::lab1::convert::{
+    change_to_rpc, digest_to_rpc, kv_from_rpc, op_from_rpc, pattern_from_rpc, result_to_rpc,
+};
+
+/// Serves the [TribStorage] RPC service by relaying every call to the
+/// wrapped [Storage]. Calls which modify storage are refused to read-only
+/// tokens, see [tribbler::auth].
+///
+/// Each call runs with the deadline and request id the client sent as its
+/// [CallOptions]. Calls arriving after the deadline are refused, but a write
+/// which started runs to completion, see [CallOptions::spawn].
+pub struct StorageServer {
+    storage: Arc<dyn Storage>,
+}
+
+impl StorageServer {
+    pub fn new(storage: Box<dyn Storage>) -> StorageServer {
+        StorageServer {
+            storage: Arc::from(storage),
+        }
+    }
+
+    /// runs the write `f` makes on the wrapped storage with `options`, see
+    /// [CallOptions::spawn]
+    async fn spawn<T, F, Fut>(&self, options: CallOptions, f: F) -> TribResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(Arc<dyn Storage>) -> Fut,
+        Fut: Future<Output = TribResult<T>> + Send + 'static,
+    {
+        options.spawn(f(self.storage.clone())).await
+    }
+}
+
+/// reads the [CallOptions] a client sent along with `request`, refusing
+/// calls whose deadline has already passed
+fn call_options<T>(request: &Request<T>) -> Result<CallOptions, Status> {
+    let metadata = request.metadata();
+    let mut options = CallOptions::new();
+    if let Some(timeout) = metadata.get("grpc-timeout") {
+        let timeout = timeout
+            .to_str()
+            .ok()
+            .and_then(parse_grpc_timeout)
+            .ok_or_else(|| Status::invalid_argument("malformed grpc-timeout"))?;
+        if timeout.is_zero() {
+            return Err(Status::deadline_exceeded("deadline passed before the call"));
+        }
+        options = options.deadline(Instant::now() + timeout);
+    }
+    if let Some(id) = metadata.get(REQUEST_ID_METADATA) {
+        let id = id
+            .to_str()
+            .map_err(|_| Status::invalid_argument("malformed request id"))?;
+        options = options.request_id(id);
+    }
+    Ok(options)
+}
+
+/// turns an error of a storage call into the status of an RPC, keeping its
+/// [tribbler::err::ErrorCode]
+fn status(e: Box<dyn Error + Send + Sync>) -> Status {
+    match e.downcast::<TribblerError>() {
+        Ok(e) => (*e).into(),
+        Err(e) => Status::internal(e.to_string()),
+    }
+}
+
+/// turns the result of a storage call into the result of an RPC
+fn reply<T, R>(r: TribResult<T>, f: impl FnOnce(T) -> R) -> Result<Response<R>, Status> {
+    r.map(|v| Response::new(f(v))).map_err(status)
+}
+
+fn scan_reply(s: Scan) -> rpc::ScanResponse {
+    rpc::ScanResponse {
+        keys: s.keys.0,
+        cursor: s.cursor,
+    }
+}
+
+#[async_trait]
+impl TribStorage for StorageServer {
+    async fn get(&self, request: Request<rpc::Key>) -> Result<Response<rpc::Value>, Status> {
+        let options = call_options(&request)?;
+        let key = request.into_inner().key;
+        reply(options.run(self.storage.get(&key)).await, |v| rpc::Value {
+            value: v.unwrap_or_default(),
+        })
+    }
+
+    async fn set(&self, request: Request<rpc::KeyValue>) -> Result<Response<rpc::Bool>, Status> {
+        require_write(&request)?;
+        let options = call_options(&request)?;
+        let kv = kv_from_rpc(request.into_inner());
+        reply(
+            self.spawn(options, move |s| async move { s.set(&kv).await })
+                .await,
+            |value| rpc::Bool { value },
+        )
+    }
+
+    async fn set_ttl(
+        &self,
+        request: Request<rpc::TtlKeyValue>,
+    ) -> Result<Response<rpc::Bool>, Status> {
+        require_write(&request)?;
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        let kv = kv_from_rpc(rpc::KeyValue {
+            key: r.key,
+            value: r.value,
+        });
+        let ttl = Duration::from_millis(r.ttl_ms);
+        reply(
+            self.spawn(options, move |s| async move { s.set_ttl(&kv, ttl).await })
+                .await,
+            |value| rpc::Bool { value },
+        )
+    }
+
+    async fn cas(
+        &self,
+        request: Request<rpc::CasRequest>,
+    ) -> Result<Response<rpc::CasResponse>, Status> {
+        require_write(&request)?;
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        let kv = kv_from_rpc(rpc::KeyValue {
+            key: r.key,
+            value: r.value,
+        });
+        reply(
+            self.spawn(options, move |s| async move { s.cas(&kv, &r.old).await })
+                .await,
+            |(swapped, current)| rpc::CasResponse {
+                swapped,
+                current: current.unwrap_or_default(),
+            },
+        )
+    }
+
+    async fn incr(
+        &self,
+        request: Request<rpc::IncrRequest>,
+    ) -> Result<Response<rpc::IncrResponse>, Status> {
+        require_write(&request)?;
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        reply(
+            self.spawn(
+                options,
+                move |s| async move { s.incr(&r.key, r.delta).await },
+            )
+            .await,
+            |value| rpc::IncrResponse { value },
+        )
+    }
+
+    async fn keys(
+        &self,
+        request: Request<rpc::Pattern>,
+    ) -> Result<Response<rpc::StringList>, Status> {
+        let options = call_options(&request)?;
+        let p = pattern_from_rpc(Some(request.into_inner()));
+        reply(options.run(self.storage.keys(&p)).await, |l| {
+            rpc::StringList { list: l.0 }
+        })
+    }
+
+    async fn scan(
+        &self,
+        request: Request<rpc::ScanRequest>,
+    ) -> Result<Response<rpc::ScanResponse>, Status> {
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        let p = pattern_from_rpc(r.pattern);
+        reply(
+            options.run(self.storage.scan(&p, &r.cursor, r.limit)).await,
+            scan_reply,
+        )
+    }
+
+    async fn list_get(
+        &self,
+        request: Request<rpc::Key>,
+    ) -> Result<Response<rpc::StringList>, Status> {
+        let options = call_options(&request)?;
+        let key = request.into_inner().key;
+        reply(options.run(self.storage.list_get(&key)).await, |l| {
+            rpc::StringList { list: l.0 }
+        })
+    }
+
+    async fn list_append(
+        &self,
+        request: Request<rpc::KeyValue>,
+    ) -> Result<Response<rpc::Bool>, Status> {
+        require_write(&request)?;
+        let options = call_options(&request)?;
+        let kv = kv_from_rpc(request.into_inner());
+        reply(
+            self.spawn(options, move |s| async move { s.list_append(&kv).await })
+                .await,
+            |value| rpc::Bool { value },
+        )
+    }
+
+    async fn list_append_ttl(
+        &self,
+        request: Request<rpc::TtlKeyValue>,
+    ) -> Result<Response<rpc::Bool>, Status> {
+        require_write(&request)?;
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        let kv = kv_from_rpc(rpc::KeyValue {
+            key: r.key,
+            value: r.value,
+        });
+        let ttl = Duration::from_millis(r.ttl_ms);
+        reply(
+            self.spawn(options, move |s| async move {
+                s.list_append_ttl(&kv, ttl).await
+            })
+            .await,
+            |value| rpc::Bool { value },
+        )
+    }
+
+    async fn list_remove(
+        &self,
+        request: Request<rpc::KeyValue>,
+    ) -> Result<Response<rpc::ListRemoveResponse>, Status> {
+        require_write(&request)?;
+        let options = call_options(&request)?;
+        let kv = kv_from_rpc(request.into_inner());
+        reply(
+            self.spawn(options, move |s| async move { s.list_remove(&kv).await })
+                .await,
+            |removed| rpc::ListRemoveResponse { removed },
+        )
+    }
+
+    async fn list_keys(
+        &self,
+        request: Request<rpc::Pattern>,
+    ) -> Result<Response<rpc::StringList>, Status> {
+        let options = call_options(&request)?;
+        let p = pattern_from_rpc(Some(request.into_inner()));
+        reply(options.run(self.storage.list_keys(&p)).await, |l| {
+            rpc::StringList { list: l.0 }
+        })
+    }
+
+    async fn list_scan(
+        &self,
+        request: Request<rpc::ScanRequest>,
+    ) -> Result<Response<rpc::ScanResponse>, Status> {
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        let p = pattern_from_rpc(r.pattern);
+        reply(
+            options
+                .run(self.storage.list_scan(&p, &r.cursor, r.limit))
+                .await,
+            scan_reply,
+        )
+    }
+
+    async fn list_range(
+        &self,
+        request: Request<rpc::ListRangeRequest>,
+    ) -> Result<Response<rpc::StringList>, Status> {
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        reply(
+            options
+                .run(self.storage.list_range(&r.key, r.start, r.end))
+                .await,
+            |l| rpc::StringList { list: l.0 },
+        )
+    }
+
+    async fn list_len(
+        &self,
+        request: Request<rpc::Key>,
+    ) -> Result<Response<rpc::ListLenResponse>, Status> {
+        let options = call_options(&request)?;
+        let key = request.into_inner().key;
+        reply(options.run(self.storage.list_len(&key)).await, |len| {
+            rpc::ListLenResponse { len }
+        })
+    }
+
+    async fn clock(&self, request: Request<rpc::Clock>) -> Result<Response<rpc::Clock>, Status> {
+        require_write(&request)?;
+        let options = call_options(&request)?;
+        let at_least = request.into_inner().timestamp;
+        reply(
+            self.spawn(options, move |s| async move { s.clock(at_least).await })
+                .await,
+            |timestamp| rpc::Clock { timestamp },
+        )
+    }
+
+    async fn batch(
+        &self,
+        request: Request<rpc::BatchRequest>,
+    ) -> Result<Response<rpc::BatchResponse>, Status> {
+        let writable = require_write(&request);
+        let options = call_options(&request)?;
+        let ops = request
+            .into_inner()
+            .ops
+            .into_iter()
+            .map(op_from_rpc)
+            .collect::<TribResult<Vec<_>>>()
+            .map_err(status)?;
+        if ops.iter().any(Op::is_write) {
+            writable?;
+        }
+        reply(
+            self.spawn(options, move |s| async move { s.batch(&ops).await })
+                .await,
+            |results| rpc::BatchResponse {
+                results: results.into_iter().map(result_to_rpc).collect(),
+            },
+        )
+    }
+
+    async fn digest(
+        &self,
+        request: Request<rpc::DigestRequest>,
+    ) -> Result<Response<rpc::DigestResponse>, Status> {
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        if r.first > r.last {
+            return Err(Status::invalid_argument("digest range is empty"));
+        }
+        let range = HashRange::new(r.first, r.last);
+        reply(
+            options
+                .run(self.storage.digest(range, r.parts, r.max_keys))
+                .await,
+            |ranges| rpc::DigestResponse {
+                ranges: ranges.into_iter().map(digest_to_rpc).collect(),
+            },
+        )
+    }
+
+    type watchStream = Pin<Box<dyn Stream<Item = Result<rpc::Change, Status>> + Send>>;
+
+    async fn watch(
+        &self,
+        request: Request<rpc::WatchRequest>,
+    ) -> Result<Response<Self::watchStream>, Status> {
+        let options = call_options(&request)?;
+        let r = request.into_inner();
+        let p = pattern_from_rpc(r.pattern);
+        // the deadline only bounds establishing the stream
+        reply(
+            options.run(self.storage.watch(&p, r.resume)).await,
+            |changes| {
+                let stream = changes.map(|c| match c {
+                    Ok(c) => Ok(change_to_rpc(c)),
+                    Err(e) => Err(status(e)),
+                });
+                Box::pin(stream) as Self::watchStream
+            },
+        )
+    }
+}
@@ -0,0 +1,229 @@
+//! the anti-entropy which the leading keeper started by
+//! [crate::lab2::serve_keeper] runs over the replicas of a quorum bin
+//! storage
+use std::{collections::BTreeSet, time::Duration};
+
+use futures_util::future::join_all;
+use log::warn;
+use tribbler::{
+    digest::{self, HashRange, RangeDigest},
+    err::TribResult,
+    namespace,
+    ring::{Ring, DEFAULT_VNODES},
+    storage::{KeyList, KeyString, KeyValue, Storage},
+};
+
+use crate::{
+    lab1::{builder::ClientBuilder, client::StorageClient},
+    lab2::quorum::{put, unstamp, HINTS_BIN},
+};
+
+/// how often the leading keeper runs [AntiEntropy::run]
+pub const ANTI_ENTROPY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// number of parts a range whose replicas differ is split into to narrow
+/// the difference down
+pub const FANOUT: u32 = 16;
+
+/// ranges with at most this many entries are compared key by key rather
+/// than split further
+pub const MAX_KEYS: u32 = 64;
+
+/// What a pass of [AntiEntropy::run] did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Repairs {
+    /// number of digests asked for
+    pub digests: usize,
+    /// number of entries found to differ between replicas
+    pub keys: usize,
+    /// number of replicas an entry was copied to
+    pub copies: usize,
+}
+
+/// Keeps the replicas of a quorum bin storage in sync in the background,
+/// for entries which reads, and so read repair, never touch.
+///
+/// Every arc of the [Ring] is held by the same [tribbler::config::QuorumConfig::n]
+/// back-ends. For each arc, their [Storage::digest]s are compared, and the
+/// parts which differ are split again until they hold few enough entries to
+/// compare the hash of each. Only the entries which differ are then read
+/// and copied, the newest value or the union of the list entries winning,
+/// as with read repair by [crate::lab2::quorum::QuorumClient].
+///
+/// The [HINTS_BIN] of a back-end holds the writes it keeps for others, not
+/// replicas of its own, so its position is left out of every comparison.
+pub struct AntiEntropy {
+    ring: Ring,
+    backs: Vec<StorageClient>,
+    n: usize,
+}
+
+impl AntiEntropy {
+    /// Creates an anti-entropy over the back-ends at `backs` (`host:port`),
+    /// each bin being stored on `n` of them, which connects to them as set
+    /// up by `builder`.
+    pub fn new(backs: &[String], builder: &ClientBuilder, n: usize) -> TribResult<AntiEntropy> {
+        let clients = backs
+            .iter()
+            .map(|addr| builder.build(&format!("http://{}", addr)))
+            .collect::<TribResult<Vec<StorageClient>>>()?;
+        Ok(AntiEntropy {
+            ring: Ring::new(backs, DEFAULT_VNODES),
+            backs: clients,
+            n,
+        })
+    }
+
+    /// Compares the replicas of every arc of the ring once, and repairs the
+    /// entries which differ. Back-ends which cannot be reached are left out.
+    pub async fn run(&self) -> TribResult<Repairs> {
+        let mut repairs = Repairs::default();
+        let hints = digest::position(&namespace::prefix(HINTS_BIN));
+        for (range, replicas) in self.ring.arcs(self.n) {
+            if replicas.len() < 2 {
+                continue;
+            }
+            for range in outside(range, hints) {
+                self.sync(range, &replicas, &mut repairs).await?;
+            }
+        }
+        Ok(repairs)
+    }
+
+    /// Brings the entries in `range` in sync on `replicas`.
+    async fn sync(
+        &self,
+        range: HashRange,
+        replicas: &[usize],
+        repairs: &mut Repairs,
+    ) -> TribResult<()> {
+        let mut pending = vec![(range, MAX_KEYS)];
+        while let Some((range, max_keys)) = pending.pop() {
+            let calls = replicas
+                .iter()
+                .map(|&i| async move { (i, self.backs[i].digest(range, FANOUT, max_keys).await) });
+            let mut answers = vec![];
+            for (i, r) in join_all(calls).await {
+                repairs.digests += 1;
+                match r {
+                    Ok(digests) => answers.push((i, digests)),
+                    Err(e) => warn!("no digest from {}: {}", self.ring.nodes()[i], e),
+                }
+            }
+            if answers.len() < 2 {
+                continue;
+            }
+            for part in 0..answers[0].1.len() {
+                let parts = answers
+                    .iter()
+                    .map(|(_, digests)| &digests[part])
+                    .collect::<Vec<&RangeDigest>>();
+                if parts.iter().all(|d| d.hash == parts[0].hash) {
+                    continue;
+                }
+                if parts.iter().any(|d| d.keys.len() as u64 != d.count) {
+                    // too many entries to compare one by one: split further,
+                    // unless the part is a single position, e.g. a large bin
+                    let range = parts[0].range;
+                    let max_keys = match range.first == range.last {
+                        true => u32::MAX,
+                        false => MAX_KEYS,
+                    };
+                    pending.push((range, max_keys));
+                    continue;
+                }
+                let stores = answers
+                    .iter()
+                    .map(|(i, _)| &self.backs[*i])
+                    .collect::<Vec<_>>();
+                for (key, list) in differing(&parts) {
+                    repairs.keys += 1;
+                    let copied = match list {
+                        true => repair_list(&stores, &key).await,
+                        false => repair_value(&stores, &key).await,
+                    };
+                    match copied {
+                        Ok(copies) => repairs.copies += copies,
+                        Err(e) => warn!("repairing {:?} failed: {}", key, e),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the parts of `range` which are left once `position` is taken
+/// out of it.
+fn outside(range: HashRange, position: u64) -> Vec<HashRange> {
+    if !range.contains(position) {
+        return vec![range];
+    }
+    let mut parts = vec![];
+    if position > range.first {
+        parts.push(HashRange::new(range.first, position - 1));
+    }
+    if position < range.last {
+        parts.push(HashRange::new(position + 1, range.last));
+    }
+    parts
+}
+
+/// Returns the entries of `parts`, digests of the same range on different
+/// replicas, which are missing from or differ on some of them.
+fn differing(parts: &[&RangeDigest]) -> BTreeSet<(String, bool)> {
+    let all = parts
+        .iter()
+        .flat_map(|d| d.keys.iter())
+        .collect::<BTreeSet<_>>();
+    all.iter()
+        .filter(|k| !parts.iter().all(|d| d.keys.contains(k)))
+        .map(|k| (k.key.clone(), k.list))
+        .collect()
+}
+
+/// Sets `key` to its newest value on every one of `stores`, and returns the
+/// number of stores which did not have it.
+async fn repair_value(stores: &[&StorageClient], key: &str) -> TribResult<usize> {
+    let values = join_all(stores.iter().map(|s| s.get(key)))
+        .await
+        .into_iter()
+        .collect::<TribResult<Vec<_>>>()?;
+    let newest = match values.iter().flatten().max_by_key(|v| unstamp(v)) {
+        Some(newest) => newest,
+        None => return Ok(0),
+    };
+    let mut copies = 0;
+    for (store, value) in stores.iter().zip(values.iter()) {
+        if value.as_ref() != Some(newest) {
+            put(*store, key, newest).await?;
+            copies += 1;
+        }
+    }
+    Ok(copies)
+}
+
+/// Appends the entries of list `key` found on any of `stores` to those
+/// which miss them, and returns the number of stores which did.
+async fn repair_list(stores: &[&StorageClient], key: &str) -> TribResult<usize> {
+    let lists = join_all(stores.iter().map(|s| s.list_get(key)))
+        .await
+        .into_iter()
+        .collect::<TribResult<Vec<_>>>()?;
+    let all = lists
+        .iter()
+        .flat_map(|l| l.0.iter())
+        .collect::<BTreeSet<_>>();
+    let mut copies = 0;
+    for (store, list) in stores.iter().zip(lists.iter()) {
+        let have = list.0.iter().collect::<BTreeSet<_>>();
+        let missing = all.difference(&have).collect::<Vec<_>>();
+        for entry in missing.iter() {
+            store.list_append(&KeyValue::new(key, entry)).await?;
+        }
+        if !missing.is_empty() {
+            copies += 1;
+        }
+    }
+    Ok(copies)
+}
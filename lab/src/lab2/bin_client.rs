@@ -0,0 +1,46 @@
+//! the [BinStorage] client returned by [crate::lab2::new_bin_client]
+use async_trait::async_trait;
+use tribbler::{
+    err::{TribResult, TribblerError},
+    namespace::Bin,
+    ring::{Ring, DEFAULT_VNODES},
+    storage::{BinStorage, Storage},
+};
+
+use crate::lab1::{builder::ClientBuilder, client::StorageClient};
+
+/// Maps every bin to one of the back-ends on a consistent-hash [Ring], and
+/// keeps the bins apart on a back-end with [Bin].
+pub struct BinClient {
+    ring: Ring,
+    backs: Vec<StorageClient>,
+}
+
+impl BinClient {
+    /// Creates a client for the back-ends at `backs` (`host:port`), which
+    /// connects to them as set up by `builder`. All bins on the same back-end
+    /// share one connection.
+    pub fn new(backs: &[String], builder: &ClientBuilder) -> TribResult<BinClient> {
+        let ring = Ring::new(backs, DEFAULT_VNODES);
+        let backs = backs
+            .iter()
+            .map(|addr| builder.build(&format!("http://{}", addr)))
+            .collect::<TribResult<Vec<StorageClient>>>()?;
+        Ok(BinClient { ring, backs })
+    }
+}
+
+#[async_trait]
+impl BinStorage for BinClient {
+    async fn bin(&self, name: &str) -> TribResult<Box<dyn Storage>> {
+        let back = match self.ring.node(name) {
+            Some(i) => &self.backs[i],
+            None => {
+                return Err(Box::new(TribblerError::Unknown(
+                    "no back-ends to store bins on".to_string(),
+                )))
+            }
+        };
+        Ok(Box::new(Bin::new(name, back.clone())))
+    }
+}
@@ -0,0 +1,48 @@
+//! who leads the keepers started by [crate::lab2::serve_keeper], and how
+//! they reach each other
+//!
+//! The keepers are led by the leader of the Raft log they replicate, see
+//! [crate::lab2::raft]: it is the only one which can add to the log, so it
+//! is also the one which does the work only one keeper should do, with
+//! [RaftNode::while_leader](crate::lab2::raft::RaftNode::while_leader).
+//! Of the keepers alive, the one with the oldest incarnation, i.e. the
+//! smallest [KeeperConfig::id], ends up leading: a leader which hears of an
+//! older keeper, e.g. one back from a partition, steps down for it.
+//! Every leadership has a term, larger than any term before it, so work
+//! done on behalf of a leader can be told apart by term. Any keeper tells
+//! who leads as far as it knows over the
+//! [crate::keeper::keeper_server::Keeper] RPC service.
+#![allow(clippy::result_large_err)]
+
+use tonic::{
+    codegen::InterceptedService,
+    transport::{Channel, Endpoint},
+};
+use tribbler::{auth::TokenInterceptor, config::KeeperConfig, err::TribResult};
+
+/// Who leads the keepers, as far as one of them knows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Leadership {
+    /// the latest term known
+    pub term: u64,
+    /// index of the keeper leading in that term, if known
+    pub leader: Option<usize>,
+}
+
+/// a channel to another keeper
+pub(crate) type KeeperChannel = InterceptedService<Channel, TokenInterceptor>;
+
+/// Returns a channel to the keeper at `addr` (`host:port`), which connects
+/// on first use with the TLS and token settings of `kc`.
+pub(crate) fn connect(kc: &KeeperConfig, addr: &str) -> TribResult<KeeperChannel> {
+    let config = kc.client_config();
+    let mut endpoint = Endpoint::from_shared(format!("http://{}", addr))?;
+    if let Some(tls) = &config.tls {
+        endpoint = endpoint.tls_config(tls.client_config()?)?;
+    }
+    let interceptor = TokenInterceptor::new(config.token.as_deref())?;
+    Ok(InterceptedService::new(
+        endpoint.connect_lazy(),
+        interceptor,
+    ))
+}
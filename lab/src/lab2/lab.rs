@@ -1,11 +1,74 @@
-use tribbler::{config::KeeperConfig, err::TribResult, storage::BinStorage, trib::Server};
+use std::sync::Arc;
+
+use futures_util::future::join_all;
+use log::{info, warn};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+    time,
+};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{codegen::InterceptedService, transport::Server as TransportServer};
+use tribbler::{
+    auth::AuthInterceptor,
+    config::{ClientConfig, KeeperConfig, QuorumConfig},
+    err::TribResult,
+    hlc,
+    storage::{BinStorage, MemStorage, Storage},
+    trib::Server,
+};
+
+use crate::{
+    lab1::{
+        builder::{ClientBuilder, RetryPolicy},
+        client::StorageClient,
+    },
+    lab2::{
+        anti_entropy::{AntiEntropy, ANTI_ENTROPY_INTERVAL},
+        bin_client::BinClient,
+        membership::{FailureDetector, PUBLISH_INTERVAL},
+        quorum::QuorumClient,
+        raft::{
+            Command, Membership, RaftNode, HYBRID_WATERMARK_STEP, WATERMARK_INTERVAL,
+            WATERMARK_STEP,
+        },
+    },
+};
 
 /// This function accepts a list of backend addresses, and returns a
 /// type which should implement the [BinStorage] trait to access the
 /// underlying storage system.
-#[allow(unused_variables)]
 pub async fn new_bin_client(backs: Vec<String>) -> TribResult<Box<dyn BinStorage>> {
-    todo!()
+    new_bin_client_with(backs, &ClientConfig::default()).await
+}
+
+/// Same as [new_bin_client], but connects to the back-ends with the given
+/// settings, usually [Config::client_config](tribbler::config::Config::client_config).
+pub async fn new_bin_client_with(
+    backs: Vec<String>,
+    config: &ClientConfig,
+) -> TribResult<Box<dyn BinStorage>> {
+    let builder = ClientBuilder::new().config(config.clone());
+    Ok(Box::new(BinClient::new(&backs, &builder)?))
+}
+
+/// Same as [new_bin_client_with], but stores every bin on `quorum.n`
+/// back-ends and reads and writes it by quorum, see [QuorumClient]. Must be
+/// called from within a tokio runtime, which hands hinted writes off to
+/// their replicas in the background for as long as the client lives.
+pub async fn new_quorum_bin_client(
+    backs: Vec<String>,
+    config: &ClientConfig,
+    quorum: QuorumConfig,
+) -> TribResult<Box<dyn BinStorage>> {
+    // the other replicas stand in for one which is down, so retrying it
+    // would only hold up the call
+    let builder = ClientBuilder::new()
+        .config(config.clone())
+        .retry(RetryPolicy::none());
+    let client = QuorumClient::new(&backs, &builder, quorum)?;
+    client.spawn_handoff();
+    Ok(Box::new(client))
 }
 
 /// this async function accepts a [KeeperConfig] that should be used to start
@@ -14,9 +77,200 @@ pub async fn new_bin_client(backs: Vec<String>) -> TribResult<Box<dyn BinStorage
 /// This function should block indefinitely and only return upon erroring. Make
 /// sure to send the proper signal to the channel in `kc` when the keeper has
 /// started.
-#[allow(unused_variables)]
-pub async fn serve_keeper(kc: KeeperConfig) -> TribResult<()> {
-    todo!()
+///
+/// The keepers replicate a log of what they know, see [RaftNode], persisted
+/// in [KeeperConfig::state], and the keeper leading the log leads the
+/// keepers, see [crate::lab2::election]. Both are served on the keeper's
+/// address. When [KeeperConfig::quorum] is set, the leader runs
+/// [AntiEntropy] every [ANTI_ENTROPY_INTERVAL], and as soon as it is
+/// elected, to bring the replicas of the bins back in sync.
+///
+/// Every [WATERMARK_INTERVAL], the leader syncs the back-end clocks to no
+/// less than the latest clock it has seen and the last watermark recorded,
+/// so that a restarted back-end does not hand out clocks from before it
+/// went down. Once the latest clock is [WATERMARK_STEP] past the last
+/// watermark, it records it as the new one, so back-end clocks go back by
+/// no more than a step across keeper failovers. With
+/// [KeeperConfig::hybrid_clock], it syncs them to no less than [hlc::now]
+/// either, so that the clock of an idle back-end keeps up with wall-clock
+/// time, and steps are [HYBRID_WATERMARK_STEP] long.
+///
+/// Every keeper also runs a [FailureDetector] over the back-ends. The keeper
+/// leading the log records every change in which are alive as a new
+/// membership view, and publishes it for bin clients to read, see
+/// [crate::lab2::membership].
+pub async fn serve_keeper(mut kc: KeeperConfig) -> TribResult<()> {
+    let storage = kc
+        .state
+        .take()
+        .unwrap_or_else(|| Box::new(MemStorage::new()));
+    let started = async {
+        let mut server = TransportServer::builder();
+        if let Some(tls) = &kc.tls {
+            server = server.tls_config(tls.server_config()?)?;
+        }
+        let listener = TcpListener::bind(kc.addr()).await?;
+        let builder = ClientBuilder::new()
+            .config(kc.client_config())
+            .retry(RetryPolicy::none());
+        let anti_entropy = match kc.quorum {
+            Some(quorum) => Some(AntiEntropy::new(&kc.backs, &builder, quorum.n)?),
+            None => None,
+        };
+        let backs = kc
+            .backs
+            .iter()
+            .map(|addr| builder.build(&format!("http://{}", addr)))
+            .collect::<TribResult<Vec<StorageClient>>>()?;
+        let detector = Arc::new(FailureDetector::new(&kc.backs, &builder)?);
+        let raft = RaftNode::new(&kc, storage).await?;
+        TribResult::Ok((server, listener, raft, detector, anti_entropy, backs))
+    };
+    let (mut server, listener, raft, detector, anti_entropy, backs) = match started.await {
+        Ok(s) => s,
+        Err(e) => {
+            if let Some(tx) = &kc.ready {
+                let _ = tx.send(false);
+            }
+            return Err(e);
+        }
+    };
+    if let Some(tx) = &kc.ready {
+        let _ = tx.send(true);
+    }
+
+    let auth = AuthInterceptor::new(kc.auth);
+    let (stop, stopped) = oneshot::channel::<()>();
+    let serving = tokio::spawn(
+        server
+            .add_service(InterceptedService::new(raft.keeper_service(), auth.clone()))
+            .add_service(InterceptedService::new(raft.service(), auth))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+                let _ = stopped.await;
+            }),
+    );
+    let ticks = tokio::spawn(raft.clone().run());
+    let (events_tx, mut events) = mpsc::channel(16);
+    let probes = tokio::spawn(detector.clone().run(events_tx));
+    let members = {
+        let raft = raft.clone();
+        tokio::spawn(async move {
+            let mut interval = time::interval(PUBLISH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => (),
+                    Some(event) = events.recv() => info!("back-end membership: {:?}", event),
+                }
+                if raft.is_leader().await {
+                    if let Err(e) = update_membership(&raft, &detector).await {
+                        warn!("updating the membership view failed: {}", e);
+                    }
+                }
+            }
+        })
+    };
+    let watermarks = {
+        let raft = raft.clone();
+        let hybrid_clock = kc.hybrid_clock;
+        tokio::spawn(async move {
+            let mut interval = time::interval(WATERMARK_INTERVAL);
+            let mut latest = 0;
+            loop {
+                interval.tick().await;
+                if raft.is_leader().await {
+                    let raised = raise_watermark(&raft, &backs, hybrid_clock, &mut latest);
+                    if let Err(e) = raised.await {
+                        warn!("raising the clock watermark failed: {}", e);
+                    }
+                }
+            }
+        })
+    };
+    let work = async {
+        let mut changes = raft.subscribe_leadership();
+        let mut interval = time::interval(ANTI_ENTROPY_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => (),
+                _ = changes.changed() => (),
+            }
+            let anti_entropy = match &anti_entropy {
+                Some(anti_entropy) => anti_entropy,
+                None => continue,
+            };
+            match raft.while_leader(anti_entropy.run()).await {
+                Some(Ok(repairs)) if repairs.keys > 0 => info!("anti-entropy: {:?}", repairs),
+                Some(Err(e)) => warn!("anti-entropy failed: {}", e),
+                _ => (),
+            }
+        }
+    };
+    let mut shutdown = kc.shutdown;
+    tokio::select! {
+        _ = work => (),
+        _ = async {
+            match &mut shutdown {
+                Some(rx) => rx.recv().await,
+                None => std::future::pending().await,
+            }
+        } => (),
+    }
+    ticks.abort();
+    probes.abort();
+    members.abort();
+    watermarks.abort();
+    // let the connections of other keepers go too, so they stop hearing
+    // from this one
+    let _ = stop.send(());
+    serving.await.map_err(|e| e.to_string())??;
+    Ok(())
+}
+
+/// Syncs the clocks of `backs` to no less than `latest`, the latest clock
+/// seen so far, and the watermark recorded in the log of `raft`, and with
+/// `hybrid_clock` to no less than [hlc::now] either. Then raises `latest` to
+/// the latest of them, and records it as the new watermark if it is a
+/// [WATERMARK_STEP] past the last one. Back-ends which cannot be reached
+/// are left out.
+async fn raise_watermark(
+    raft: &RaftNode,
+    backs: &[StorageClient],
+    hybrid_clock: bool,
+    latest: &mut u64,
+) -> TribResult<()> {
+    let watermark = raft.state().watermark;
+    let at_least = hlc::sync_floor(watermark.max(*latest), hybrid_clock);
+    let clocks = join_all(backs.iter().map(|b| b.clock(at_least))).await;
+    if let Some(clock) = clocks.into_iter().flatten().max() {
+        *latest = (*latest).max(clock);
+    }
+    let step = match hybrid_clock {
+        true => hlc::from_parts(HYBRID_WATERMARK_STEP.as_millis() as u64, 0),
+        false => WATERMARK_STEP,
+    };
+    if *latest >= watermark.saturating_add(step) {
+        raft.propose(Command::Watermark(*latest)).await?;
+    }
+    Ok(())
+}
+
+/// Records the back-ends `detector` takes for alive as a new membership view
+/// in the log of `raft` if they changed, then publishes the latest view.
+async fn update_membership(raft: &RaftNode, detector: &FailureDetector) -> TribResult<()> {
+    let alive = match detector.alive() {
+        Some(alive) => alive,
+        None => return Ok(()),
+    };
+    let view = raft.state().membership;
+    if view.version == 0 || view.alive != alive {
+        let view = Membership {
+            version: view.version + 1,
+            alive,
+        };
+        raft.propose(Command::Membership(view)).await?;
+    }
+    detector.publish(&raft.state().membership).await;
+    Ok(())
 }
 
 /// this function accepts a [BinStorage] client which should be used in order to
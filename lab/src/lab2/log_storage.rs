@@ -0,0 +1,617 @@
+//! a [Storage] which keeps values and lists as logs of writes, so that
+//! writers racing on the same key converge
+//!
+//! [LogStorage] stores every key as a list in the storage it wraps, which
+//! only ever gets entries appended: each write becomes an entry stamped with
+//! a clock from the storage and the id of the writer, as quorum writes are
+//! (see [crate::lab2::quorum]). Readers order the entries by their stamps,
+//! so they all see the same value and the same list whatever order the
+//! entries were appended in:
+//!
+//! - value `k` is logged in list `k@value`. Its value is that of the newest
+//!   entry, `=<value>`, or `~<expiry><value>` when set with a TTL; an empty
+//!   value clears the key, and is logged as expiring right away.
+//! - list `k` is logged in list `k@list`, as `+<element>` entries, and
+//!   `-<stamp>` entries which remove the element appended with that stamp.
+//!
+//! The logs only grow until compacted: [LogStorage::compact_log] drops the
+//! entries a log no longer needs, i.e. every value entry but the newest, and
+//! the removed list elements along with their removals. The newest value
+//! entry is kept even when it clears the key, so that an older write
+//! appended late cannot bring the value back, until it has been expired for
+//! a grace period ([TOMBSTONE_GRACE] unless set with
+//! [LogStorage::with_grace]); the whole log is dropped then. [LogStorage::run]
+//! compacts the logs written to in the background, and drops the logs of
+//! expired or cleared values once their grace period is over.
+//!
+//! What remains costs space and writes: a compacted log still holds the
+//! newest entry of every value, and of every cleared one for the grace
+//! period, and the elements of every list. Compacting a log reads it whole
+//! and removes the entries dropped one by one. Value logs have no TTL in the
+//! wrapped storage, which would be set by whichever write came last rather
+//! than by the newest one; the logs of expired values written by others, or
+//! before a restart, are only dropped by [LogStorage::compact]. A list
+//! appended to with a TTL expires whole, as in the wrapped storage, but an
+//! append older than an entry already in the log leaves the TTL of the log
+//! as it is.
+//!
+//! ```
+//! # use lab::lab2::log_storage::LogStorage;
+//! # use tribbler::storage::{KeyList, KeyString, KeyValue, MemStorage};
+//! # #[tokio::main]
+//! # async fn main() -> tribbler::err::TribResult<()> {
+//! let storage = LogStorage::new(MemStorage::new());
+//! storage.set(&KeyValue::new("name", "Alice")).await?;
+//! storage.set(&KeyValue::new("name", "Alicia")).await?;
+//! assert_eq!(Some("Alicia".to_string()), storage.get("name").await?);
+//! assert_eq!(2, storage.inner().list_get("name@value").await?.0.len());
+//! assert_eq!(1, storage.compact_log("name@value").await?);
+//! assert_eq!(Some("Alicia".to_string()), storage.get("name").await?);
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use log::warn;
+use tribbler::{
+    err::{ErrorCode, TribResult, TribblerError},
+    storage::{KeyList, KeyString, KeyValue, List, Op, OpResult, Pattern, Storage},
+};
+
+use crate::lab2::quorum::{live, now_millis, stamp, unstamp, Version};
+
+/// suffix of the list logging the writes to a value
+pub const VALUE_LOG: &str = "@value";
+
+/// suffix of the list logging the writes to a list
+pub const LIST_LOG: &str = "@list";
+
+/// how often [LogStorage::run] compacts the logs written to
+pub const COMPACT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// how long the log of a value is kept once the value expired or was
+/// cleared, so that older writes appended late cannot bring it back
+pub const TOMBSTONE_GRACE: Duration = Duration::from_secs(60);
+
+/// number of hex digits of the expiry of a value set with a TTL
+const EXPIRY_LEN: usize = 16;
+
+/// Encodes the payload of a value entry.
+fn encode_value(value: &str, expires: Option<u64>) -> String {
+    match expires {
+        Some(expires) => format!("~{:016x}{}", expires, value),
+        None => format!("={}", value),
+    }
+}
+
+/// Decodes the payload of a value entry into the value and when it expires,
+/// in milliseconds since the Unix epoch.
+fn decode_value(payload: &str) -> (&str, Option<u64>) {
+    if let Some(value) = payload.strip_prefix('=') {
+        return (value, None);
+    }
+    let expiring = payload
+        .strip_prefix('~')
+        .filter(|s| s.len() >= EXPIRY_LEN && s.is_char_boundary(EXPIRY_LEN))
+        .and_then(|s| {
+            let expires = u64::from_str_radix(&s[..EXPIRY_LEN], 16).ok()?;
+            Some((&s[EXPIRY_LEN..], Some(expires)))
+        });
+    // not written by a log storage
+    expiring.unwrap_or((payload, None))
+}
+
+/// Returns the value of the newest entry of a value log with its expiry,
+/// whether it has expired or not.
+fn newest_value(entries: &[String]) -> Option<(&str, Option<u64>)> {
+    let (_, payload) = entries.iter().map(|e| unstamp(e)).max()?;
+    Some(decode_value(payload))
+}
+
+/// Returns the value logged in `entries` as of `now`, with its expiry, or
+/// [None] if it is unset.
+fn logged_value(entries: &[String], now: u64) -> Option<(String, Option<u64>)> {
+    match newest_value(entries)? {
+        ("", _) => None,
+        (_, Some(expires)) if expires <= now => None,
+        (value, expires) => Some((value.to_string(), expires)),
+    }
+}
+
+/// Returns the entries of a value log which no longer matter as of `now`:
+/// all of them once the newest has been expired for `grace`, all but the
+/// newest otherwise.
+fn value_garbage(entries: &[String], now: u64, grace: Duration) -> Vec<String> {
+    let newest = entries.iter().max_by_key(|e| unstamp(e));
+    let gone = newest
+        .and_then(|e| decode_value(unstamp(e).1).1)
+        .is_some_and(|expires| expires.saturating_add(grace.as_millis() as u64) <= now);
+    entries
+        .iter()
+        .filter(|&e| gone || Some(e) != newest)
+        .cloned()
+        .collect()
+}
+
+/// Returns the entries of a list log which no longer matter: the elements
+/// removed, then the removals. Dropping them in that order never brings an
+/// element back.
+fn list_garbage(entries: &[String]) -> Vec<String> {
+    let removed = entries
+        .iter()
+        .filter_map(|e| unstamp(e).1.strip_prefix('-').and_then(Version::decode))
+        .collect::<BTreeSet<_>>();
+    let elements = entries.iter().filter(|e| {
+        let (v, s) = unstamp(e);
+        s.starts_with('+') && removed.contains(&v)
+    });
+    // a removal whose element is not in the log is left over from an
+    // earlier compaction
+    let removals = entries.iter().filter(|e| unstamp(e).1.starts_with('-'));
+    elements.chain(removals).cloned().collect()
+}
+
+/// Keeps values and lists as logs of writes in the storage it wraps, see the
+/// module documentation.
+///
+/// As on a [crate::lab2::quorum::QuorumClient], [KeyString::cas] and
+/// [KeyString::incr] read, then append, so two writers racing on the same
+/// key may both succeed. [Storage::watch] is not supported.
+pub struct LogStorage<S> {
+    storage: S,
+    /// id of this writer, which orders writes made at the same clock
+    writer: u32,
+    /// the largest clock value handed out so far
+    clock: AtomicU64,
+    /// the logs written to since they were last compacted
+    dirty: Mutex<BTreeSet<String>>,
+    /// the logs of values which expire, with when they are due to be
+    /// dropped, in milliseconds since the Unix epoch
+    expiring: Mutex<BTreeSet<(u64, String)>>,
+    /// how long the log of an expired value is kept
+    grace: Duration,
+}
+
+impl<S: Storage> LogStorage<S> {
+    /// Creates a log storage keeping its logs in `storage`.
+    pub fn new(storage: S) -> LogStorage<S> {
+        LogStorage {
+            storage,
+            writer: rand::random(),
+            clock: AtomicU64::new(0),
+            dirty: Mutex::new(BTreeSet::new()),
+            expiring: Mutex::new(BTreeSet::new()),
+            grace: TOMBSTONE_GRACE,
+        }
+    }
+
+    /// Keeps the log of a value for `grace` once the value expired or was
+    /// cleared, instead of [TOMBSTONE_GRACE]. Every writer sharing the logs
+    /// should use the same.
+    pub fn with_grace(mut self, grace: Duration) -> LogStorage<S> {
+        self.grace = grace;
+        self
+    }
+
+    /// the storage holding the logs
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    /// a version newer than every version this writer has seen
+    async fn version(&self) -> TribResult<Version> {
+        Ok(Version::new(self.clock(0).await?, self.writer))
+    }
+
+    fn touch(&self, log: String) {
+        self.dirty
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(log);
+    }
+
+    /// Has `log`, whose newest value entry expires at `expires`, dropped
+    /// once the grace period after that is over.
+    fn expire(&self, log: &str, expires: u64) {
+        let due = expires.saturating_add(self.grace.as_millis() as u64);
+        self.expiring
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert((due, log.to_string()));
+    }
+
+    /// Appends `payload`, stamped, to `log`.
+    async fn append(&self, log: String, payload: &str) -> TribResult<()> {
+        let kv = KeyValue::new(&log, &stamp(self.version().await?, payload));
+        self.storage.list_append(&kv).await?;
+        self.touch(log);
+        Ok(())
+    }
+
+    /// Appends `payload`, stamped with `version`, to `log`, with a TTL for
+    /// the whole log unless the log already holds a newer entry, whose
+    /// writer has set it.
+    async fn append_ttl(
+        &self,
+        log: String,
+        version: Version,
+        payload: &str,
+        ttl: Duration,
+    ) -> TribResult<()> {
+        let entries = self.storage.list_get(&log).await?.0;
+        let kv = KeyValue::new(&log, &stamp(version, payload));
+        match entries.iter().all(|e| unstamp(e).0 < version) {
+            true => self.storage.list_append_ttl(&kv, ttl).await?,
+            false => self.storage.list_append(&kv).await?,
+        };
+        self.touch(log);
+        Ok(())
+    }
+
+    /// the value of `key` with its expiry, if set
+    async fn value(&self, key: &str) -> TribResult<Option<(String, Option<u64>)>> {
+        let entries = self.storage.list_get(&value_log(key)).await?.0;
+        Ok(logged_value(&entries, now_millis()))
+    }
+
+    /// Logs `value` for `key`, expiring at `expires` if set, and has the
+    /// log dropped once the grace period after that is over.
+    async fn put(&self, key: &str, value: &str, expires: Option<u64>) -> TribResult<()> {
+        let expires = match value {
+            "" => Some(now_millis()),
+            _ => expires,
+        };
+        let log = value_log(key);
+        if let Some(expires) = expires {
+            self.expire(&log, expires);
+        }
+        self.append(log, &encode_value(value, expires)).await
+    }
+
+    /// the elements of list `key`, with their versions
+    async fn elements(&self, key: &str) -> TribResult<Vec<(Version, String)>> {
+        let entries = self.storage.list_get(&list_log(key)).await?.0;
+        Ok(live(&entries.into_iter().collect()))
+    }
+
+    /// Lists the keys matching `p` whose logs end with `suffix`, with the
+    /// entries of each log.
+    async fn logs(&self, p: &Pattern, suffix: &str) -> TribResult<Vec<(String, Vec<String>)>> {
+        let logged = Pattern {
+            prefix: p.prefix.clone(),
+            suffix: suffix.to_string(),
+            ..Default::default()
+        };
+        let m = p.matcher()?;
+        let keys = self
+            .storage
+            .list_keys(&logged)
+            .await?
+            .0
+            .into_iter()
+            .filter_map(|log| {
+                let key = log.strip_suffix(suffix)?;
+                m.matches(key).then(|| (key.to_string(), log.clone()))
+            })
+            .collect::<Vec<_>>();
+        let ops = keys
+            .iter()
+            .map(|(_, log)| Op::ListGet(log.clone()))
+            .collect::<Vec<_>>();
+        let results = self.storage.batch(&ops).await?;
+        Ok(keys
+            .into_iter()
+            .zip(results)
+            .map(|((key, _), r)| match r {
+                OpResult::List(l) => (key, l.0),
+                _ => (key, vec![]),
+            })
+            .collect())
+    }
+
+    /// Drops the entries `log` no longer needs, and returns how many.
+    pub async fn compact_log(&self, log: &str) -> TribResult<u64> {
+        let entries = self.storage.list_get(log).await?.0;
+        let garbage = if log.ends_with(VALUE_LOG) {
+            let garbage = value_garbage(&entries, now_millis(), self.grace);
+            if garbage.len() < entries.len() {
+                if let Some((_, Some(expires))) = newest_value(&entries) {
+                    self.expire(log, expires);
+                }
+            }
+            garbage
+        } else if log.ends_with(LIST_LOG) {
+            list_garbage(&entries)
+        } else {
+            vec![]
+        };
+        let mut dropped = 0;
+        for entry in garbage {
+            dropped += self
+                .storage
+                .list_remove(&KeyValue::new(log, &entry))
+                .await? as u64;
+        }
+        Ok(dropped)
+    }
+
+    /// Compacts every log in the storage, and returns the number of entries
+    /// dropped.
+    pub async fn compact(&self) -> TribResult<u64> {
+        let mut dropped = 0;
+        for suffix in [VALUE_LOG, LIST_LOG] {
+            let p = Pattern {
+                suffix: suffix.to_string(),
+                ..Default::default()
+            };
+            for log in self.storage.list_keys(&p).await?.0 {
+                dropped += self.compact_log(&log).await?;
+            }
+        }
+        Ok(dropped)
+    }
+
+    /// Compacts the logs this storage wrote to since they were last
+    /// compacted, and those of the values it wrote which are due to be
+    /// dropped, and returns the number of entries dropped.
+    pub async fn compact_dirty(&self) -> TribResult<u64> {
+        let mut dirty = std::mem::take(&mut *self.dirty.lock().unwrap_or_else(|e| e.into_inner()));
+        {
+            let mut expiring = self.expiring.lock().unwrap_or_else(|e| e.into_inner());
+            let pending = expiring.split_off(&(now_millis() + 1, String::new()));
+            dirty.extend(
+                std::mem::replace(&mut *expiring, pending)
+                    .into_iter()
+                    .map(|(_, log)| log),
+            );
+        }
+        let mut dropped = 0;
+        let mut logs = dirty.into_iter();
+        while let Some(log) = logs.next() {
+            match self.compact_log(&log).await {
+                Ok(n) => dropped += n,
+                Err(e) => {
+                    // left for the next compaction
+                    self.touch(log);
+                    logs.for_each(|log| self.touch(log));
+                    return Err(e);
+                }
+            }
+        }
+        Ok(dropped)
+    }
+
+    /// Calls [LogStorage::compact_dirty] every [COMPACT_INTERVAL], for as
+    /// long as the storage is used elsewhere.
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(COMPACT_INTERVAL);
+        loop {
+            interval.tick().await;
+            if Arc::strong_count(&self) == 1 {
+                return;
+            }
+            if let Err(e) = self.compact_dirty().await {
+                warn!("log compaction failed: {}", e);
+            }
+        }
+    }
+}
+
+fn value_log(key: &str) -> String {
+    format!("{}{}", key, VALUE_LOG)
+}
+
+fn list_log(key: &str) -> String {
+    format!("{}{}", key, LIST_LOG)
+}
+
+#[async_trait]
+impl<S: Storage> KeyString for LogStorage<S> {
+    async fn get(&self, key: &str) -> TribResult<Option<String>> {
+        Ok(self.value(key).await?.map(|(value, _)| value))
+    }
+
+    async fn set(&self, kv: &KeyValue) -> TribResult<bool> {
+        self.put(&kv.key, &kv.value, None).await?;
+        Ok(true)
+    }
+
+    async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        let expires = now_millis().saturating_add(ttl.as_millis() as u64);
+        self.put(&kv.key, &kv.value, Some(expires)).await?;
+        Ok(true)
+    }
+
+    async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)> {
+        let current = self.get(&kv.key).await?;
+        if current.as_deref().unwrap_or("") != old {
+            return Ok((false, current));
+        }
+        self.put(&kv.key, &kv.value, None).await?;
+        Ok((true, Some(kv.value.clone()).filter(|v| !v.is_empty())))
+    }
+
+    async fn incr(&self, key: &str, delta: i64) -> TribResult<i64> {
+        let (current, expires) = match self.value(key).await? {
+            Some((v, expires)) => (
+                v.parse::<i64>().map_err(|_| {
+                    TribblerError::Status(
+                        ErrorCode::Invalid,
+                        format!("value of \"{}\" is not an integer", key),
+                    )
+                })?,
+                expires,
+            ),
+            None => (0, None),
+        };
+        let value = current.checked_add(delta).ok_or_else(|| {
+            TribblerError::Status(
+                ErrorCode::Invalid,
+                format!("incrementing \"{}\" overflows", key),
+            )
+        })?;
+        self.put(key, &value.to_string(), expires).await?;
+        Ok(value)
+    }
+
+    async fn keys(&self, p: &Pattern) -> TribResult<List> {
+        let now = now_millis();
+        let mut keys = self
+            .logs(p, VALUE_LOG)
+            .await?
+            .into_iter()
+            .filter(|(_, entries)| logged_value(entries, now).is_some())
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        keys.sort();
+        Ok(List(keys))
+    }
+}
+
+#[async_trait]
+impl<S: Storage> KeyList for LogStorage<S> {
+    async fn list_get(&self, key: &str) -> TribResult<List> {
+        Ok(List(
+            self.elements(key)
+                .await?
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect(),
+        ))
+    }
+
+    async fn list_append(&self, kv: &KeyValue) -> TribResult<bool> {
+        self.append(list_log(&kv.key), &format!("+{}", kv.value))
+            .await?;
+        Ok(true)
+    }
+
+    async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        // the whole log expires along with the list
+        let version = self.version().await?;
+        self.append_ttl(list_log(&kv.key), version, &format!("+{}", kv.value), ttl)
+            .await?;
+        Ok(true)
+    }
+
+    async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32> {
+        let removed = self
+            .elements(&kv.key)
+            .await?
+            .into_iter()
+            .filter(|(_, v)| *v == kv.value)
+            .map(|(version, _)| version)
+            .collect::<Vec<_>>();
+        for version in removed.iter() {
+            self.append(list_log(&kv.key), &format!("-{}", version.encode()))
+                .await?;
+        }
+        Ok(removed.len() as u32)
+    }
+
+    async fn list_keys(&self, p: &Pattern) -> TribResult<List> {
+        let mut keys = self
+            .logs(p, LIST_LOG)
+            .await?
+            .into_iter()
+            .filter(|(_, entries)| !live(&entries.iter().cloned().collect()).is_empty())
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        keys.sort();
+        Ok(List(keys))
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for LogStorage<S> {
+    async fn clock(&self, at_least: u64) -> TribResult<u64> {
+        let at_least = at_least.max(self.clock.load(Ordering::SeqCst).saturating_add(1));
+        let clock = self.storage.clock(at_least).await?;
+        self.clock.fetch_max(clock, Ordering::SeqCst);
+        Ok(clock)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tribbler::{
+        err::TribResult,
+        storage::{KeyList, KeyString, KeyValue, MemStorage},
+    };
+
+    use super::{
+        decode_value, encode_value, list_garbage, list_log, logged_value, now_millis, stamp,
+        value_garbage, value_log, LogStorage, Version,
+    };
+
+    #[test]
+    fn log_storage_entries() {
+        assert_eq!(("a=b", None), decode_value(&encode_value("a=b", None)));
+        assert_eq!(("v", Some(42)), decode_value(&encode_value("v", Some(42))));
+        assert_eq!(("~plain", None), decode_value("~plain"));
+
+        let v = |clock| Version::new(clock, 1);
+        let values = vec![
+            stamp(v(3), &encode_value("expired", Some(100))),
+            stamp(v(1), &encode_value("first", None)),
+            stamp(v(2), &encode_value("second", None)),
+        ];
+        assert_eq!(None, logged_value(&values, 100));
+        assert_eq!(
+            Some(("expired".to_string(), Some(100))),
+            logged_value(&values, 99)
+        );
+        let grace = Duration::from_millis(10);
+        assert_eq!(values[1..].to_vec(), value_garbage(&values, 99, grace));
+        assert_eq!(values[1..].to_vec(), value_garbage(&values, 109, grace));
+        // the whole log goes once the newest entry expired for the grace
+        // period, be it a value set with a TTL or a cleared one
+        assert_eq!(values, value_garbage(&values, 110, grace));
+        let cleared = vec![values[1].clone(), stamp(v(4), &encode_value("", Some(50)))];
+        assert_eq!(cleared[..1].to_vec(), value_garbage(&cleared, 59, grace));
+        assert_eq!(cleared, value_garbage(&cleared, 60, grace));
+
+        let list = vec![
+            stamp(v(1), "+a"),
+            stamp(v(2), "+b"),
+            stamp(v(3), &format!("-{}", v(1).encode())),
+            stamp(v(4), &format!("-{}", v(9).encode())),
+        ];
+        assert_eq!(
+            vec![list[0].clone(), list[2].clone(), list[3].clone()],
+            list_garbage(&list)
+        );
+    }
+
+    #[tokio::test]
+    async fn log_storage_late_ttl() -> TribResult<()> {
+        let s = LogStorage::new(MemStorage::new());
+        let old = Version::new(0, 0);
+        let ttl = Duration::from_millis(50);
+        // a value set with a TTL, appended after a newer one set without
+        s.set(&KeyValue::new("k", "new")).await?;
+        let expiring = encode_value("old", Some(now_millis() + 50));
+        s.storage
+            .list_append(&KeyValue::new(&value_log("k"), &stamp(old, &expiring)))
+            .await?;
+        // an element appended with a TTL, after a newer one
+        s.list_append_ttl(&KeyValue::new("l", "new"), Duration::from_secs(60))
+            .await?;
+        s.append_ttl(list_log("l"), old, "+old", ttl).await?;
+
+        tokio::time::sleep(ttl * 2).await;
+        assert_eq!(Some("new".to_string()), s.get("k").await?);
+        assert_eq!(vec!["old", "new"], s.list_get("l").await?.0);
+        Ok(())
+    }
+}
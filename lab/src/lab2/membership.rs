@@ -0,0 +1,269 @@
+//! the failure detector a keeper started by [crate::lab2::serve_keeper] uses
+//! to learn which back-ends are alive, and the membership view it publishes
+//! for bin clients to read
+//!
+//! [FailureDetector] probes every back-end with [Storage::clock] every
+//! [PROBE_INTERVAL]. A back-end is suspected, and taken for dead, after
+//! [SUSPECT_AFTER] probes in a row failed, and taken for alive again as
+//! soon as one succeeds; each change comes out as a [MembershipEvent].
+//!
+//! The keeper leading the Raft log (see [crate::lab2::raft]) records every
+//! new view there as a [Membership] with a newer version, and writes it to
+//! every back-end alive, under [VIEW_KEY] in bin [MEMBERSHIP_BIN]. The view
+//! is stamped with its version as a quorum write would be (see
+//! [crate::lab2::quorum]), so an older view never overwrites a newer one,
+//! whether by a stale keeper, read repair or anti-entropy. A bin client
+//! reads it back with [read_view]:
+//!
+//! ```no_run
+//! # use lab::lab2::{self, membership::{read_view, MEMBERSHIP_BIN}};
+//! # use tribbler::storage::BinStorage;
+//! # #[tokio::main]
+//! # async fn main() -> tribbler::err::TribResult<()> {
+//! let bc = lab2::new_bin_client(vec!["127.0.0.1:3000".to_string()]).await?;
+//! if let Some(view) = read_view(&*bc.bin(MEMBERSHIP_BIN).await?).await? {
+//!     println!("back-ends {:?} are alive as of version {}", view.alive, view.version);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::future::join_all;
+use log::warn;
+use tokio::sync::mpsc::Sender;
+use tribbler::{err::TribResult, namespace::Bin, storage::Storage};
+
+use crate::{
+    lab1::{
+        builder::{ClientBuilder, RetryPolicy},
+        client::StorageClient,
+    },
+    lab2::{
+        quorum::{put, stamp, unstamp, Version},
+        raft::Membership,
+    },
+};
+
+/// how often [FailureDetector::run] probes every back-end
+pub const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// how long a probe may take before it counts as failed
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// number of probes in a row which must fail before a back-end is taken for
+/// dead
+pub const SUSPECT_AFTER: u32 = 3;
+
+/// how often the keeper leading the Raft log publishes the membership view
+/// again, so that back-ends which joined get it too
+pub const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// name of the bin holding the membership view published by the keepers.
+/// No user bin should have this name.
+pub const MEMBERSHIP_BIN: &str = "@membership";
+
+/// key of the membership view in [MEMBERSHIP_BIN]
+pub const VIEW_KEY: &str = "view";
+
+/// A change in which back-ends are alive, by index in the list of back-ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MembershipEvent {
+    /// the back-end answers, for the first time or since it was taken for
+    /// dead
+    Join(usize),
+    /// the back-end stopped answering, or never did
+    Leave(usize),
+}
+
+/// The suspicion-based failure detection of [FailureDetector], which does
+/// no I/O: probe outcomes are fed to [Suspicion::observe].
+#[derive(Debug, Clone)]
+pub struct Suspicion {
+    /// probes failed in a row, by back-end
+    failures: Vec<u32>,
+    /// whether each back-end is taken for alive, [None] until known
+    alive: Vec<Option<bool>>,
+    suspect_after: u32,
+}
+
+impl Suspicion {
+    /// Creates the state of `n` back-ends, each taken for dead after
+    /// `suspect_after` failed probes in a row.
+    pub fn new(n: usize, suspect_after: u32) -> Suspicion {
+        Suspicion {
+            failures: vec![0; n],
+            alive: vec![None; n],
+            suspect_after: suspect_after.max(1),
+        }
+    }
+
+    /// Records whether a probe of back-end `i` succeeded, and returns the
+    /// change it makes, if any.
+    pub fn observe(&mut self, i: usize, ok: bool) -> Option<MembershipEvent> {
+        if ok {
+            self.failures[i] = 0;
+            if self.alive[i] == Some(true) {
+                return None;
+            }
+            self.alive[i] = Some(true);
+            return Some(MembershipEvent::Join(i));
+        }
+        self.failures[i] += 1;
+        if self.failures[i] < self.suspect_after || self.alive[i] == Some(false) {
+            return None;
+        }
+        self.alive[i] = Some(false);
+        Some(MembershipEvent::Leave(i))
+    }
+
+    /// Returns the back-ends taken for alive, or [None] while some back-end
+    /// is neither known to be alive nor dead.
+    pub fn alive(&self) -> Option<Vec<usize>> {
+        let mut alive = vec![];
+        for (i, a) in self.alive.iter().enumerate() {
+            match a {
+                Some(true) => alive.push(i),
+                Some(false) => (),
+                None => return None,
+            }
+        }
+        Some(alive)
+    }
+}
+
+/// Probes the back-ends to tell which are alive, see [Suspicion].
+pub struct FailureDetector {
+    backs: Vec<StorageClient>,
+    suspicion: Mutex<Suspicion>,
+}
+
+impl FailureDetector {
+    /// Creates a detector for the back-ends at `backs` (`host:port`), which
+    /// connects to them as set up by `builder`, but without retries and with
+    /// a deadline of [PROBE_TIMEOUT].
+    pub fn new(backs: &[String], builder: &ClientBuilder) -> TribResult<FailureDetector> {
+        let builder = builder
+            .clone()
+            .deadline(PROBE_TIMEOUT)
+            .retry(RetryPolicy::none());
+        let clients = backs
+            .iter()
+            .map(|addr| builder.build(&format!("http://{}", addr)))
+            .collect::<TribResult<Vec<StorageClient>>>()?;
+        Ok(FailureDetector {
+            suspicion: Mutex::new(Suspicion::new(backs.len(), SUSPECT_AFTER)),
+            backs: clients,
+        })
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Suspicion) -> T) -> T {
+        let mut suspicion = self.suspicion.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut suspicion)
+    }
+
+    /// Probes every back-end once, and returns the changes seen.
+    pub async fn probe(&self) -> Vec<MembershipEvent> {
+        let probes = self.backs.iter().map(|b| b.clock(0));
+        let outcomes = join_all(probes).await;
+        self.with(|s| {
+            outcomes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, r)| s.observe(i, r.is_ok()))
+                .collect()
+        })
+    }
+
+    /// Returns the back-ends taken for alive, or [None] until every back-end
+    /// has been probed enough to tell.
+    pub fn alive(&self) -> Option<Vec<usize>> {
+        self.with(|s| s.alive())
+    }
+
+    /// Calls [FailureDetector::probe] every [PROBE_INTERVAL] and sends the
+    /// changes to `events`, until its receiver is dropped.
+    pub async fn run(self: Arc<Self>, events: Sender<MembershipEvent>) {
+        let mut interval = tokio::time::interval(PROBE_INTERVAL);
+        loop {
+            interval.tick().await;
+            for event in self.probe().await {
+                if events.send(event).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Writes `view` to every back-end taken for alive, unless it holds a
+    /// newer view, and returns the number of back-ends written to. The
+    /// back-ends which fail are left out, to get the view on the next
+    /// publish.
+    pub async fn publish(&self, view: &Membership) -> usize {
+        let alive = self.alive().unwrap_or_default();
+        let writes = alive.iter().map(|&i| {
+            let bin = Bin::new(MEMBERSHIP_BIN, self.backs[i].clone());
+            async move { (i, publish(&bin, view).await) }
+        });
+        let mut written = 0;
+        for (i, r) in join_all(writes).await {
+            match r {
+                Ok(()) => written += 1,
+                Err(e) => warn!(
+                    "publishing view {} to back-end {} failed: {}",
+                    view.version, i, e
+                ),
+            }
+        }
+        written
+    }
+}
+
+/// Writes `view` to [VIEW_KEY] of `bin`, the [MEMBERSHIP_BIN] bin of a
+/// back-end, unless it holds a newer view.
+pub async fn publish<S: Storage>(bin: &S, view: &Membership) -> TribResult<()> {
+    let json = serde_json::to_string(view).map_err(|e| e.to_string())?;
+    put(bin, VIEW_KEY, &stamp(Version::new(view.version, 0), &json)).await
+}
+
+/// Reads the view published in `bin`, the [MEMBERSHIP_BIN] bin of a bin
+/// storage or of a back-end, if there is one.
+pub async fn read_view<S: Storage + ?Sized>(bin: &S) -> TribResult<Option<Membership>> {
+    match bin.get(VIEW_KEY).await? {
+        // quorum reads take the version off, plain reads do not
+        Some(stored) => Ok(Some(
+            serde_json::from_str(unstamp(&stored).1).map_err(|e| e.to_string())?,
+        )),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MembershipEvent::*, Suspicion};
+
+    #[test]
+    fn membership_suspicion() {
+        let mut s = Suspicion::new(3, 2);
+        assert_eq!(Some(Join(0)), s.observe(0, true));
+        assert_eq!(None, s.observe(0, true));
+        assert_eq!(None, s.observe(1, false));
+        assert_eq!(Some(Join(2)), s.observe(2, true));
+        // back-end 1 is not known yet
+        assert_eq!(None, s.alive());
+        assert_eq!(Some(Leave(1)), s.observe(1, false));
+        assert_eq!(Some(vec![0, 2]), s.alive());
+
+        // a single failure is only a suspicion
+        assert_eq!(None, s.observe(0, false));
+        assert_eq!(None, s.observe(0, true));
+        assert_eq!(None, s.observe(0, false));
+        assert_eq!(Some(Leave(0)), s.observe(0, false));
+        assert_eq!(None, s.observe(0, false));
+        assert_eq!(Some(Join(1)), s.observe(1, true));
+        assert_eq!(Some(vec![1, 2]), s.alive());
+    }
+}
@@ -0,0 +1,524 @@
+//! the live migration of data between back-ends, for a keeper to run when a
+//! back-end joins or leaves
+//!
+//! A [Migration] copies every value and list whose key matches a predicate,
+//! such as a [HashRange] of the ring (see [in_range]), from a source
+//! [Storage] to a destination one. Clients may keep writing to the source
+//! meanwhile: the migration watches it with [Storage::watch] before it
+//! copies anything, and once the copy is done replays the tail of changes
+//! in rounds, each copying again the keys touched since the last. The replay
+//! ends once a round copies no more than [TAIL_SETTLED] keys, after
+//! [MAX_TAIL_ROUNDS] rounds, or when no key of the range changes for
+//! [TAIL_IDLE], whatever comes first, so that it ends even while clients
+//! keep writing; changes to keys out of the range are not waited for. Every
+//! copy makes the destination hold what the source holds at that time, so
+//! copying a key twice is harmless. A list is only changed from its first
+//! element which differs, so one the source only appended to gets just the
+//! new elements.
+//!
+//! TTLs are not migrated, as [Storage] does not tell how long a key has
+//! left: a value or list which expires in the source is copied as one which
+//! does not, and stays in the destination.
+//!
+//! The caller is then expected to switch the range over to the destination,
+//! so clients stop writing it to the source, and to run the migration again
+//! to copy what was written in between.
+//!
+//! Progress is saved as a [Checkpoint] every [CHECKPOINT_EVERY] keys and at
+//! the end of every [Phase], in [Checkpoints]. Saved in the Raft log of the
+//! keepers with [RaftNode], a migration interrupted by its keeper failing is
+//! resumed by whichever keeper runs it next under the same name: the copy
+//! goes on after the last key saved, and changes are replayed from the
+//! checkpoint's place in the watch. If the source no longer has the changes
+//! since then, the migration starts over.
+//!
+//! To learn its place in the watch of the source, a migration writes to key
+//! `@migration::<name>` of the source when it starts; [Migration::finish]
+//! clears it.
+//!
+//! ```
+//! # use lab::lab2::migration::{MemCheckpoints, Migration};
+//! # use tribbler::storage::{KeyString, KeyValue, MemStorage};
+//! # #[tokio::main]
+//! # async fn main() -> tribbler::err::TribResult<()> {
+//! let (source, dest) = (MemStorage::new(), MemStorage::new());
+//! source.set(&KeyValue::new("alice::name", "Alice")).await?;
+//! source.set(&KeyValue::new("bob::name", "Bob")).await?;
+//! let migration = Migration::new("alice", &source, &dest, |key| key.starts_with("alice::"));
+//! let checkpoints = MemCheckpoints::default();
+//! let stats = migration.run(&checkpoints).await?;
+//! migration.finish(&checkpoints).await?;
+//! assert_eq!(1, stats.values);
+//! assert_eq!(Some("Alice".to_string()), dest.get("alice::name").await?);
+//! assert_eq!(None, dest.get("bob::name").await?);
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_util::{FutureExt, StreamExt};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tribbler::{
+    digest::{position, HashRange},
+    err::{TribResult, TribblerError},
+    storage::{Change, ChangeKind, ChangeStream, KeyValue, Pattern, Storage},
+};
+
+use crate::lab2::raft::{Command, RaftNode};
+
+/// number of keys copied between two checkpoints
+pub const CHECKPOINT_EVERY: u64 = 100;
+
+/// how long the source must go without changes to migrated keys for the
+/// replay of the log tail to be done
+pub const TAIL_IDLE: Duration = Duration::from_millis(100);
+
+/// a round of the tail replay which copies no more keys than this is the
+/// last one
+pub const TAIL_SETTLED: usize = 16;
+
+/// most rounds of the tail replay in a single [Migration::run]
+pub const MAX_TAIL_ROUNDS: u32 = 10;
+
+/// how long a migration waits for its own write to the source to show up in
+/// the watch
+const MARKER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// prefix of the keys migrations write to the source, which are never
+/// migrated
+const MARKER_PREFIX: &str = "@migration::";
+
+/// What a [Migration] is doing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Phase {
+    /// copying values, in key order
+    #[default]
+    Values,
+    /// copying lists, in key order
+    Lists,
+    /// replaying the changes made to the source since the migration started
+    Tail,
+}
+
+/// The progress of a [Migration], from which another keeper can resume it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Checkpoint {
+    pub phase: Phase,
+    /// the last key copied in [Checkpoint::phase], empty if none was
+    pub after: String,
+    /// the [Change::seq] after which the changes
+    /// to the source have not been replayed yet, 0 before the migration
+    /// knows its place in the watch
+    pub seq: u64,
+}
+
+/// What a [Migration::run] did, and how fast
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Stats {
+    /// values copied
+    pub values: u64,
+    /// lists copied
+    pub lists: u64,
+    /// list elements copied
+    pub items: u64,
+    /// keys copied again for changes made to the source while migrating
+    pub replayed: u64,
+    /// time the run took
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    /// values and lists copied, replays included, per second
+    pub fn keys_per_sec(&self) -> f64 {
+        let keys = (self.values + self.lists + self.replayed) as f64;
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => keys / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Where migrations save their [Checkpoint]s, by migration name
+#[async_trait]
+pub trait Checkpoints: Send + Sync {
+    /// the last checkpoint saved for `migration`, if any
+    async fn load(&self, migration: &str) -> TribResult<Option<Checkpoint>>;
+
+    /// Saves `checkpoint` for `migration`, or removes the one saved when
+    /// [None].
+    async fn save(&self, migration: &str, checkpoint: Option<&Checkpoint>) -> TribResult<()>;
+}
+
+/// Keeps checkpoints in memory, for migrations which need not survive the
+/// process running them.
+#[derive(Debug, Default)]
+pub struct MemCheckpoints(Mutex<HashMap<String, Checkpoint>>);
+
+#[async_trait]
+impl Checkpoints for MemCheckpoints {
+    async fn load(&self, migration: &str) -> TribResult<Option<Checkpoint>> {
+        let saved = self.0.lock().map_err(|e| e.to_string())?;
+        Ok(saved.get(migration).cloned())
+    }
+
+    async fn save(&self, migration: &str, checkpoint: Option<&Checkpoint>) -> TribResult<()> {
+        let mut saved = self.0.lock().map_err(|e| e.to_string())?;
+        match checkpoint {
+            Some(c) => saved.insert(migration.to_string(), c.clone()),
+            None => saved.remove(migration),
+        };
+        Ok(())
+    }
+}
+
+/// Keeps checkpoints in the Raft log, as [Command::Checkpoint]. Only the
+/// keeper leading the log can save them.
+#[async_trait]
+impl Checkpoints for RaftNode {
+    async fn load(&self, migration: &str) -> TribResult<Option<Checkpoint>> {
+        match self.state().checkpoints.get(migration) {
+            Some(progress) => Ok(Some(
+                serde_json::from_str(progress).map_err(|e| e.to_string())?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, migration: &str, checkpoint: Option<&Checkpoint>) -> TribResult<()> {
+        let progress = match checkpoint {
+            Some(c) => serde_json::to_string(c).map_err(|e| e.to_string())?,
+            None => String::new(),
+        };
+        self.propose(Command::Checkpoint {
+            migration: migration.to_string(),
+            progress,
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// Returns a predicate for [Migration::new] which holds for the keys whose
+/// [position] on the ring is in `range`.
+pub fn in_range(range: HashRange) -> impl Fn(&str) -> bool + Send + Sync {
+    move |key| range.contains(position(key))
+}
+
+/// The keys touched by the changes seen in the watch of the source
+#[derive(Default)]
+struct Tail {
+    /// keys to copy again, with whether each is a list
+    touched: BTreeSet<(String, bool)>,
+    /// the sequence number of the first change seen
+    first: Option<u64>,
+    /// the sequence number of the last change seen
+    last: u64,
+}
+
+/// Copies the values and lists of a key range from one storage to another,
+/// see the module documentation.
+pub struct Migration<'a> {
+    name: String,
+    source: &'a dyn Storage,
+    dest: &'a dyn Storage,
+    wanted: Box<dyn Fn(&str) -> bool + Send + Sync + 'a>,
+}
+
+impl<'a> Migration<'a> {
+    /// Creates a migration named `name` of the keys of `source` for which
+    /// `wanted` holds, to `dest`. Runs under the same name resume each
+    /// other.
+    pub fn new(
+        name: &str,
+        source: &'a dyn Storage,
+        dest: &'a dyn Storage,
+        wanted: impl Fn(&str) -> bool + Send + Sync + 'a,
+    ) -> Migration<'a> {
+        Migration {
+            name: name.to_string(),
+            source,
+            dest,
+            wanted: Box::new(wanted),
+        }
+    }
+
+    fn marker(&self) -> String {
+        format!("{}{}", MARKER_PREFIX, self.name)
+    }
+
+    fn wanted(&self, key: &str) -> bool {
+        !key.starts_with(MARKER_PREFIX) && (self.wanted)(key)
+    }
+
+    /// Copies the range, resuming from the checkpoint saved in
+    /// `checkpoints` if any, then replays the changes made to the source
+    /// meanwhile until they settle, see the module documentation. Writes
+    /// still going to the source afterwards are not copied: run it again
+    /// once they stopped, to copy those made in between.
+    pub async fn run<C: Checkpoints + ?Sized>(&self, checkpoints: &C) -> TribResult<Stats> {
+        let start = Instant::now();
+        let mut stats = Stats::default();
+        let mut cp = checkpoints.load(&self.name).await?.unwrap_or_default();
+        let all = Pattern::default();
+        let mut changes = match self.source.watch(&all, cp.seq).await {
+            Ok(changes) => changes,
+            Err(e) if cp.seq > 0 => {
+                warn!(
+                    "migration {}: cannot replay changes since {}, starting over: {}",
+                    self.name, cp.seq, e
+                );
+                cp = Checkpoint::default();
+                self.source.watch(&all, 0).await?
+            }
+            Err(e) => return Err(e),
+        };
+        let mut tail = Tail {
+            last: cp.seq,
+            ..Default::default()
+        };
+        if cp.seq == 0 {
+            // nothing copied can be trusted without a place in the watch
+            cp = Checkpoint::default();
+            self.place(&mut changes, &mut tail).await?;
+            cp.seq = tail.first.unwrap_or(1) - 1;
+            checkpoints.save(&self.name, Some(&cp)).await?;
+        }
+
+        if cp.phase == Phase::Values {
+            let mut keys = self.source.keys(&all).await?.0;
+            keys.sort();
+            for key in keys {
+                if key <= cp.after || !self.wanted(&key) {
+                    continue;
+                }
+                self.copy_value(&key).await?;
+                stats.values += 1;
+                cp.after = key;
+                self.drain(&mut changes, &mut tail).await?;
+                if stats.values % CHECKPOINT_EVERY == 0 {
+                    self.save(checkpoints, &cp, &stats, start).await?;
+                }
+            }
+            cp.phase = Phase::Lists;
+            cp.after = String::new();
+            self.save(checkpoints, &cp, &stats, start).await?;
+        }
+        if cp.phase == Phase::Lists {
+            let mut keys = self.source.list_keys(&all).await?.0;
+            keys.sort();
+            for key in keys {
+                if key <= cp.after || !self.wanted(&key) {
+                    continue;
+                }
+                stats.items += self.copy_list(&key).await?;
+                stats.lists += 1;
+                cp.after = key;
+                self.drain(&mut changes, &mut tail).await?;
+                if stats.lists % CHECKPOINT_EVERY == 0 {
+                    self.save(checkpoints, &cp, &stats, start).await?;
+                }
+            }
+            cp.phase = Phase::Tail;
+            cp.after = String::new();
+            self.save(checkpoints, &cp, &stats, start).await?;
+        }
+
+        for _ in 0..MAX_TAIL_ROUNDS {
+            self.drain(&mut changes, &mut tail).await?;
+            if tail.touched.is_empty() && !self.wait(&mut changes, &mut tail).await? {
+                break;
+            }
+            let touched = std::mem::take(&mut tail.touched);
+            let settled = touched.len() <= TAIL_SETTLED;
+            for (key, list) in touched {
+                match list {
+                    true => stats.items += self.copy_list(&key).await?,
+                    false => self.copy_value(&key).await?,
+                }
+                stats.replayed += 1;
+            }
+            cp.seq = tail.last;
+            self.save(checkpoints, &cp, &stats, start).await?;
+            if settled {
+                break;
+            }
+        }
+        // the changes to other keys seen while waiting need not be replayed
+        // by the next run either
+        if cp.seq < tail.last {
+            cp.seq = tail.last;
+            self.save(checkpoints, &cp, &stats, start).await?;
+        }
+        stats.elapsed = start.elapsed();
+        info!(
+            "migration {}: copied {} values, {} lists and replayed {} keys in {:?}, {:.0} keys/s",
+            self.name,
+            stats.values,
+            stats.lists,
+            stats.replayed,
+            stats.elapsed,
+            stats.keys_per_sec()
+        );
+        Ok(stats)
+    }
+
+    /// Removes the checkpoint of the migration, and what it wrote to the
+    /// source, once it needs not be resumed.
+    pub async fn finish<C: Checkpoints + ?Sized>(&self, checkpoints: &C) -> TribResult<()> {
+        checkpoints.save(&self.name, None).await?;
+        self.source.set(&KeyValue::new(&self.marker(), "")).await?;
+        Ok(())
+    }
+
+    async fn save<C: Checkpoints + ?Sized>(
+        &self,
+        checkpoints: &C,
+        cp: &Checkpoint,
+        stats: &Stats,
+        start: Instant,
+    ) -> TribResult<()> {
+        checkpoints.save(&self.name, Some(cp)).await?;
+        let stats = Stats {
+            elapsed: start.elapsed(),
+            ..*stats
+        };
+        info!(
+            "migration {}: {:?} after {:?}, {:.0} keys/s",
+            self.name,
+            cp.phase,
+            cp.after,
+            stats.keys_per_sec()
+        );
+        Ok(())
+    }
+
+    /// Writes to the marker key of the source, and waits for the write to
+    /// show up in `changes`, so that the first change seen is known.
+    async fn place(&self, changes: &mut ChangeStream, tail: &mut Tail) -> TribResult<()> {
+        let marker = self.marker();
+        let clock = self.source.clock(0).await?;
+        self.source
+            .set(&KeyValue::new(&marker, &clock.to_string()))
+            .await?;
+        let deadline = Instant::now() + MARKER_TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let change = match tokio::time::timeout(left, changes.next()).await {
+                // the first change seen is not known yet
+                Ok(Some(change)) => change?,
+                _ => {
+                    return Err(Box::new(TribblerError::Unknown(format!(
+                        "migration {}: the watch of the source did not see {}",
+                        self.name, marker
+                    ))))
+                }
+            };
+            let seen = change.key == marker;
+            self.record(changes, Some(Ok(change)), tail).await?;
+            if seen {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Records `change`, the next item of `changes`. Should the watch have
+    /// fallen behind, watches the source again from the last change seen.
+    async fn record(
+        &self,
+        changes: &mut ChangeStream,
+        change: Option<TribResult<Change>>,
+        tail: &mut Tail,
+    ) -> TribResult<()> {
+        let change = match change {
+            Some(Ok(change)) => change,
+            Some(Err(e)) if tail.last > 0 => {
+                warn!(
+                    "migration {}: watching again from {}: {}",
+                    self.name, tail.last, e
+                );
+                *changes = self.source.watch(&Pattern::default(), tail.last).await?;
+                return Ok(());
+            }
+            Some(Err(e)) => return Err(e),
+            None => {
+                return Err(Box::new(TribblerError::Unknown(format!(
+                    "migration {}: the watch of the source ended",
+                    self.name
+                ))))
+            }
+        };
+        tail.first.get_or_insert(change.seq);
+        tail.last = change.seq;
+        if self.wanted(&change.key) {
+            let list = change.kind != ChangeKind::Set;
+            tail.touched.insert((change.key, list));
+        }
+        Ok(())
+    }
+
+    /// Waits up to [TAIL_IDLE] for a change to a migrated key, recording the
+    /// changes seen meanwhile, and returns whether one came.
+    async fn wait(&self, changes: &mut ChangeStream, tail: &mut Tail) -> TribResult<bool> {
+        let deadline = Instant::now() + TAIL_IDLE;
+        while tail.touched.is_empty() {
+            // changes to other keys may keep coming, so the deadline is
+            // checked before the stream is polled again
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(false);
+            }
+            match tokio::time::timeout(left, changes.next()).await {
+                Err(_) => return Ok(false),
+                Ok(change) => self.record(changes, change, tail).await?,
+            }
+        }
+        Ok(true)
+    }
+
+    /// Records the changes already delivered by `changes`, without waiting.
+    async fn drain(&self, changes: &mut ChangeStream, tail: &mut Tail) -> TribResult<()> {
+        while let Some(change) = changes.next().now_or_never() {
+            self.record(changes, change, tail).await?;
+        }
+        Ok(())
+    }
+
+    async fn copy_value(&self, key: &str) -> TribResult<()> {
+        let value = self.source.get(key).await?.unwrap_or_default();
+        self.dest.set(&KeyValue::new(key, &value)).await?;
+        Ok(())
+    }
+
+    /// Makes the list under `key` in the destination the same as in the
+    /// source, and returns its length.
+    async fn copy_list(&self, key: &str) -> TribResult<u64> {
+        let source = self.source.list_get(key).await?.0;
+        let mut dest = self.dest.list_get(key).await?.0;
+        // a removal drops every copy of a value, so values are removed until
+        // what is left of the destination's list starts the source's
+        let mut stale = BTreeSet::new();
+        loop {
+            let same = dest.iter().zip(source.iter()).take_while(|(d, s)| d == s);
+            let kept = same.count();
+            if kept == dest.len() {
+                break;
+            }
+            let value = dest[kept].clone();
+            dest.retain(|v| *v != value);
+            stale.insert(value);
+        }
+        for value in stale {
+            self.dest.list_remove(&KeyValue::new(key, &value)).await?;
+        }
+        for value in source[dest.len()..].iter() {
+            self.dest.list_append(&KeyValue::new(key, value)).await?;
+        }
+        Ok(source.len() as u64)
+    }
+}
@@ -84,6 +84,53 @@
 //! trivially satisfy this requirement. The keeper's job comes in when there are
 //! multiple back-ends to keep in sync.
 //!
+//! When [Config::hybrid_clock](tribbler::config::Config) is set, the back-ends
+//! run hybrid logical clocks (see [tribbler::hlc]) whose values also track
+//! wall-clock time, and the keeper's sync should never move them below
+//! [tribbler::hlc::now]: sync them to [tribbler::hlc::sync_floor] of the
+//! largest clock seen rather than to the clock itself. The same "no smaller than" guarantee still holds;
+//! clock values just become meaningful as timestamps too.
+//!
+//! When [Config::tls](tribbler::config::Config) is set, the back-ends only
+//! accept TLS connections, and [new_bin_client_with] should be used to reach
+//! them; see [tribbler::config::TlsConfig] for the settings, including mutual
+//! TLS. Likewise, when [Config::auth](tribbler::config::Config::auth) returns
+//! settings, the back-ends only answer calls carrying a known token (see
+//! [tribbler::auth]), and [Config::compression](tribbler::config::Config)
+//! turns on gzip. [new_bin_client_with] takes care of all three when given
+//! [Config::client_config](tribbler::config::Config::client_config).
+//!
+//! When [Config::quorum](tribbler::config::Config) is set, every bin is kept on
+//! several back-ends instead of one, and [new_quorum_bin_client] reads and
+//! writes it by quorum, so the bins stay available while a minority of their
+//! back-ends is down; see [quorum::QuorumClient]. The keepers then also compare
+//! the replicas of every bin in the background and copy over what differs,
+//! see [anti_entropy::AntiEntropy].
+//!
+//! The keepers also replicate what they know of the bin storage, such as
+//! which back-ends are alive, migration progress and clock watermarks, in a
+//! Raft log (see [raft]), so that it survives keepers failing over. A keeper
+//! persists its part of the log in
+//! [KeeperConfig::state](tribbler::config::KeeperConfig) when set, and takes
+//! up from it when restarted. [raft_sim] runs a cluster of Raft nodes
+//! deterministically, to test partitions and crashes.
+//!
+//! Rather than probing the back-ends with their own loop, keepers can use
+//! [membership::FailureDetector], which reports back-ends joining and
+//! leaving on a channel. [serve_keeper] uses it to keep a versioned view of
+//! the back-ends alive in the log, and publishes that view in bin
+//! [membership::MEMBERSHIP_BIN], where bin clients can read it.
+//!
+//! When back-ends come and go, the keys they should hold have to be copied
+//! from those which held them. [migration::Migration] copies a key range from
+//! one storage to another while clients keep writing, and saves its progress
+//! so that another keeper can resume it.
+//!
+//! To have concurrent writers converge without coordination, a storage can
+//! be wrapped in a [log_storage::LogStorage], which keeps every value and
+//! list as a log of clock-stamped writes, and compacts the logs in the
+//! background.
+//!
 //! As mentioned, we already implemented the back-end for Lab 1, and the
 //! key-value store API will not change. Both the bin storage client and the
 //! keeper will communicate with the "dumb" back-ends via the RPC calls we
@@ -589,7 +636,18 @@
 //!
 //! ## Happy Lab 2!
 //!
+pub mod anti_entropy;
+pub mod bin_client;
+pub mod election;
 mod lab;
+pub mod log_storage;
+pub mod membership;
+pub mod migration;
+pub mod quorum;
+pub mod raft;
+pub mod raft_sim;
 pub use crate::lab2::lab::new_bin_client;
+pub use crate::lab2::lab::new_bin_client_with;
 pub use crate::lab2::lab::new_front;
+pub use crate::lab2::lab::new_quorum_bin_client;
 pub use crate::lab2::lab::serve_keeper;
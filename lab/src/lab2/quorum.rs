@@ -0,0 +1,807 @@
+//! the replicated [BinStorage] client returned by
+//! [crate::lab2::new_quorum_bin_client]
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tribbler::{
+    config::QuorumConfig,
+    err::{ErrorCode, TribResult, TribblerError},
+    namespace::Bin,
+    ring::{Ring, DEFAULT_VNODES},
+    storage::{BinStorage, KeyList, KeyString, KeyValue, List, Op, OpResult, Pattern, Storage},
+};
+
+use crate::lab1::{builder::ClientBuilder, client::StorageClient};
+
+/// name of the bin in which a back-end holds the writes it keeps for
+/// replicas which could not be reached, under the address of the replica.
+/// No user bin may have this name.
+pub const HINTS_BIN: &str = "@hints";
+
+/// how often [crate::lab2::new_quorum_bin_client] tries to hand hinted
+/// writes off to their replicas
+pub const HANDOFF_INTERVAL: Duration = Duration::from_secs(1);
+
+/// number of hex digits of an encoded [Version]
+const VERSION_LEN: usize = 24;
+
+/// The version of a value or list entry: the clock of the write which made
+/// it, with the id of the writing client to break ties. Newer writes win.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(super) struct Version {
+    clock: u64,
+    writer: u32,
+}
+
+impl Version {
+    pub(super) fn new(clock: u64, writer: u32) -> Version {
+        Version { clock, writer }
+    }
+
+    pub(super) fn encode(&self) -> String {
+        format!("{:016x}{:08x}", self.clock, self.writer)
+    }
+
+    pub(super) fn decode(s: &str) -> Option<Version> {
+        if s.len() != VERSION_LEN || !s.is_ascii() {
+            return None;
+        }
+        Some(Version {
+            clock: u64::from_str_radix(&s[..16], 16).ok()?,
+            writer: u32::from_str_radix(&s[16..], 16).ok()?,
+        })
+    }
+}
+
+/// Stores `value` with its version, as `<version>:<value>`.
+pub(super) fn stamp(version: Version, value: &str) -> String {
+    format!("{}:{}", version.encode(), value)
+}
+
+/// Splits a stored value into its version and the value itself. Values
+/// stored without a version, e.g. before replication was turned on, are
+/// older than any versioned one.
+pub(super) fn unstamp(stored: &str) -> (Version, &str) {
+    let parsed = stored
+        .get(..VERSION_LEN)
+        .and_then(Version::decode)
+        .zip(stored.get(VERSION_LEN..).and_then(|s| s.strip_prefix(':')));
+    parsed.unwrap_or((Version::default(), stored))
+}
+
+/// Works out the list made of `entries`, the union of the stored entries of
+/// every replica which answered. Appended elements are stored as
+/// `<version>:+<value>`, and removals as `<version>:-<version removed>`, so
+/// an element removed on some replicas does not come back from the others.
+pub(super) fn live(entries: &BTreeSet<String>) -> Vec<(Version, String)> {
+    let mut items = vec![];
+    let mut removed = BTreeSet::new();
+    for e in entries {
+        match unstamp(e) {
+            (v, s) if s.starts_with('+') => items.push((v, s[1..].to_string())),
+            (_, s) if s.starts_with('-') => {
+                if let Some(v) = Version::decode(&s[1..]) {
+                    removed.insert(v);
+                }
+            }
+            // not written by a quorum client
+            (v, s) => items.push((v, s.to_string())),
+        }
+    }
+    items.retain(|(v, _)| !removed.contains(v));
+    items.sort();
+    items
+}
+
+pub(super) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// A write kept by a back-end for a replica it could not be delivered to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum Hint {
+    /// a stamped value to set
+    Set {
+        bin: String,
+        key: String,
+        value: String,
+        /// when the value expires, in milliseconds since the Unix epoch
+        expires: Option<u64>,
+    },
+    /// a stamped entry to append to a list
+    Append {
+        bin: String,
+        key: String,
+        entry: String,
+        expires: Option<u64>,
+    },
+}
+
+/// Sets `key` to the stamped `value` on `store`, unless it already holds a
+/// value at least as new. Of two values with the same version, the greater
+/// one wins, so that replicas agree.
+pub(super) async fn put<S: Storage>(store: &S, key: &str, value: &str) -> TribResult<()> {
+    let mut current = store.get(key).await?;
+    loop {
+        let old = current.unwrap_or_default();
+        if !old.is_empty() && unstamp(&old) >= unstamp(value) {
+            return Ok(());
+        }
+        let (swapped, now) = store.cas(&KeyValue::new(key, value), &old).await?;
+        if swapped {
+            return Ok(());
+        }
+        current = now;
+    }
+}
+
+impl Hint {
+    /// Delivers the write to `back`, and returns whether it did. Writes which
+    /// have expired meanwhile are dropped.
+    async fn apply(&self, back: &StorageClient) -> TribResult<bool> {
+        let (bin, key, expires) = match self {
+            Hint::Set {
+                bin, key, expires, ..
+            }
+            | Hint::Append {
+                bin, key, expires, ..
+            } => (bin, key, *expires),
+        };
+        let ttl = match expires {
+            Some(at) if at <= now_millis() => return Ok(false),
+            Some(at) => Some(Duration::from_millis(at - now_millis())),
+            None => None,
+        };
+        let store = Bin::new(bin, back.clone());
+        match (self, ttl) {
+            (Hint::Set { value, .. }, None) => put(&store, key, value).await?,
+            (Hint::Set { value, .. }, Some(ttl)) => {
+                store.set_ttl(&KeyValue::new(key, value), ttl).await?;
+            }
+            (Hint::Append { entry, .. }, None) => {
+                store.list_append(&KeyValue::new(key, entry)).await?;
+            }
+            (Hint::Append { entry, .. }, Some(ttl)) => {
+                store
+                    .list_append_ttl(&KeyValue::new(key, entry), ttl)
+                    .await?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// state shared by a [QuorumClient] and all of its bins
+struct Shared {
+    ring: Ring,
+    backs: Vec<StorageClient>,
+    quorum: QuorumConfig,
+    /// id of this client, which orders writes made at the same clock
+    writer: u32,
+    /// the largest clock value handed out so far
+    clock: AtomicU64,
+}
+
+/// Stores every bin on [QuorumConfig::n] back-ends, the first distinct ones
+/// on a consistent-hash [Ring] from the bin's name, and reads and writes it
+/// by quorum.
+///
+/// Every value and list entry is stored with the version of the write which
+/// made it, and the newest version wins:
+///
+/// - a read asks every replica, returns once [QuorumConfig::r] answered, and
+///   repairs the replicas which returned an older version or missed list
+///   entries (read repair);
+/// - a write goes to every replica and returns once [QuorumConfig::w] of
+///   them took it; the rest still get it in the background. A write for a
+///   replica which cannot be reached is kept by the next back-end on the
+///   ring instead, and counts towards the quorum; [QuorumClient::handoff]
+///   delivers it once the replica is back (hinted handoff).
+///
+/// [KeyString::cas] and [KeyString::incr] read, then write by quorum, so
+/// unlike on a single back-end, two clients racing on the same key may both
+/// succeed. Values set with a TTL overwrite the replica's value whatever its
+/// version.
+pub struct QuorumClient {
+    shared: Arc<Shared>,
+}
+
+impl QuorumClient {
+    /// Creates a client for the back-ends at `backs` (`host:port`), which
+    /// connects to them as set up by `builder`.
+    pub fn new(
+        backs: &[String],
+        builder: &ClientBuilder,
+        quorum: QuorumConfig,
+    ) -> TribResult<QuorumClient> {
+        quorum.validate(backs.len())?;
+        let clients = backs
+            .iter()
+            .map(|addr| builder.build(&format!("http://{}", addr)))
+            .collect::<TribResult<Vec<StorageClient>>>()?;
+        Ok(QuorumClient {
+            shared: Arc::new(Shared {
+                ring: Ring::new(backs, DEFAULT_VNODES),
+                backs: clients,
+                quorum,
+                writer: rand::random(),
+                clock: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    /// Delivers the writes back-ends keep for replicas which could not be
+    /// reached, to every such replica which can be reached now, and returns
+    /// the number of writes delivered. A back-end whose hints cannot be read
+    /// or cleared is skipped and tried again on the next pass.
+    pub async fn handoff(&self) -> TribResult<usize> {
+        let shared = &self.shared;
+        let mut delivered = 0;
+        'holders: for holder in shared.backs.iter() {
+            let hints = Bin::new(HINTS_BIN, holder.clone());
+            // the holder itself may be down, in which case it is tried again
+            // on the next pass
+            let targets = match hints.list_keys(&Pattern::default()).await {
+                Ok(targets) => targets.0,
+                Err(_) => continue,
+            };
+            for target in targets {
+                let back = match shared.ring.nodes().iter().position(|a| *a == target) {
+                    Some(i) => &shared.backs[i],
+                    None => continue,
+                };
+                let raws = match hints.list_get(&target).await {
+                    Ok(raws) => raws.0,
+                    Err(_) => continue 'holders,
+                };
+                for raw in raws {
+                    match serde_json::from_str::<Hint>(&raw) {
+                        Ok(hint) => match hint.apply(back).await {
+                            Ok(true) => delivered += 1,
+                            Ok(false) => (),
+                            // still unreachable
+                            Err(_) => break,
+                        },
+                        Err(e) => warn!("dropping malformed hint {:?}: {}", raw, e),
+                    }
+                    if hints
+                        .list_remove(&KeyValue::new(&target, &raw))
+                        .await
+                        .is_err()
+                    {
+                        continue 'holders;
+                    }
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Calls [QuorumClient::handoff] every [HANDOFF_INTERVAL] for as long as
+    /// the client lives.
+    pub(crate) fn spawn_handoff(&self) {
+        let shared: Weak<Shared> = Arc::downgrade(&self.shared);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HANDOFF_INTERVAL);
+            loop {
+                interval.tick().await;
+                let client = match shared.upgrade() {
+                    Some(shared) => QuorumClient { shared },
+                    None => return,
+                };
+                if let Err(e) = client.handoff().await {
+                    warn!("hinted handoff failed: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl BinStorage for QuorumClient {
+    async fn bin(&self, name: &str) -> TribResult<Box<dyn Storage>> {
+        if name == HINTS_BIN {
+            return Err(Box::new(TribblerError::Status(
+                ErrorCode::Invalid,
+                format!("bin name {:?} is reserved", name),
+            )));
+        }
+        let replicas = self.shared.ring.successors(name, self.shared.backs.len());
+        Ok(Box::new(QuorumBin {
+            name: name.to_string(),
+            shared: self.shared.clone(),
+            replicas,
+        }))
+    }
+}
+
+/// A single bin of a [QuorumClient]
+#[derive(Clone)]
+struct QuorumBin {
+    name: String,
+    shared: Arc<Shared>,
+    /// every back-end, in the order the bin's ring walk meets them. The first
+    /// [QuorumConfig::n] hold the bin, the others keep hints for them.
+    replicas: Vec<usize>,
+}
+
+type Replica = Bin<StorageClient>;
+
+impl QuorumBin {
+    fn replica(&self, i: usize) -> Replica {
+        Bin::new(&self.name, self.shared.backs[i].clone())
+    }
+
+    fn preferred(&self) -> &[usize] {
+        &self.replicas[..self.shared.quorum.n]
+    }
+
+    /// Fails a call which only `got` of the `needed` replicas answered,
+    /// with the error of the last replica which did not if it was refused:
+    /// refused calls fail the same way on every replica.
+    fn missed(
+        &self,
+        op: &str,
+        got: usize,
+        needed: usize,
+        last: Option<Box<dyn Error + Send + Sync>>,
+    ) -> TribResult<()> {
+        match last {
+            Some(e) if ErrorCode::of(&*e) == ErrorCode::Invalid => Err(e),
+            last => Err(Box::new(TribblerError::Status(
+                ErrorCode::Unavailable,
+                format!(
+                    "{} of bin {:?} reached {} of {} replicas, {} needed; last error: {}",
+                    op,
+                    self.name,
+                    got,
+                    self.shared.quorum.n,
+                    needed,
+                    last.map(|e| e.to_string()).unwrap_or_default()
+                ),
+            ))),
+        }
+    }
+
+    /// Calls `f` on every replica and returns the answers, along with the
+    /// replica each came from, as soon as [QuorumConfig::r] answered.
+    /// Replicas which fail count as missing.
+    async fn read<T, F, Fut>(&self, f: F) -> TribResult<Vec<(usize, T)>>
+    where
+        F: Fn(Replica) -> Fut,
+        Fut: Future<Output = TribResult<T>>,
+    {
+        let mut calls = self
+            .preferred()
+            .iter()
+            .map(|&i| {
+                let call = f(self.replica(i));
+                async move { (i, call.await) }
+            })
+            .collect::<FuturesUnordered<_>>();
+        let mut answers = vec![];
+        let mut last = None;
+        while answers.len() < self.shared.quorum.r {
+            match calls.next().await {
+                Some((i, Ok(v))) => answers.push((i, v)),
+                Some((_, Err(e))) => last = Some(e),
+                None => break,
+            }
+        }
+        if answers.len() < self.shared.quorum.r {
+            self.missed("read", answers.len(), self.shared.quorum.r, last)?;
+        }
+        Ok(answers)
+    }
+
+    /// Makes a write on every replica with `f`. The write for a replica
+    /// which cannot be reached is handed as `hint` to the next back-end
+    /// which can. Returns as soon as [QuorumConfig::w] replicas, or
+    /// back-ends standing in for them, took the write, and fails if fewer
+    /// did; replicas which fail count as missing. The write goes on in the
+    /// background for the replicas which did not answer yet.
+    async fn write<F, Fut>(&self, f: F, hint: Hint) -> TribResult<()>
+    where
+        F: Fn(Replica) -> Fut,
+        Fut: Future<Output = TribResult<()>> + Send + 'static,
+    {
+        let mut calls = self
+            .preferred()
+            .iter()
+            .map(|&i| {
+                let call = f(self.replica(i));
+                let (bin, hint) = (self.clone(), hint.clone());
+                async move {
+                    match call.await {
+                        Err(e) if ErrorCode::of(&*e) == ErrorCode::Unavailable => {
+                            match bin.hand(i, &hint).await {
+                                true => Ok(()),
+                                false => Err(e),
+                            }
+                        }
+                        r => r,
+                    }
+                }
+            })
+            .collect::<FuturesUnordered<_>>();
+        let mut acks = 0;
+        let mut last = None;
+        while acks < self.shared.quorum.w {
+            match calls.next().await {
+                Some(Ok(())) => acks += 1,
+                Some(Err(e)) => last = Some(e),
+                None => break,
+            }
+        }
+        if acks < self.shared.quorum.w {
+            self.missed("write", acks, self.shared.quorum.w, last)?;
+        }
+        if !calls.is_empty() {
+            tokio::spawn(async move { while calls.next().await.is_some() {} });
+        }
+        Ok(())
+    }
+
+    /// Leaves `hint` for replica `target` with the first back-end after the
+    /// bin's replicas which takes it, and returns whether one did.
+    async fn hand(&self, target: usize, hint: &Hint) -> bool {
+        let hint = match serde_json::to_string(hint) {
+            Ok(hint) => hint,
+            Err(_) => return false,
+        };
+        let kv = KeyValue::new(&self.shared.ring.nodes()[target], &hint);
+        for &i in &self.replicas[self.shared.quorum.n..] {
+            let holder = Bin::new(HINTS_BIN, self.shared.backs[i].clone());
+            if holder.list_append(&kv).await.is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// a version newer than every version this client has seen
+    async fn version(&self) -> TribResult<Version> {
+        Ok(Version {
+            clock: self.clock(0).await?,
+            writer: self.shared.writer,
+        })
+    }
+
+    /// Reads `key` by quorum, repairing stale replicas, and returns its
+    /// stamped value.
+    async fn newest(&self, key: &str) -> TribResult<Option<String>> {
+        let answers = self.read(|r| async move { r.get(key).await }).await?;
+        let newest = answers
+            .iter()
+            .filter_map(|(_, v)| v.as_ref())
+            .max_by_key(|v| unstamp(v))
+            .cloned();
+        if let Some(newest) = &newest {
+            let stale = answers.iter().filter(|(_, v)| v.as_ref() != Some(newest));
+            for (i, _) in stale {
+                if let Err(e) = put(&self.replica(*i), key, newest).await {
+                    warn!("read repair of {:?} failed: {}", key, e);
+                }
+            }
+        }
+        Ok(newest)
+    }
+
+    /// Sets `key` to `value` by quorum, with a TTL if `ttl` is set.
+    async fn put(&self, key: &str, value: &str, ttl: Option<Duration>) -> TribResult<()> {
+        let stamped = stamp(self.version().await?, value);
+        let hint = Hint::Set {
+            bin: self.name.clone(),
+            key: key.to_string(),
+            value: stamped.clone(),
+            expires: ttl.map(|t| now_millis() + t.as_millis() as u64),
+        };
+        let kv = KeyValue::new(key, &stamped);
+        self.write(
+            |r| {
+                let kv = kv.clone();
+                async move {
+                    match ttl {
+                        Some(ttl) => {
+                            r.set_ttl(&kv, ttl).await?;
+                            Ok(())
+                        }
+                        None => put(&r, &kv.key, &kv.value).await,
+                    }
+                }
+            },
+            hint,
+        )
+        .await
+    }
+
+    /// Reads list `key` by quorum, repairing replicas which miss entries,
+    /// and returns the elements with their versions.
+    async fn entries(&self, key: &str) -> TribResult<Vec<(Version, String)>> {
+        let answers = self.read(|r| async move { r.list_get(key).await }).await?;
+        let all = answers
+            .iter()
+            .flat_map(|(_, l)| l.0.iter().cloned())
+            .collect::<BTreeSet<String>>();
+        for (i, l) in answers.iter() {
+            let have = l.0.iter().collect::<BTreeSet<_>>();
+            for e in all.iter().filter(|e| !have.contains(e)) {
+                if let Err(e) = self.replica(*i).list_append(&KeyValue::new(key, e)).await {
+                    warn!("read repair of list {:?} failed: {}", key, e);
+                    break;
+                }
+            }
+        }
+        Ok(live(&all))
+    }
+
+    /// Appends the stamped `entry` to list `key` by quorum.
+    async fn append(&self, key: &str, entry: String, ttl: Option<Duration>) -> TribResult<()> {
+        let hint = Hint::Append {
+            bin: self.name.clone(),
+            key: key.to_string(),
+            entry: entry.clone(),
+            expires: ttl.map(|t| now_millis() + t.as_millis() as u64),
+        };
+        let kv = KeyValue::new(key, &entry);
+        self.write(
+            |r| {
+                let kv = kv.clone();
+                async move {
+                    match ttl {
+                        Some(ttl) => r.list_append_ttl(&kv, ttl).await?,
+                        None => r.list_append(&kv).await?,
+                    };
+                    Ok(())
+                }
+            },
+            hint,
+        )
+        .await
+    }
+
+    /// Lists the keys matching `p` on a quorum of replicas, with the values
+    /// fetched by `op` for each key on each replica.
+    async fn scan_keys<F>(
+        &self,
+        p: &Pattern,
+        list: bool,
+        op: F,
+    ) -> TribResult<HashMap<String, Vec<OpResult>>>
+    where
+        F: Fn(String) -> Op,
+    {
+        let op = &op;
+        let answers = self
+            .read(|r| async move {
+                let keys = match list {
+                    true => r.list_keys(p).await?.0,
+                    false => r.keys(p).await?.0,
+                };
+                let ops = keys.iter().cloned().map(op).collect::<Vec<_>>();
+                let values = r.batch(&ops).await?;
+                TribResult::Ok(keys.into_iter().zip(values).collect::<Vec<_>>())
+            })
+            .await?;
+        let mut found: HashMap<String, Vec<OpResult>> = HashMap::new();
+        for (key, value) in answers.into_iter().flat_map(|(_, kvs)| kvs) {
+            found.entry(key).or_default().push(value);
+        }
+        Ok(found)
+    }
+}
+
+#[async_trait]
+impl KeyString for QuorumBin {
+    async fn get(&self, key: &str) -> TribResult<Option<String>> {
+        Ok(self
+            .newest(key)
+            .await?
+            .map(|v| unstamp(&v).1.to_string())
+            .filter(|v| !v.is_empty()))
+    }
+
+    async fn set(&self, kv: &KeyValue) -> TribResult<bool> {
+        self.put(&kv.key, &kv.value, None).await?;
+        Ok(true)
+    }
+
+    async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        self.put(&kv.key, &kv.value, Some(ttl)).await?;
+        Ok(true)
+    }
+
+    async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)> {
+        let current = self.get(&kv.key).await?;
+        if current.as_deref().unwrap_or("") != old {
+            return Ok((false, current));
+        }
+        self.put(&kv.key, &kv.value, None).await?;
+        Ok((true, Some(kv.value.clone()).filter(|v| !v.is_empty())))
+    }
+
+    async fn incr(&self, key: &str, delta: i64) -> TribResult<i64> {
+        let current = match self.get(key).await? {
+            Some(v) => v.parse::<i64>().map_err(|_| {
+                TribblerError::Status(
+                    ErrorCode::Invalid,
+                    format!("value of \"{}\" is not an integer", key),
+                )
+            })?,
+            None => 0,
+        };
+        let value = current.checked_add(delta).ok_or_else(|| {
+            TribblerError::Status(
+                ErrorCode::Invalid,
+                format!("incrementing \"{}\" overflows", key),
+            )
+        })?;
+        self.put(key, &value.to_string(), None).await?;
+        Ok(value)
+    }
+
+    async fn keys(&self, p: &Pattern) -> TribResult<List> {
+        let found = self.scan_keys(p, false, Op::Get).await?;
+        let mut keys = found
+            .into_iter()
+            .filter(|(_, values)| {
+                let newest = values
+                    .iter()
+                    .filter_map(|v| match v {
+                        OpResult::Value(Some(v)) => Some(unstamp(v)),
+                        _ => None,
+                    })
+                    .max();
+                matches!(newest, Some((_, v)) if !v.is_empty())
+            })
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>();
+        keys.sort();
+        Ok(List(keys))
+    }
+}
+
+#[async_trait]
+impl KeyList for QuorumBin {
+    async fn list_get(&self, key: &str) -> TribResult<List> {
+        Ok(List(
+            self.entries(key)
+                .await?
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect(),
+        ))
+    }
+
+    async fn list_append(&self, kv: &KeyValue) -> TribResult<bool> {
+        let entry = stamp(self.version().await?, &format!("+{}", kv.value));
+        self.append(&kv.key, entry, None).await?;
+        Ok(true)
+    }
+
+    async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        let entry = stamp(self.version().await?, &format!("+{}", kv.value));
+        self.append(&kv.key, entry, Some(ttl)).await?;
+        Ok(true)
+    }
+
+    async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32> {
+        let removed = self
+            .entries(&kv.key)
+            .await?
+            .into_iter()
+            .filter(|(_, v)| *v == kv.value)
+            .map(|(version, _)| version)
+            .collect::<Vec<_>>();
+        for version in removed.iter() {
+            let entry = stamp(self.version().await?, &format!("-{}", version.encode()));
+            self.append(&kv.key, entry, None).await?;
+        }
+        Ok(removed.len() as u32)
+    }
+
+    async fn list_keys(&self, p: &Pattern) -> TribResult<List> {
+        let found = self.scan_keys(p, true, Op::ListGet).await?;
+        let mut keys = found
+            .into_iter()
+            .filter(|(_, lists)| {
+                let all = lists
+                    .iter()
+                    .flat_map(|l| match l {
+                        OpResult::List(l) => l.0.clone(),
+                        _ => vec![],
+                    })
+                    .collect::<BTreeSet<_>>();
+                !live(&all).is_empty()
+            })
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>();
+        keys.sort();
+        Ok(List(keys))
+    }
+}
+
+#[async_trait]
+impl Storage for QuorumBin {
+    async fn clock(&self, at_least: u64) -> TribResult<u64> {
+        // every answer exceeds what this client handed out before, so the
+        // clock keeps moving forward while replicas come and go
+        let at_least = at_least.max(self.shared.clock.load(Ordering::SeqCst).saturating_add(1));
+        let answers = self
+            .read(|r| async move { r.clock(at_least).await })
+            .await?;
+        let clock = answers
+            .into_iter()
+            .map(|(_, c)| c)
+            .max()
+            .unwrap_or(at_least);
+        self.shared.clock.fetch_max(clock, Ordering::SeqCst);
+        Ok(clock)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::{live, stamp, unstamp, Version};
+
+    #[test]
+    fn quorum_stamps() {
+        let v = Version {
+            clock: 42,
+            writer: 7,
+        };
+        let s = stamp(v, "a:b");
+        assert_eq!((v, "a:b"), unstamp(&s));
+        assert_eq!((Version::default(), "plain"), unstamp("plain"));
+        assert!(
+            unstamp(&stamp(v, ""))
+                < unstamp(&stamp(
+                    Version {
+                        clock: 43,
+                        writer: 0
+                    },
+                    ""
+                ))
+        );
+
+        let a = Version {
+            clock: 1,
+            writer: 1,
+        };
+        let b = Version {
+            clock: 2,
+            writer: 1,
+        };
+        let entries = [
+            stamp(b, "+second"),
+            stamp(a, "+first"),
+            stamp(
+                Version {
+                    clock: 3,
+                    writer: 2,
+                },
+                &format!("-{}", a.encode()),
+            ),
+        ]
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+        assert_eq!(vec![(b, "second".to_string())], live(&entries));
+    }
+}
@@ -0,0 +1,1147 @@
+//! the Raft log the keepers started by [crate::lab2::serve_keeper] replicate
+//! among themselves, so that what they know of the bin storage survives any
+//! of them failing or restarting
+//!
+//! [Raft] is the consensus algorithm run by a single keeper, as a state
+//! machine which does no I/O and moves on with logical ticks, so that a
+//! cluster of them can be run deterministically, see
+//! [crate::lab2::raft_sim]. Its log holds [Command]s, which every keeper
+//! applies to its [KeeperState] once they are committed. [RaftNode] runs a
+//! [Raft] over the network with the [crate::keeper::raft_server::Raft] RPC
+//! service, and persists its term, vote and log in a [Storage] with [save]
+//! before any of its messages goes out, so a keeper restarted on the same
+//! storage takes up where it left off.
+//!
+//! The leader of the log is the leader of the keepers: only it can add to
+//! the log, with [RaftNode::propose], and work which only one keeper should
+//! do is run with [RaftNode::while_leader]. A leader which no longer hears
+//! from a majority steps down.
+//!
+//! The log is never compacted, and a restarted keeper replays it whole. It
+//! only grows when something the keepers know changes: a membership view
+//! with every change in which back-ends are alive, a checkpoint with every
+//! batch a migration copies, and a watermark every time the back-end clocks
+//! have moved [WATERMARK_STEP] further, so a keeper cluster running for long
+//! has a long log to replay.
+//!
+//! Leadership follows incarnation order: every message carries the
+//! [KeeperConfig::id] of its sender, and a leader which hears from a keeper
+//! with an older incarnation, i.e. a smaller id, hands leadership over to it
+//! with [Body::TimeoutNow] once its log has caught up. The older keeper then
+//! wins an election in a newer term, so the keeper up the longest leads,
+//! and leadership does not move every time a keeper restarts.
+#![allow(clippy::result_large_err)]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
+
+use log::warn;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, watch, Mutex};
+use tonic::{Request, Response, Status};
+use tribbler::{
+    auth::require_write,
+    config::KeeperConfig,
+    err::{ErrorCode, TribResult, TribblerError},
+    storage::{KeyValue, Op, OpResult, Storage},
+};
+
+use crate::{
+    keeper::{
+        self,
+        keeper_server::{Keeper, KeeperServer},
+        raft_client::RaftClient,
+        raft_message::Kind,
+        raft_server::{Raft as RaftService, RaftServer},
+    },
+    lab2::election::{connect, KeeperChannel, Leadership},
+};
+
+/// how often a [RaftNode] ticks its [Raft]
+pub const TICK: Duration = Duration::from_millis(100);
+
+/// a follower which has not heard from a leader for between this many ticks
+/// and twice as many stands for election
+pub const ELECTION_TICKS: u32 = 10;
+
+/// how many ticks a leader lets pass between appends to its followers, which
+/// serve as heartbeats
+pub const HEARTBEAT_TICKS: u32 = 2;
+
+/// most entries sent in a single append
+pub const MAX_APPEND: usize = 64;
+
+/// how long [RaftNode::propose] waits for a command to be committed
+pub const PROPOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// how often the keeper leading the log syncs the back-end clocks, and
+/// records a clock watermark if they moved far enough, see
+/// [crate::lab2::serve_keeper]
+pub const WATERMARK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// how far the back-end clocks must have moved past the watermark recorded
+/// before a new one is, so that the log grows with how far the clocks move
+/// rather than with time: this many ticks of a Lamport clock, or
+/// [HYBRID_WATERMARK_STEP] of a hybrid one
+pub const WATERMARK_STEP: u64 = 1 << 12;
+
+/// the [WATERMARK_STEP] of hybrid clocks, in wall-clock time
+pub const HYBRID_WATERMARK_STEP: Duration = Duration::from_secs(60);
+
+/// A view of which back-ends are alive
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Membership {
+    /// increases with every new view
+    pub version: u64,
+    /// indices of the alive back-ends in [KeeperConfig::backs]
+    pub alive: Vec<usize>,
+}
+
+/// A change to the [KeeperState]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// appended by a new leader, so that the entries of earlier terms get
+    /// committed
+    Noop,
+    /// a new membership view, which replaces the current one if its version
+    /// is newer
+    Membership(Membership),
+    /// the progress of a data migration, which another keeper can resume
+    /// from; an empty `progress` means the migration is done
+    Checkpoint { migration: String, progress: String },
+    /// a clock value the back-ends have been synced to, which the watermark
+    /// is raised to
+    Watermark(u64),
+}
+
+/// What the keepers know of the bin storage, as replicated by the log
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeeperState {
+    pub membership: Membership,
+    /// the progress of the migrations under way, by name
+    pub checkpoints: BTreeMap<String, String>,
+    /// no back-end clock should ever go below this value
+    pub watermark: u64,
+    /// index of the last entry applied
+    pub applied: u64,
+}
+
+impl KeeperState {
+    /// Applies `command`, the entry at `index` of the log.
+    pub fn apply(&mut self, index: u64, command: &Command) {
+        match command {
+            Command::Noop => (),
+            Command::Membership(m) => {
+                if m.version > self.membership.version {
+                    self.membership = m.clone();
+                }
+            }
+            Command::Checkpoint {
+                migration,
+                progress,
+            } => {
+                if progress.is_empty() {
+                    self.checkpoints.remove(migration);
+                } else {
+                    self.checkpoints.insert(migration.clone(), progress.clone());
+                }
+            }
+            Command::Watermark(w) => self.watermark = self.watermark.max(*w),
+        }
+        self.applied = index;
+    }
+}
+
+/// An entry of the log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// the term of the leader which appended it
+    pub term: u64,
+    pub command: Command,
+}
+
+/// What a [Message] asks or answers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    /// a candidate asks for a vote
+    RequestVote { last_index: u64, last_term: u64 },
+    /// the answer to [Body::RequestVote]
+    Vote { granted: bool },
+    /// a leader sends the entries following `prev_index`, or none as a
+    /// heartbeat
+    Append {
+        prev_index: u64,
+        prev_term: u64,
+        entries: Vec<Entry>,
+        commit: u64,
+    },
+    /// the answer to [Body::Append]: on success, `index` is that of the last
+    /// entry matching the leader's; otherwise a hint to retry from
+    Appended { success: bool, index: u64 },
+    /// the leader asks a node with an older incarnation, whose log has
+    /// caught up, to stand for election right away
+    TimeoutNow,
+}
+
+/// A message from one node to another, `from` and `to` being indices in
+/// [KeeperConfig::addrs]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub from: usize,
+    pub to: usize,
+    /// the incarnation of the sender, see [KeeperConfig::id]
+    pub id: u128,
+    /// the term of the sender
+    pub term: u64,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// The state of a node which must be persisted before it answers anyone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HardState {
+    pub term: u64,
+    /// whom the node voted for in `term`
+    pub vote: Option<usize>,
+}
+
+/// What a [Raft] has pending since the last [Raft::advance]: state to
+/// persist, then messages to send and committed entries to apply, in that
+/// order
+#[derive(Debug, Clone, Default)]
+pub struct Ready {
+    /// the hard state, if it changed
+    pub hard_state: Option<HardState>,
+    /// index of the first of `entries`
+    pub first: u64,
+    /// the log from `first` on, which replaces whatever was persisted there
+    pub entries: Vec<Entry>,
+    pub messages: Vec<Message>,
+    /// the entries committed, with their index
+    pub committed: Vec<(u64, Entry)>,
+}
+
+/// The Raft consensus algorithm run by a single node among `nodes`
+pub struct Raft {
+    this: usize,
+    nodes: usize,
+    /// the incarnation of this node
+    id: u128,
+    /// the incarnation each node was last heard from with
+    ids: Vec<Option<u128>>,
+    hard: HardState,
+    persisted: HardState,
+    /// the entry at index `i` is `log[i - 1]`
+    log: Vec<Entry>,
+    /// index of the first entry not persisted yet
+    unstable: u64,
+    commit: u64,
+    applied: u64,
+    role: Role,
+    leader: Option<usize>,
+    /// which nodes voted for this one, while a candidate
+    votes: Vec<bool>,
+    /// for each node, the index of the next entry to send it, while leader
+    next: Vec<u64>,
+    /// for each node, the index of the last entry known to match, while
+    /// leader
+    matched: Vec<u64>,
+    /// which nodes were heard from since the last quorum check, while leader
+    heard: Vec<bool>,
+    /// whether leadership was handed over since the last quorum check, while
+    /// leader
+    transferring: bool,
+    elapsed: u32,
+    timeout: u32,
+    rng: StdRng,
+    outbox: Vec<Message>,
+}
+
+impl Raft {
+    /// Creates node `this` of a cluster of `nodes`, in its incarnation `id`,
+    /// with the hard state and log it persisted before, if any. `seed`
+    /// randomizes its election timeouts.
+    pub fn new(
+        this: usize,
+        nodes: usize,
+        id: u128,
+        seed: u64,
+        hard: HardState,
+        log: Vec<Entry>,
+    ) -> Raft {
+        let mut ids = vec![None; nodes];
+        ids[this] = Some(id);
+        let mut raft = Raft {
+            this,
+            nodes,
+            id,
+            ids,
+            hard,
+            persisted: hard,
+            unstable: log.len() as u64 + 1,
+            log,
+            commit: 0,
+            applied: 0,
+            role: Role::Follower,
+            leader: None,
+            votes: vec![false; nodes],
+            next: vec![1; nodes],
+            matched: vec![0; nodes],
+            heard: vec![false; nodes],
+            transferring: false,
+            elapsed: 0,
+            timeout: ELECTION_TICKS,
+            rng: StdRng::seed_from_u64(seed),
+            outbox: vec![],
+        };
+        raft.reset_timeout();
+        raft
+    }
+
+    pub fn term(&self) -> u64 {
+        self.hard.term
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// the leader of the current term, if known
+    pub fn leader(&self) -> Option<usize> {
+        self.leader
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.role == Role::Leader
+    }
+
+    /// index of the last committed entry
+    pub fn commit(&self) -> u64 {
+        self.commit
+    }
+
+    pub fn log(&self) -> &[Entry] {
+        &self.log
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.log.len() as u64
+    }
+
+    fn last_term(&self) -> u64 {
+        self.log.last().map(|e| e.term).unwrap_or(0)
+    }
+
+    /// the term of the entry at `index`, 0 for the empty log before the
+    /// first entry
+    fn term_at(&self, index: u64) -> Option<u64> {
+        match index {
+            0 => Some(0),
+            i => self.log.get(i as usize - 1).map(|e| e.term),
+        }
+    }
+
+    fn others(&self) -> impl Iterator<Item = usize> {
+        let this = self.this;
+        (0..self.nodes).filter(move |&i| i != this)
+    }
+
+    fn send(&mut self, to: usize, body: Body) {
+        self.outbox.push(Message {
+            from: self.this,
+            to,
+            id: self.id,
+            term: self.hard.term,
+            body,
+        });
+    }
+
+    fn reset_timeout(&mut self) {
+        self.elapsed = 0;
+        self.timeout = self.rng.gen_range(ELECTION_TICKS..2 * ELECTION_TICKS);
+    }
+
+    /// Moves time on by one tick: a follower or candidate which has waited
+    /// long enough stands for election, and a leader sends heartbeats.
+    pub fn tick(&mut self) {
+        self.elapsed += 1;
+        if self.role != Role::Leader {
+            if self.elapsed >= self.timeout {
+                self.campaign();
+            }
+            return;
+        }
+        if self.elapsed.is_multiple_of(HEARTBEAT_TICKS) {
+            self.broadcast_append();
+        }
+        if self.elapsed >= 2 * ELECTION_TICKS {
+            self.elapsed = 0;
+            // a leader cut off from the majority cannot commit anything,
+            // so it had better let the majority know there is no leader
+            let heard = self.heard.iter().filter(|&&h| h).count() + 1;
+            self.heard = vec![false; self.nodes];
+            self.transferring = false;
+            if 2 * heard <= self.nodes {
+                self.become_follower(self.hard.term, None);
+            }
+        }
+    }
+
+    /// Appends `command` to the log if this node leads, and returns its index
+    /// and term. It is committed once it is applied with that term.
+    pub fn propose(&mut self, command: Command) -> Option<(u64, u64)> {
+        if self.role != Role::Leader {
+            return None;
+        }
+        self.append(command);
+        self.broadcast_append();
+        self.maybe_commit();
+        Some((self.last_index(), self.hard.term))
+    }
+
+    /// Handles a message from another node.
+    pub fn step(&mut self, msg: Message) {
+        if msg.to != self.this || msg.from >= self.nodes || msg.from == self.this {
+            return;
+        }
+        self.ids[msg.from] = Some(msg.id);
+        if msg.term > self.hard.term {
+            let leader = match msg.body {
+                Body::Append { .. } => Some(msg.from),
+                _ => None,
+            };
+            self.become_follower(msg.term, leader);
+        } else if msg.term < self.hard.term {
+            // let a stale candidate or leader know of the newer term
+            match msg.body {
+                Body::RequestVote { .. } => self.send(msg.from, Body::Vote { granted: false }),
+                Body::Append { .. } => self.send(
+                    msg.from,
+                    Body::Appended {
+                        success: false,
+                        index: 0,
+                    },
+                ),
+                _ => (),
+            }
+            return;
+        }
+        self.heard[msg.from] = true;
+        match msg.body {
+            Body::RequestVote {
+                last_index,
+                last_term,
+            } => {
+                let free = self.hard.vote.is_none_or(|v| v == msg.from);
+                let up_to_date = (last_term, last_index) >= (self.last_term(), self.last_index());
+                let granted = free && up_to_date;
+                if granted {
+                    self.hard.vote = Some(msg.from);
+                    self.reset_timeout();
+                }
+                self.send(msg.from, Body::Vote { granted });
+            }
+            Body::Vote { granted } => {
+                if self.role == Role::Candidate && granted {
+                    self.votes[msg.from] = true;
+                    if self.won() {
+                        self.become_leader();
+                    }
+                }
+            }
+            Body::Append {
+                prev_index,
+                prev_term,
+                entries,
+                commit,
+            } => self.handle_append(msg.from, prev_index, prev_term, entries, commit),
+            Body::Appended { success, index } => {
+                if self.role == Role::Leader {
+                    self.handle_appended(msg.from, success, index);
+                }
+            }
+            Body::TimeoutNow => {
+                if self.role == Role::Follower && self.leader == Some(msg.from) {
+                    self.campaign();
+                }
+            }
+        }
+    }
+
+    fn handle_append(
+        &mut self,
+        from: usize,
+        prev_index: u64,
+        prev_term: u64,
+        entries: Vec<Entry>,
+        commit: u64,
+    ) {
+        self.role = Role::Follower;
+        self.leader = Some(from);
+        self.reset_timeout();
+        if prev_index > self.last_index() {
+            let index = self.last_index();
+            self.send(
+                from,
+                Body::Appended {
+                    success: false,
+                    index,
+                },
+            );
+            return;
+        }
+        if self.term_at(prev_index) != Some(prev_term) {
+            self.send(
+                from,
+                Body::Appended {
+                    success: false,
+                    index: prev_index - 1,
+                },
+            );
+            return;
+        }
+        let last = prev_index + entries.len() as u64;
+        for (index, entry) in (prev_index + 1..).zip(entries) {
+            match self.term_at(index) {
+                Some(term) if term == entry.term => continue,
+                Some(_) => {
+                    // a conflicting entry, never committed: drop it and
+                    // everything after it
+                    self.log.truncate(index as usize - 1);
+                    self.unstable = self.unstable.min(index);
+                    self.log.push(entry);
+                }
+                None => self.log.push(entry),
+            }
+        }
+        self.commit = self.commit.max(commit.min(last));
+        self.send(
+            from,
+            Body::Appended {
+                success: true,
+                index: last,
+            },
+        );
+    }
+
+    fn handle_appended(&mut self, from: usize, success: bool, index: u64) {
+        if success {
+            if index > self.matched[from] {
+                self.matched[from] = index;
+                self.maybe_commit();
+            }
+            self.next[from] = self.next[from].max(index + 1);
+            if self.next[from] <= self.last_index() {
+                self.send_append(from);
+            } else {
+                self.maybe_transfer(from);
+            }
+        } else {
+            self.next[from] = (index + 1)
+                .min(self.next[from].saturating_sub(1))
+                .max(self.matched[from] + 1);
+            self.send_append(from);
+        }
+    }
+
+    /// Hands leadership over to node `to` if its incarnation is older than
+    /// this node's and its log has caught up. The node stands for election
+    /// in a newer term, which makes this one step down.
+    fn maybe_transfer(&mut self, to: usize) {
+        let older = matches!(self.ids[to], Some(id) if id < self.id);
+        if older && !self.transferring && self.matched[to] == self.last_index() {
+            self.transferring = true;
+            self.send(to, Body::TimeoutNow);
+        }
+    }
+
+    fn won(&self) -> bool {
+        2 * self.votes.iter().filter(|&&v| v).count() > self.nodes
+    }
+
+    fn campaign(&mut self) {
+        self.become_follower(self.hard.term + 1, None);
+        self.role = Role::Candidate;
+        self.hard.vote = Some(self.this);
+        self.votes = vec![false; self.nodes];
+        self.votes[self.this] = true;
+        if self.won() {
+            self.become_leader();
+            return;
+        }
+        let (last_index, last_term) = (self.last_index(), self.last_term());
+        for to in self.others().collect::<Vec<_>>() {
+            self.send(
+                to,
+                Body::RequestVote {
+                    last_index,
+                    last_term,
+                },
+            );
+        }
+    }
+
+    fn become_follower(&mut self, term: u64, leader: Option<usize>) {
+        if term > self.hard.term {
+            self.hard.term = term;
+            self.hard.vote = None;
+        }
+        self.role = Role::Follower;
+        self.leader = leader;
+        self.reset_timeout();
+    }
+
+    fn become_leader(&mut self) {
+        self.role = Role::Leader;
+        self.leader = Some(self.this);
+        self.elapsed = 0;
+        self.next = vec![self.last_index() + 1; self.nodes];
+        self.matched = vec![0; self.nodes];
+        self.heard = vec![false; self.nodes];
+        self.transferring = false;
+        self.append(Command::Noop);
+        self.broadcast_append();
+        self.maybe_commit();
+    }
+
+    fn append(&mut self, command: Command) {
+        self.log.push(Entry {
+            term: self.hard.term,
+            command,
+        });
+        self.matched[self.this] = self.last_index();
+    }
+
+    fn send_append(&mut self, to: usize) {
+        let prev_index = self.next[to] - 1;
+        let prev_term = self.term_at(prev_index).unwrap_or(0);
+        let end = (prev_index as usize + MAX_APPEND).min(self.log.len());
+        let entries = self.log[prev_index as usize..end].to_vec();
+        let commit = self.commit;
+        self.send(
+            to,
+            Body::Append {
+                prev_index,
+                prev_term,
+                entries,
+                commit,
+            },
+        );
+    }
+
+    fn broadcast_append(&mut self) {
+        for to in self.others().collect::<Vec<_>>() {
+            self.send_append(to);
+        }
+    }
+
+    /// Commits the entries stored on a majority, if the last of them is of
+    /// the current term.
+    fn maybe_commit(&mut self) {
+        let mut matched = self.matched.clone();
+        matched.sort_unstable();
+        let index = matched[(self.nodes - 1) / 2];
+        if index > self.commit && self.term_at(index) == Some(self.hard.term) {
+            self.commit = index;
+        }
+    }
+
+    /// Returns what is pending, to be handled in order, and then passed to
+    /// [Raft::advance]. The messages are handed out only once.
+    pub fn ready(&mut self) -> Ready {
+        Ready {
+            hard_state: Some(self.hard).filter(|h| *h != self.persisted),
+            first: self.unstable,
+            entries: self.log[self.unstable as usize - 1..].to_vec(),
+            messages: std::mem::take(&mut self.outbox),
+            committed: (self.applied + 1..=self.commit)
+                .map(|i| (i, self.log[i as usize - 1].clone()))
+                .collect(),
+        }
+    }
+
+    /// Records that `ready` was persisted and applied.
+    pub fn advance(&mut self, ready: &Ready) {
+        if let Some(hard) = ready.hard_state {
+            self.persisted = hard;
+        }
+        self.unstable = ready.first + ready.entries.len() as u64;
+        if let Some((i, _)) = ready.committed.last() {
+            self.applied = *i;
+        }
+    }
+}
+
+const TERM_KEY: &str = "raft::term";
+const VOTE_KEY: &str = "raft::vote";
+const LAST_KEY: &str = "raft::last";
+
+fn entry_key(index: u64) -> String {
+    format!("raft::log::{:016x}", index)
+}
+
+/// Persists the hard state and entries of `ready` in `storage`, with a
+/// single [Storage::batch].
+pub async fn save<S: Storage + ?Sized>(storage: &S, ready: &Ready) -> TribResult<()> {
+    let mut ops = vec![];
+    for (index, entry) in (ready.first..).zip(ready.entries.iter()) {
+        let value = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        ops.push(Op::Set(KeyValue::new(&entry_key(index), &value)));
+    }
+    if !ready.entries.is_empty() {
+        let last = ready.first + ready.entries.len() as u64 - 1;
+        ops.push(Op::Set(KeyValue::new(LAST_KEY, &last.to_string())));
+    }
+    if let Some(hard) = ready.hard_state {
+        let vote = hard.vote.map(|v| v.to_string()).unwrap_or_default();
+        ops.push(Op::Set(KeyValue::new(TERM_KEY, &hard.term.to_string())));
+        ops.push(Op::Set(KeyValue::new(VOTE_KEY, &vote)));
+    }
+    if !ops.is_empty() {
+        storage.batch(&ops).await?;
+    }
+    Ok(())
+}
+
+/// Loads the hard state and log persisted in `storage` by [save], which are
+/// empty if nothing was.
+pub async fn load<S: Storage + ?Sized>(storage: &S) -> TribResult<(HardState, Vec<Entry>)> {
+    let keys = [TERM_KEY, VOTE_KEY, LAST_KEY]
+        .iter()
+        .map(|k| Op::Get(k.to_string()))
+        .collect::<Vec<_>>();
+    let mut values = vec![];
+    for result in storage.batch(&keys).await? {
+        values.push(match result {
+            OpResult::Value(Some(v)) => Some(v.parse::<u64>().map_err(|e| e.to_string())?),
+            _ => None,
+        });
+    }
+    let hard = HardState {
+        term: values[0].unwrap_or(0),
+        vote: values[1].map(|v| v as usize),
+    };
+    let last = values[2].unwrap_or(0);
+    let ops = (1..=last)
+        .map(|i| Op::Get(entry_key(i)))
+        .collect::<Vec<_>>();
+    let mut log = vec![];
+    for (index, result) in (1..).zip(storage.batch(&ops).await?) {
+        match result {
+            OpResult::Value(Some(v)) => {
+                log.push(serde_json::from_str(&v).map_err(|e| e.to_string())?)
+            }
+            _ => {
+                return Err(Box::new(TribblerError::Status(
+                    ErrorCode::Internal,
+                    format!("raft log entry {} is missing", index),
+                )))
+            }
+        }
+    }
+    Ok((hard, log))
+}
+
+fn to_rpc(m: &Message) -> TribResult<keeper::RaftMessage> {
+    let mut msg = keeper::RaftMessage {
+        from: m.from as u32,
+        to: m.to as u32,
+        term: m.term,
+        id_high: (m.id >> 64) as u64,
+        id_low: m.id as u64,
+        ..Default::default()
+    };
+    match &m.body {
+        Body::RequestVote {
+            last_index,
+            last_term,
+        } => {
+            msg.set_kind(Kind::RequestVote);
+            msg.index = *last_index;
+            msg.log_term = *last_term;
+        }
+        Body::Vote { granted } => {
+            msg.set_kind(Kind::Vote);
+            msg.ok = *granted;
+        }
+        Body::Append {
+            prev_index,
+            prev_term,
+            entries,
+            commit,
+        } => {
+            msg.set_kind(Kind::Append);
+            msg.index = *prev_index;
+            msg.log_term = *prev_term;
+            msg.commit = *commit;
+            for e in entries {
+                msg.entries.push(keeper::RaftEntry {
+                    term: e.term,
+                    command: serde_json::to_string(&e.command).map_err(|e| e.to_string())?,
+                });
+            }
+        }
+        Body::Appended { success, index } => {
+            msg.set_kind(Kind::Appended);
+            msg.ok = *success;
+            msg.index = *index;
+        }
+        Body::TimeoutNow => msg.set_kind(Kind::TimeoutNow),
+    }
+    Ok(msg)
+}
+
+fn from_rpc(msg: keeper::RaftMessage) -> TribResult<Message> {
+    let body = match Kind::from_i32(msg.kind) {
+        Some(Kind::RequestVote) => Body::RequestVote {
+            last_index: msg.index,
+            last_term: msg.log_term,
+        },
+        Some(Kind::Vote) => Body::Vote { granted: msg.ok },
+        Some(Kind::Append) => {
+            let mut entries = vec![];
+            for e in msg.entries {
+                entries.push(Entry {
+                    term: e.term,
+                    command: serde_json::from_str(&e.command).map_err(|e| e.to_string())?,
+                });
+            }
+            Body::Append {
+                prev_index: msg.index,
+                prev_term: msg.log_term,
+                entries,
+                commit: msg.commit,
+            }
+        }
+        Some(Kind::Appended) => Body::Appended {
+            success: msg.ok,
+            index: msg.index,
+        },
+        Some(Kind::TimeoutNow) => Body::TimeoutNow,
+        None => return Err(format!("unknown raft message kind {}", msg.kind).into()),
+    };
+    Ok(Message {
+        from: msg.from as usize,
+        to: msg.to as usize,
+        id: ((msg.id_high as u128) << 64) | msg.id_low as u128,
+        term: msg.term,
+        body,
+    })
+}
+
+struct Inner {
+    raft: Raft,
+    storage: Box<dyn Storage>,
+    /// the proposals waiting to be committed, by index, with their term
+    waiting: HashMap<u64, (u64, oneshot::Sender<bool>)>,
+}
+
+/// Runs the [Raft] of a keeper over the network: serves the
+/// [crate::keeper::raft_server::Raft] RPC service to the other keepers,
+/// sends them its messages, and ticks it every [TICK] with [RaftNode::run].
+/// It also serves the [crate::keeper::keeper_server::Keeper] RPC service,
+/// which tells who leads the log. Clones share the same state.
+#[derive(Clone)]
+pub struct RaftNode {
+    this: usize,
+    inner: Arc<Mutex<Inner>>,
+    peers: Arc<HashMap<usize, RaftClient<KeeperChannel>>>,
+    state: Arc<watch::Sender<KeeperState>>,
+    leadership: Arc<watch::Sender<Leadership>>,
+}
+
+impl RaftNode {
+    /// Creates the node of the keeper set up by `kc`, which persists its
+    /// state in `storage` and takes up from what it persisted there before.
+    pub async fn new(kc: &KeeperConfig, storage: Box<dyn Storage>) -> TribResult<RaftNode> {
+        let (hard, log) = load(&*storage).await?;
+        let raft = Raft::new(kc.this, kc.addrs.len(), kc.id, kc.id as u64, hard, log);
+        let mut peers = HashMap::new();
+        for (i, addr) in kc.addrs.iter().enumerate() {
+            if i != kc.this {
+                peers.insert(i, RaftClient::new(connect(kc, addr)?));
+            }
+        }
+        let (state, _) = watch::channel(KeeperState::default());
+        let (leadership, _) = watch::channel(Leadership::default());
+        Ok(RaftNode {
+            this: kc.this,
+            inner: Arc::new(Mutex::new(Inner {
+                raft,
+                storage,
+                waiting: HashMap::new(),
+            })),
+            peers: Arc::new(peers),
+            state: Arc::new(state),
+            leadership: Arc::new(leadership),
+        })
+    }
+
+    /// Persists what the [Raft] of `inner` has pending, then sends its
+    /// messages and applies what it committed.
+    async fn process(&self, inner: &mut Inner) -> TribResult<()> {
+        let ready = inner.raft.ready();
+        save(&*inner.storage, &ready).await?;
+        inner.raft.advance(&ready);
+        let leadership = Leadership {
+            term: inner.raft.term(),
+            leader: inner.raft.leader(),
+        };
+        self.leadership.send_if_modified(|current| {
+            let changed = *current != leadership;
+            *current = leadership;
+            changed
+        });
+        for m in ready.messages {
+            self.send(m);
+        }
+        if ready.committed.is_empty() {
+            return Ok(());
+        }
+        self.state.send_modify(|state| {
+            for (index, entry) in ready.committed.iter() {
+                state.apply(*index, &entry.command);
+            }
+        });
+        for (index, entry) in ready.committed.iter() {
+            if let Some((term, tx)) = inner.waiting.remove(index) {
+                let _ = tx.send(term == entry.term);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `m` in the background. Messages which do not make it are
+    /// simply lost, which Raft copes with.
+    fn send(&self, m: Message) {
+        let mut client = match self.peers.get(&m.to) {
+            Some(client) => client.clone(),
+            None => return,
+        };
+        let msg = match to_rpc(&m) {
+            Ok(msg) => msg,
+            Err(e) => return warn!("cannot send raft message: {}", e),
+        };
+        tokio::spawn(async move {
+            let _ = tokio::time::timeout(TICK * ELECTION_TICKS, client.step(msg)).await;
+        });
+    }
+
+    /// Moves the [Raft] on by one tick.
+    pub async fn tick(&self) -> TribResult<()> {
+        let mut inner = self.inner.lock().await;
+        inner.raft.tick();
+        self.process(&mut inner).await
+    }
+
+    /// Calls [RaftNode::tick] every [TICK], forever.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.tick().await {
+                warn!("raft tick failed: {}", e);
+            }
+        }
+    }
+
+    /// Appends `command` to the log, and returns its index once it is
+    /// committed. Fails with [ErrorCode::Unavailable] if this keeper does
+    /// not lead, or if the command was not committed within
+    /// [PROPOSE_TIMEOUT], in which case it may still be later.
+    pub async fn propose(&self, command: Command) -> TribResult<u64> {
+        let (tx, rx) = oneshot::channel();
+        let index = {
+            let mut inner = self.inner.lock().await;
+            let (index, term) = match inner.raft.propose(command) {
+                Some(proposed) => proposed,
+                None => {
+                    return Err(Box::new(TribblerError::Status(
+                        ErrorCode::Unavailable,
+                        "this keeper does not lead the raft log".to_string(),
+                    )))
+                }
+            };
+            inner.waiting.insert(index, (term, tx));
+            self.process(&mut inner).await?;
+            index
+        };
+        match tokio::time::timeout(PROPOSE_TIMEOUT, rx).await {
+            Ok(Ok(true)) => Ok(index),
+            _ => Err(Box::new(TribblerError::Status(
+                ErrorCode::Unavailable,
+                format!("raft log entry {} was not committed", index),
+            ))),
+        }
+    }
+
+    /// Returns whether this keeper leads the log.
+    pub async fn is_leader(&self) -> bool {
+        self.inner.lock().await.raft.is_leader()
+    }
+
+    /// the leader of the log, if known
+    pub async fn leader(&self) -> Option<usize> {
+        self.inner.lock().await.raft.leader()
+    }
+
+    /// who leads the log, as of the last message or tick processed
+    pub fn leadership(&self) -> Leadership {
+        *self.leadership.borrow()
+    }
+
+    /// Returns a receiver which sees every change of leadership.
+    pub fn subscribe_leadership(&self) -> watch::Receiver<Leadership> {
+        self.leadership.subscribe()
+    }
+
+    /// Runs `f` if this keeper leads the log, and abandons it as soon as the
+    /// keeper steps down or the term changes. Returns [None] if `f` did not
+    /// run to completion.
+    pub async fn while_leader<F: Future>(&self, f: F) -> Option<F::Output> {
+        let mut changes = self.subscribe_leadership();
+        let start = *changes.borrow_and_update();
+        if start.leader != Some(self.this) {
+            return None;
+        }
+        let stepped_down = async {
+            while changes.changed().await.is_ok() {
+                if *changes.borrow() != start {
+                    return;
+                }
+            }
+        };
+        tokio::select! {
+            output = f => Some(output),
+            _ = stepped_down => None,
+        }
+    }
+
+    /// the state as of the last entry applied
+    pub fn state(&self) -> KeeperState {
+        self.state.borrow().clone()
+    }
+
+    /// Returns a receiver which sees the state change with every entry
+    /// applied.
+    pub fn subscribe(&self) -> watch::Receiver<KeeperState> {
+        self.state.subscribe()
+    }
+
+    /// the RPC service other keepers send messages to
+    pub fn service(&self) -> RaftServer<RaftNode> {
+        RaftServer::new(self.clone())
+    }
+
+    /// the RPC service which tells who leads the log
+    pub fn keeper_service(&self) -> KeeperServer<RaftNode> {
+        KeeperServer::new(self.clone())
+    }
+}
+
+#[tonic::async_trait]
+impl Keeper for RaftNode {
+    async fn leader(
+        &self,
+        _: Request<keeper::LeaderRequest>,
+    ) -> Result<Response<keeper::LeaderReply>, Status> {
+        let leadership = self.leadership();
+        Ok(Response::new(keeper::LeaderReply {
+            term: leadership.term,
+            known: leadership.leader.is_some(),
+            leader: leadership.leader.unwrap_or_default() as u32,
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl RaftService for RaftNode {
+    async fn step(
+        &self,
+        request: Request<keeper::RaftMessage>,
+    ) -> Result<Response<keeper::RaftAck>, Status> {
+        require_write(&request)?;
+        let msg =
+            from_rpc(request.into_inner()).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let mut inner = self.inner.lock().await;
+        inner.raft.step(msg);
+        self.process(&mut inner)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(keeper::RaftAck {}))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tribbler::storage::MemStorage;
+
+    use super::{load, save, Command, Entry, HardState, KeeperState, Membership, Ready};
+
+    #[test]
+    fn raft_state_apply() {
+        let mut state = KeeperState::default();
+        let view = |version| {
+            Command::Membership(Membership {
+                version,
+                alive: vec![version as usize],
+            })
+        };
+        state.apply(1, &view(2));
+        state.apply(2, &view(1));
+        assert_eq!(vec![2], state.membership.alive);
+        state.apply(3, &Command::Watermark(10));
+        state.apply(4, &Command::Watermark(5));
+        assert_eq!(10, state.watermark);
+        let checkpoint = |progress: &str| Command::Checkpoint {
+            migration: "m".to_string(),
+            progress: progress.to_string(),
+        };
+        state.apply(5, &checkpoint("k1"));
+        assert_eq!(Some("k1"), state.checkpoints.get("m").map(|s| s.as_str()));
+        state.apply(6, &checkpoint(""));
+        assert!(state.checkpoints.is_empty());
+        assert_eq!(6, state.applied);
+    }
+
+    #[tokio::test]
+    async fn raft_persist() -> tribbler::err::TribResult<()> {
+        let storage = MemStorage::new();
+        assert_eq!((HardState::default(), vec![]), load(&storage).await?);
+        let entry = |term| Entry {
+            term,
+            command: Command::Watermark(term),
+        };
+        let hard = HardState {
+            term: 2,
+            vote: Some(1),
+        };
+        let ready = Ready {
+            hard_state: Some(hard),
+            first: 1,
+            entries: vec![entry(1), entry(1), entry(2)],
+            ..Default::default()
+        };
+        save(&storage, &ready).await?;
+        // a conflicting suffix is replaced
+        let ready = Ready {
+            first: 2,
+            entries: vec![entry(3)],
+            ..Default::default()
+        };
+        save(&storage, &ready).await?;
+        assert_eq!((hard, vec![entry(1), entry(3)]), load(&storage).await?);
+        Ok(())
+    }
+}
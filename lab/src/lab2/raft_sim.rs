@@ -0,0 +1,175 @@
+//! a deterministic, in-process harness running a cluster of [Raft] nodes,
+//! to test how the keepers' log copes with partitions and crashes
+//!
+//! Nothing runs in the background: time only moves on with [Sim::tick], and
+//! messages are delivered in the order they were sent, unless a partition or
+//! a crash drops them, so runs with the same seed always end the same. Each
+//! node persists its state with [raft::save] into a [MemStorage] which
+//! outlives [Sim::crash], and which [Sim::restart] loads it back from.
+//!
+//! ```
+//! # use lab::lab2::{raft::Command, raft_sim::Sim};
+//! # #[tokio::main]
+//! # async fn main() -> tribbler::err::TribResult<()> {
+//! let mut sim = Sim::new(3, 1);
+//! sim.run(50).await?;
+//! let leader = sim.leader().unwrap();
+//! sim.propose(leader, Command::Watermark(7)).await?;
+//! sim.run(5).await?;
+//! assert!((0..3).all(|i| sim.state(i).watermark == 7));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashSet, VecDeque};
+
+use tribbler::{err::TribResult, storage::MemStorage};
+
+use crate::lab2::raft::{self, Command, HardState, KeeperState, Message, Raft};
+
+/// A cluster of [Raft] nodes and the network between them
+pub struct Sim {
+    seed: u64,
+    starts: u64,
+    /// [None] while crashed
+    nodes: Vec<Option<Raft>>,
+    stores: Vec<MemStorage>,
+    states: Vec<KeeperState>,
+    /// the links messages are dropped on, as (from, to)
+    cut: HashSet<(usize, usize)>,
+    in_flight: VecDeque<Message>,
+}
+
+impl Sim {
+    /// Creates a cluster of `n` fresh nodes, whose election timeouts are
+    /// drawn from `seed`.
+    pub fn new(n: usize, seed: u64) -> Sim {
+        let mut sim = Sim {
+            seed,
+            starts: 0,
+            nodes: vec![],
+            stores: (0..n).map(|_| MemStorage::new()).collect(),
+            states: vec![KeeperState::default(); n],
+            cut: HashSet::new(),
+            in_flight: VecDeque::new(),
+        };
+        for i in 0..n {
+            let node = sim.start(i, HardState::default(), vec![]);
+            sim.nodes.push(Some(node));
+        }
+        sim
+    }
+
+    fn start(&mut self, i: usize, hard: HardState, log: Vec<raft::Entry>) -> Raft {
+        // every start is a new incarnation, younger than any before it
+        self.starts += 1;
+        let seed = self.seed.wrapping_mul(1_000_003).wrapping_add(self.starts);
+        Raft::new(i, self.stores.len(), self.starts as u128, seed, hard, log)
+    }
+
+    /// Splits the network into `groups`, which only hear from nodes in the
+    /// same group. Nodes in no group are cut off from every other.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let group = |i: usize| groups.iter().position(|g| g.contains(&i));
+        let n = self.nodes.len();
+        self.cut = (0..n)
+            .flat_map(|a| (0..n).map(move |b| (a, b)))
+            .filter(|&(a, b)| a != b && (group(a).is_none() || group(a) != group(b)))
+            .collect();
+    }
+
+    /// Lets every node hear from every other again.
+    pub fn heal(&mut self) {
+        self.cut.clear();
+    }
+
+    /// Stops node `i`, which loses everything it did not persist, and the
+    /// messages on their way to it.
+    pub fn crash(&mut self, i: usize) {
+        self.nodes[i] = None;
+        self.states[i] = KeeperState::default();
+    }
+
+    /// Starts node `i` again from what it persisted.
+    pub async fn restart(&mut self, i: usize) -> TribResult<()> {
+        let (hard, log) = raft::load(&self.stores[i]).await?;
+        let node = self.start(i, hard, log);
+        self.nodes[i] = Some(node);
+        Ok(())
+    }
+
+    /// Moves time on by one tick on every node up, and delivers messages
+    /// until there are none left.
+    pub async fn tick(&mut self) -> TribResult<()> {
+        for node in self.nodes.iter_mut().flatten() {
+            node.tick();
+        }
+        self.settle().await
+    }
+
+    /// Calls [Sim::tick] `ticks` times.
+    pub async fn run(&mut self, ticks: u32) -> TribResult<()> {
+        for _ in 0..ticks {
+            self.tick().await?;
+        }
+        Ok(())
+    }
+
+    /// Proposes `command` to node `i`, and delivers messages until there are
+    /// none left. Returns the index and term of the entry if `i` leads.
+    pub async fn propose(&mut self, i: usize, command: Command) -> TribResult<Option<(u64, u64)>> {
+        let proposed = self.nodes[i].as_mut().and_then(|n| n.propose(command));
+        self.settle().await?;
+        Ok(proposed)
+    }
+
+    async fn settle(&mut self) -> TribResult<()> {
+        loop {
+            for (i, node) in self.nodes.iter_mut().enumerate() {
+                let node = match node {
+                    Some(node) => node,
+                    None => continue,
+                };
+                let ready = node.ready();
+                raft::save(&self.stores[i], &ready).await?;
+                node.advance(&ready);
+                for (index, entry) in ready.committed.iter() {
+                    self.states[i].apply(*index, &entry.command);
+                }
+                self.in_flight.extend(ready.messages);
+            }
+            if self.in_flight.is_empty() {
+                return Ok(());
+            }
+            while let Some(m) = self.in_flight.pop_front() {
+                if self.cut.contains(&(m.from, m.to)) {
+                    continue;
+                }
+                if let Some(Some(node)) = self.nodes.get_mut(m.to) {
+                    node.step(m);
+                }
+            }
+        }
+    }
+
+    /// node `i`, unless it crashed
+    pub fn node(&self, i: usize) -> Option<&Raft> {
+        self.nodes[i].as_ref()
+    }
+
+    /// the state applied by node `i`
+    pub fn state(&self, i: usize) -> &KeeperState {
+        &self.states[i]
+    }
+
+    /// the node up which leads in the latest term, if any
+    pub fn leader(&self) -> Option<usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, n)| n.as_ref().map(|n| (i, n)))
+            .filter(|(_, n)| n.is_leader())
+            .max_by_key(|(_, n)| n.term())
+            .map(|(i, _)| i)
+    }
+}
@@ -197,6 +197,8 @@
 //! - [tribbler::storage] contains an in-memory thread-safe implementation of
 //!   the [tribbler::storage::Storage] interface. We will use this as the basic
 //!   building block for our back-end storage system.
+//! - [tribbler::disk] contains a durable implementation of the same interface
+//!   which keeps its data in a write-ahead log and snapshots on disk.
 //! - [tribbler::addr] provides helper functions that check if an address
 //!   belongs to the machine that the program is running.
 //! - [tribbler::addr::rand] provides helper functions that generate a network
@@ -280,7 +282,8 @@
 #![doc(
     html_favicon_url = "https://upload.wikimedia.org/wikipedia/commons/thumb/f/f8/Creative-Tail-Animal-penguin.svg/128px-Creative-Tail-Animal-penguin.svg.png?20160314145218"
 )]
-mod keeper;
+/// protobuf-generated keeper RPC stubs and message structs
+pub mod keeper;
 pub mod lab1;
 pub mod lab2;
 pub mod lab3;
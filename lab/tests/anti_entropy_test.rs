@@ -0,0 +1,153 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+use lab::{
+    lab1::builder::ClientBuilder,
+    lab2::{
+        self,
+        anti_entropy::AntiEntropy,
+        quorum::{QuorumClient, HINTS_BIN},
+    },
+};
+use tribbler::{
+    addr::rand::rand_port,
+    config::{Config, QuorumConfig},
+    digest::HashRange,
+    err::TribResult,
+    namespace::Bin,
+    storage::{BinStorage, KeyList, KeyString, MemStorage, Storage},
+};
+
+mod common;
+
+use common::{kv, BackBuilder};
+
+async fn serve(n: usize) -> TribResult<(Vec<Arc<MemStorage>>, Vec<String>)> {
+    let mut storages = vec![];
+    let mut addrs = vec![];
+    for _ in 0..n {
+        let storage = Arc::new(MemStorage::new());
+        let back = BackBuilder::new().storage(storage.clone()).start().await?;
+        storages.push(storage);
+        addrs.push(back.addr);
+    }
+    Ok((storages, addrs))
+}
+
+const QUORUM: QuorumConfig = QuorumConfig { n: 3, r: 2, w: 2 };
+
+/// fills bins through a quorum client: a small bin per user, and one bin
+/// too large to be compared key by key at once. Every write waits for all
+/// replicas, which are in sync once it returns.
+async fn fill(addrs: &[String]) -> TribResult<()> {
+    let all = QuorumConfig { w: 3, ..QUORUM };
+    let bc = QuorumClient::new(addrs, &ClientBuilder::new(), all)?;
+    for i in 0..30 {
+        let bin = bc.bin(&format!("user{}", i)).await?;
+        bin.set(&kv("name", &format!("user {}", i))).await?;
+        bin.list_append(&kv("tribs", "hello")).await?;
+    }
+    let big = bc.bin("big").await?;
+    for i in 0..100 {
+        big.set(&kv(&format!("k{}", i), "v")).await?;
+    }
+    Ok(())
+}
+
+/// makes the back-ends diverge in 4 entries
+async fn diverge(storages: &[Arc<MemStorage>]) -> TribResult<()> {
+    storages[0].set(&kv("user7::name", "")).await?;
+    let entries = storages[1].list_get("user8::tribs").await?.0;
+    storages[1]
+        .list_remove(&kv("user8::tribs", &entries[0]))
+        .await?;
+    storages[2].set(&kv("big::k42", "")).await?;
+    storages[0].set(&kv("big::k43", "")).await?;
+    Ok(())
+}
+
+async fn assert_in_sync(storages: &[Arc<MemStorage>]) -> TribResult<()> {
+    let first = storages[0].digest(HashRange::ALL, 1, 0).await?;
+    for s in &storages[1..] {
+        assert_eq!(first, s.digest(HashRange::ALL, 1, 0).await?);
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_anti_entropy_repairs_only_differences() -> TribResult<()> {
+    // as many back-ends as replicas, so every back-end holds every bin
+    let (storages, addrs) = serve(3).await?;
+    fill(&addrs).await?;
+    assert_in_sync(&storages).await?;
+    let anti_entropy = AntiEntropy::new(&addrs, &ClientBuilder::new(), 3)?;
+    assert_eq!(0, anti_entropy.run().await?.keys);
+
+    diverge(&storages).await?;
+    let repairs = anti_entropy.run().await?;
+    assert_eq!(4, repairs.keys);
+    assert_eq!(4, repairs.copies);
+    assert_in_sync(&storages).await?;
+    assert!(storages[0]
+        .get("user7::name")
+        .await?
+        .unwrap()
+        .ends_with(":user 7"));
+    assert_eq!(1, storages[1].list_get("user8::tribs").await?.0.len());
+    assert_eq!(0, anti_entropy.run().await?.keys);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_anti_entropy_skips_hints() -> TribResult<()> {
+    let (storages, addrs) = serve(3).await?;
+    Bin::new(HINTS_BIN, storages[0].clone())
+        .list_append(&kv("127.0.0.1:1", "hint"))
+        .await?;
+    storages[0].set(&kv("alice::name", "Alice")).await?;
+
+    let anti_entropy = AntiEntropy::new(&addrs, &ClientBuilder::new(), 3)?;
+    assert_eq!(1, anti_entropy.run().await?.keys);
+    for s in &storages[1..] {
+        assert_eq!(Some("Alice".to_string()), s.get("alice::name").await?);
+        let hints = Bin::new(HINTS_BIN, s.clone());
+        assert!(hints.list_get("127.0.0.1:1").await?.0.is_empty());
+    }
+    assert_eq!(0, anti_entropy.run().await?.keys);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_anti_entropy_keeper() -> TribResult<()> {
+    let (storages, addrs) = serve(3).await?;
+    fill(&addrs).await?;
+    diverge(&storages).await?;
+
+    let cfg = Config {
+        backs: addrs,
+        keepers: vec![format!("127.0.0.1:{}", rand_port())],
+        quorum: Some(QUORUM),
+        ..Default::default()
+    };
+    let (tx, rx): (Sender<bool>, Receiver<bool>) = mpsc::channel();
+    let (shut_tx, shut_rx) = tokio::sync::mpsc::channel(1);
+    let keeper = tokio::spawn(lab2::serve_keeper(cfg.keeper_config(
+        0,
+        Some(tx),
+        Some(shut_rx),
+    )?));
+    assert!(rx.recv_timeout(Duration::from_secs(5))?);
+    // the keeper leads once it has waited for others to show up, and makes
+    // a first pass right away
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_in_sync(&storages).await?;
+
+    shut_tx.send(()).await?;
+    keeper.await??;
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use lab::lab1;
+use tribbler::{
+    config::{AuthConfig, ClientConfig},
+    err::TribResult,
+    storage::{Op, Pattern},
+};
+
+mod common;
+
+use common::{kv, BackBuilder, Running};
+
+/// a back-end which `front` may write to and `monitor` read from
+async fn setup() -> TribResult<Running> {
+    BackBuilder::new()
+        .auth(AuthConfig {
+            read_write: vec!["front".to_string()],
+            read_only: vec!["monitor".to_string()],
+            token: String::new(),
+        })
+        .shutdown()
+        .start()
+        .await
+}
+
+fn token(token: &str) -> ClientConfig {
+    ClientConfig {
+        token: Some(token.to_string()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_auth_roles() -> TribResult<()> {
+    let back = setup().await?;
+    let url = back.url();
+
+    let front = lab1::new_client_with(&url, &token("front")).await?;
+    assert!(front.set(&kv("hello", "world")).await?);
+    assert!(front.list_append(&kv("l", "a")).await?);
+    assert!(front.clock(0).await.is_ok());
+
+    let monitor = lab1::new_client_with(&url, &token("monitor")).await?;
+    assert_eq!(Some("world".to_string()), monitor.get("hello").await?);
+    assert_eq!(1, monitor.list_get("l").await?.0.len());
+    assert_eq!(1, monitor.keys(&Pattern::default()).await?.0.len());
+    assert!(monitor
+        .batch(&[Op::Get("hello".to_string()), Op::ListGet("l".to_string())])
+        .await
+        .is_ok());
+    assert!(monitor.set(&kv("hello", "there")).await.is_err());
+    assert!(monitor.list_remove(&kv("l", "a")).await.is_err());
+    assert!(monitor.incr("n", 1).await.is_err());
+    assert!(monitor
+        .batch(&[Op::Get("hello".to_string()), Op::Set(kv("hello", "there"))])
+        .await
+        .is_err());
+    assert_eq!(Some("world".to_string()), front.get("hello").await?);
+
+    let stranger = lab1::new_client_with(&url, &token("guess")).await?;
+    assert!(stranger.get("hello").await.is_err());
+    let anonymous = lab1::new_client(&url).await?;
+    assert!(anonymous.get("hello").await.is_err());
+
+    back.stop().await?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use lab::{
+    lab1::builder::{ChannelPool, ClientBuilder, RetryPolicy},
+    lab2::bin_client::BinClient,
+};
+use tribbler::{
+    addr::rand::rand_port,
+    err::{ErrorCode, TribResult},
+    storage::{BinStorage, KeyList, KeyString, Storage},
+};
+
+mod common;
+
+use common::{kv, BackBuilder, Running};
+
+/// serves an empty storage at `addr`, which can be shut down
+async fn serve(addr: &str) -> TribResult<Running> {
+    BackBuilder::new().addr(addr).shutdown().start().await
+}
+
+#[test]
+fn test_retry_backoff() {
+    let retry = RetryPolicy::default();
+    assert_eq!(Duration::from_millis(100), retry.backoff(0));
+    assert_eq!(Duration::from_millis(200), retry.backoff(1));
+    assert_eq!(Duration::from_millis(800), retry.backoff(3));
+    assert_eq!(Duration::from_secs(1), retry.backoff(4));
+    assert_eq!(Duration::from_secs(1), retry.backoff(100));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_builder_pool() -> TribResult<()> {
+    let pool = ChannelPool::new();
+    let builder = ClientBuilder::new().pool(pool.clone());
+    builder.build("http://127.0.0.1:3000")?;
+    builder.clone().build("http://127.0.0.1:3000")?;
+    assert_eq!(1, pool.len());
+    ClientBuilder::new()
+        .pool(pool.clone())
+        .build("http://127.0.0.1:3001")?;
+    assert_eq!(2, pool.len());
+
+    // every bin on a back-end shares its connection
+    let pool = ChannelPool::new();
+    let bc = BinClient::new(
+        &["127.0.0.1:3000".to_string(), "127.0.0.1:3001".to_string()],
+        &ClientBuilder::new().pool(pool.clone()),
+    )?;
+    for i in 0..10 {
+        bc.bin(&format!("bin{}", i)).await?;
+    }
+    assert_eq!(2, pool.len());
+
+    assert!(ClientBuilder::new()
+        .deadline(Duration::ZERO)
+        .build("http://127.0.0.1:3000")
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_reconnect_after_restart() -> TribResult<()> {
+    let addr = format!("127.0.0.1:{}", rand_port());
+    let back = serve(&addr).await?;
+    let client = ClientBuilder::new().build(&back.url())?;
+    assert!(client.set(&kv("hello", "world")).await?);
+    back.stop().await?;
+
+    // comes back while the client is retrying
+    let calls = tokio::spawn(async move {
+        assert_eq!(None, client.get("hello").await?);
+        assert!(client.list_append(&kv("l", "a")).await?);
+        assert_eq!(vec!["a".to_string()], client.list_get("l").await?.0);
+        TribResult::Ok(())
+    });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let back = serve(&addr).await?;
+    calls.await??;
+    back.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_retry_gives_up() -> TribResult<()> {
+    let addr = format!("http://127.0.0.1:{}", rand_port());
+    let client = ClientBuilder::new()
+        .retry(RetryPolicy::none())
+        .build(&addr)?;
+    let e = client.get("hello").await.unwrap_err();
+    assert_eq!(ErrorCode::Unavailable, ErrorCode::of(&*e));
+
+    let client = ClientBuilder::new()
+        .deadline(Duration::from_millis(500))
+        .retry(RetryPolicy {
+            retries: 100,
+            ..Default::default()
+        })
+        .build(&addr)?;
+    let start = Instant::now();
+    let e = client.clock(0).await.unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(matches!(
+        ErrorCode::of(&*e),
+        ErrorCode::Unavailable | ErrorCode::Deadline
+    ));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_error_codes() -> TribResult<()> {
+    let addr = format!("127.0.0.1:{}", rand_port());
+    let back = serve(&addr).await?;
+    let client = ClientBuilder::new().build(&back.url())?;
+
+    // errors of the back-end's storage keep their code over RPC
+    assert!(client.set(&kv("n", "not a number")).await?);
+    let e = client.incr("n", 1).await.unwrap_err();
+    assert_eq!(ErrorCode::Invalid, ErrorCode::of(&*e));
+    let e = client
+        .scan(&Default::default(), "bad", 10)
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorCode::Invalid, ErrorCode::of(&*e));
+    assert_eq!(1, client.incr("m", 1).await?);
+    back.stop().await?;
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! fixtures shared by the integration tests, not all of which every test
+//! uses
+#![allow(dead_code)]
+
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+use lab::lab1;
+use tokio::{sync::mpsc::Sender as MpscSender, task::JoinHandle};
+use tribbler::{
+    addr::rand::rand_port,
+    config::{AuthConfig, BackConfig, Compression, TlsConfig},
+    err::{TribResult, TribblerError},
+    namespace::Bin,
+    storage::{KeyValue, MemStorage, Storage},
+};
+
+/// a [KeyValue] of `key` and `value`
+pub fn kv(key: &str, value: &str) -> KeyValue {
+    KeyValue::new(key, value)
+}
+
+/// sets up a back-end of the test: an empty [MemStorage] served on a fresh
+/// port, without TLS, authentication or compression, and which cannot be
+/// shut down, unless told otherwise
+pub struct BackBuilder {
+    cfg: BackConfig,
+    shutdown: bool,
+}
+
+impl BackBuilder {
+    pub fn new() -> BackBuilder {
+        BackBuilder {
+            cfg: BackConfig {
+                addr: format!("127.0.0.1:{}", rand_port()),
+                ..Default::default()
+            },
+            shutdown: false,
+        }
+    }
+
+    pub fn addr(mut self, addr: &str) -> BackBuilder {
+        self.cfg.addr = addr.to_string();
+        self
+    }
+
+    pub fn storage(mut self, storage: impl Storage + 'static) -> BackBuilder {
+        self.cfg.storage = Box::new(storage);
+        self
+    }
+
+    pub fn tls(mut self, tls: &TlsConfig) -> BackBuilder {
+        self.cfg.tls = Some(tls.clone());
+        self
+    }
+
+    pub fn auth(mut self, auth: AuthConfig) -> BackBuilder {
+        self.cfg.auth = Some(auth);
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> BackBuilder {
+        self.cfg.compression = compression;
+        self
+    }
+
+    /// lets the back-end be shut down with [Running::stop]
+    pub fn shutdown(mut self) -> BackBuilder {
+        self.shutdown = true;
+        self
+    }
+
+    /// starts the back-end, and waits for it to be ready
+    pub async fn start(self) -> TribResult<Running> {
+        let mut cfg = self.cfg;
+        let (tx, rx): (Sender<bool>, Receiver<bool>) = mpsc::channel();
+        cfg.ready = Some(tx);
+        let shutdown = match self.shutdown {
+            true => {
+                let (shut_tx, shut_rx) = tokio::sync::mpsc::channel(1);
+                cfg.shutdown = Some(shut_rx);
+                Some(shut_tx)
+            }
+            false => None,
+        };
+        let addr = cfg.addr.clone();
+        let handle = tokio::spawn(lab1::serve_back(cfg));
+        if !rx.recv_timeout(Duration::from_secs(5))? {
+            return Err(Box::new(TribblerError::Unknown(
+                "back failed to start".to_string(),
+            )));
+        }
+        Ok(Running {
+            addr,
+            handle,
+            shutdown,
+        })
+    }
+}
+
+/// a back-end started by a [BackBuilder]
+pub struct Running {
+    pub addr: String,
+    pub handle: JoinHandle<TribResult<()>>,
+    pub shutdown: Option<MpscSender<()>>,
+}
+
+impl Running {
+    /// `http://` and the address of the back-end
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// shuts the back-end down, which must have been set up with
+    /// [BackBuilder::shutdown], and waits for it to stop
+    pub async fn stop(self) -> TribResult<()> {
+        if let Some(shutdown) = self.shutdown {
+            let _ = shutdown.send(()).await;
+        }
+        self.handle.await?
+    }
+}
+
+/// a back-end of the test, which can be stopped and started again with the
+/// same data
+pub struct Back {
+    pub addr: String,
+    pub storage: Arc<MemStorage>,
+    shutdown: Option<MpscSender<()>>,
+}
+
+impl Back {
+    pub async fn start(&mut self) -> TribResult<()> {
+        let running = BackBuilder::new()
+            .addr(&self.addr)
+            .storage(self.storage.clone())
+            .shutdown()
+            .start()
+            .await?;
+        self.shutdown = running.shutdown;
+        Ok(())
+    }
+
+    pub async fn stop(&mut self) -> TribResult<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).await?;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        Ok(())
+    }
+
+    /// the raw contents of `bin` on this back-end
+    pub fn bin(&self, bin: &str) -> Bin<Arc<MemStorage>> {
+        Bin::new(bin, self.storage.clone())
+    }
+}
+
+/// starts `n` back-ends on fresh ports, returning them and their addresses
+pub async fn serve(n: usize) -> TribResult<(Vec<Back>, Vec<String>)> {
+    let mut backs = vec![];
+    for _ in 0..n {
+        let mut back = Back {
+            addr: format!("127.0.0.1:{}", rand_port()),
+            storage: Arc::new(MemStorage::new()),
+            shutdown: None,
+        };
+        back.start().await?;
+        backs.push(back);
+    }
+    let addrs = backs.iter().map(|b| b.addr.clone()).collect();
+    Ok((backs, addrs))
+}
@@ -0,0 +1,128 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use lab::lab1;
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tribbler::{
+    config::{ClientConfig, Compression},
+    err::TribResult,
+    storage::KeyValue,
+};
+
+mod common;
+
+use common::BackBuilder;
+
+/// size of every list item, so that 1024 of them make up 1 MB
+const ITEM_SIZE: usize = 1024;
+
+/// copies bytes from `from` to `to`, counting them in `count`
+async fn pipe(
+    mut from: io::ReadHalf<TcpStream>,
+    mut to: io::WriteHalf<TcpStream>,
+    count: Arc<AtomicUsize>,
+) -> io::Result<()> {
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = from.read(&mut buf).await?;
+        if n == 0 {
+            return to.shutdown().await;
+        }
+        count.fetch_add(n, Ordering::SeqCst);
+        to.write_all(&buf[..n]).await?;
+    }
+}
+
+/// starts a proxy in front of `back` and returns its address, and the
+/// number of bytes sent from the back-end to the client
+async fn proxy(back: String) -> TribResult<(String, Arc<AtomicUsize>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?.to_string();
+    let received = Arc::new(AtomicUsize::new(0));
+    let r = received.clone();
+    tokio::spawn(async move {
+        while let Ok((client, _)) = listener.accept().await {
+            let server = match TcpStream::connect(&back).await {
+                Ok(server) => server,
+                Err(_) => return,
+            };
+            let (client_read, client_write) = io::split(client);
+            let (server_read, server_write) = io::split(server);
+            tokio::spawn(pipe(client_read, server_write, Arc::default()));
+            tokio::spawn(pipe(server_read, client_write, r.clone()));
+        }
+    });
+    Ok((addr, received))
+}
+
+/// appends a 1 MB list through a proxy, reads it back, and returns the
+/// number of bytes the list took on the wire
+async fn round_trip(compression: Compression) -> TribResult<usize> {
+    let back = BackBuilder::new().compression(compression).start().await?;
+    let (addr, received) = proxy(back.addr).await?;
+    let config = ClientConfig {
+        compression,
+        ..Default::default()
+    };
+    let client = lab1::new_client_with(&format!("http://{}", addr), &config).await?;
+
+    let items: Vec<String> = (0..1024)
+        .map(|i| format!("{:0width$}", i, width = ITEM_SIZE))
+        .collect();
+    for item in items.iter() {
+        assert!(
+            client
+                .list_append(&KeyValue {
+                    key: "big".to_string(),
+                    value: item.clone(),
+                })
+                .await?
+        );
+    }
+    let received_before = received.load(Ordering::SeqCst);
+    let list = client.list_get("big").await?;
+    assert_eq!(items, list.0);
+    Ok(received.load(Ordering::SeqCst) - received_before)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_gzip_round_trip() -> TribResult<()> {
+    let plain = round_trip(Compression::None).await?;
+    assert!(plain >= 1024 * ITEM_SIZE);
+    let compressed = round_trip(Compression::Gzip).await?;
+    assert!(
+        compressed * 10 < plain,
+        "{} bytes with gzip vs {} without",
+        compressed,
+        plain
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_gzip_client_plain_back() -> TribResult<()> {
+    // backs always accept compressed calls, but only compress their answers
+    // when configured to
+    let back = BackBuilder::new().start().await?;
+    let config = ClientConfig {
+        compression: Compression::Gzip,
+        ..Default::default()
+    };
+    let client = lab1::new_client_with(&back.url(), &config).await?;
+    let value = "x".repeat(4096);
+    assert!(
+        client
+            .set(&KeyValue {
+                key: "k".to_string(),
+                value: value.clone(),
+            })
+            .await?
+    );
+    assert_eq!(Some(value), client.get("k").await?);
+    Ok(())
+}
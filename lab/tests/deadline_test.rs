@@ -0,0 +1,196 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use lab::lab1::{self, builder::ClientBuilder};
+use tribbler::{
+    call::CallOptions,
+    err::{TribResult, TribblerError},
+    storage::{KeyList, KeyString, KeyValue, List, MemStorage, Pattern, Storage},
+};
+
+mod common;
+
+use common::{kv, BackBuilder};
+
+/// A [Storage] which takes `delay` to serve every call, and remembers the
+/// [CallOptions] of each call
+struct Slow {
+    storage: Arc<MemStorage>,
+    delay: Duration,
+    seen: Arc<Mutex<Vec<CallOptions>>>,
+}
+
+impl Slow {
+    async fn wait(&self) {
+        self.seen.lock().unwrap().push(CallOptions::current());
+        tokio::time::sleep(self.delay).await;
+    }
+}
+
+#[async_trait]
+impl KeyString for Slow {
+    async fn get(&self, key: &str) -> TribResult<Option<String>> {
+        self.wait().await;
+        self.storage.get(key).await
+    }
+
+    async fn set(&self, kv: &KeyValue) -> TribResult<bool> {
+        self.wait().await;
+        self.storage.set(kv).await
+    }
+
+    async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        self.wait().await;
+        self.storage.set_ttl(kv, ttl).await
+    }
+
+    async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)> {
+        self.wait().await;
+        self.storage.cas(kv, old).await
+    }
+
+    async fn incr(&self, key: &str, delta: i64) -> TribResult<i64> {
+        self.wait().await;
+        self.storage.incr(key, delta).await
+    }
+
+    async fn keys(&self, p: &Pattern) -> TribResult<List> {
+        self.wait().await;
+        self.storage.keys(p).await
+    }
+}
+
+#[async_trait]
+impl KeyList for Slow {
+    async fn list_get(&self, key: &str) -> TribResult<List> {
+        self.wait().await;
+        self.storage.list_get(key).await
+    }
+
+    async fn list_append(&self, kv: &KeyValue) -> TribResult<bool> {
+        self.wait().await;
+        self.storage.list_append(kv).await
+    }
+
+    async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        self.wait().await;
+        self.storage.list_append_ttl(kv, ttl).await
+    }
+
+    async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32> {
+        self.wait().await;
+        self.storage.list_remove(kv).await
+    }
+
+    async fn list_keys(&self, p: &Pattern) -> TribResult<List> {
+        self.wait().await;
+        self.storage.list_keys(p).await
+    }
+}
+
+#[async_trait]
+impl Storage for Slow {
+    async fn clock(&self, at_least: u64) -> TribResult<u64> {
+        self.wait().await;
+        self.storage.clock(at_least).await
+    }
+}
+
+/// serves a [Slow] storage, returning its address, the storage behind it and
+/// the options of the calls it served
+async fn serve(
+    delay: Duration,
+) -> TribResult<(String, Arc<MemStorage>, Arc<Mutex<Vec<CallOptions>>>)> {
+    let storage = Arc::new(MemStorage::new());
+    let seen = Arc::new(Mutex::new(vec![]));
+    let slow = Slow {
+        storage: storage.clone(),
+        delay,
+        seen: seen.clone(),
+    };
+    let back = BackBuilder::new().storage(slow).start().await?;
+    Ok((back.url(), storage, seen))
+}
+
+fn assert_timeout<T: std::fmt::Debug>(r: TribResult<T>) {
+    match r {
+        Err(e) => match e.downcast_ref::<TribblerError>() {
+            Some(TribblerError::Timeout(_)) => (),
+            _ => panic!("expected a timeout, got {}", e),
+        },
+        Ok(v) => panic!("expected a timeout, got {:?}", v),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_deadline_finishes_started_call() -> TribResult<()> {
+    let (addr, storage, _) = serve(Duration::from_millis(500)).await?;
+    let client = lab1::new_client(&addr).await?;
+    let start = Instant::now();
+    let r = CallOptions::new()
+        .timeout(Duration::from_millis(100))
+        .scope(client.set(&kv("hello", "world")))
+        .await;
+    assert_timeout(r);
+    assert!(start.elapsed() < Duration::from_millis(400));
+    // the client gave up, but the back-end finished the set it started
+    // rather than dropping it half-way through
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    assert_eq!(Some("world".to_string()), storage.get("hello").await?);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_deadline_builder() -> TribResult<()> {
+    let (addr, _, _) = serve(Duration::from_millis(500)).await?;
+    let client = ClientBuilder::new()
+        .deadline(Duration::from_millis(100))
+        .build(&addr)?;
+    assert_timeout(client.get("hello").await);
+    // a scope with a later deadline does not extend the builder's
+    let r = CallOptions::new()
+        .timeout(Duration::from_secs(5))
+        .scope(client.get("hello"))
+        .await;
+    assert_timeout(r);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_deadline_passed() -> TribResult<()> {
+    let (addr, _, seen) = serve(Duration::ZERO).await?;
+    let client = lab1::new_client(&addr).await?;
+    let r = CallOptions::new()
+        .deadline(Instant::now())
+        .scope(client.get("hello"))
+        .await;
+    assert_timeout(r);
+    assert!(seen.lock().unwrap().is_empty());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_request_id() -> TribResult<()> {
+    let (addr, _, seen) = serve(Duration::ZERO).await?;
+    let client = lab1::new_client(&addr).await?;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    CallOptions::new()
+        .deadline(deadline)
+        .request_id("req-1")
+        .scope(client.set(&kv("hello", "world")))
+        .await?;
+    client.get("hello").await?;
+    let seen = seen.lock().unwrap();
+    assert_eq!(2, seen.len());
+    assert_eq!(Some("req-1".to_string()), seen[0].request_id);
+    // the back-end's deadline is taken from the time left, so it can only be
+    // a little off
+    let served = seen[0].deadline.unwrap();
+    assert!(served <= deadline + Duration::from_millis(100));
+    assert!(served + Duration::from_millis(500) > deadline);
+    assert_eq!(CallOptions::new(), seen[1]);
+    Ok(())
+}
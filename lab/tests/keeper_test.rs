@@ -0,0 +1,129 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use lab::{
+    keeper::{keeper_client::KeeperClient, LeaderRequest},
+    lab2::{self, raft::WATERMARK_INTERVAL},
+};
+use tokio::sync::mpsc::Sender as MpscSender;
+use tribbler::{
+    addr::rand::rand_port,
+    config::Config,
+    err::{TribResult, TribblerError},
+    hlc,
+    storage::{MemStorage, Storage},
+};
+
+mod common;
+
+use common::BackBuilder;
+
+fn config(keepers: usize) -> Config {
+    Config {
+        keepers: (0..keepers)
+            .map(|_| format!("127.0.0.1:{}", rand_port()))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// starts keeper `i` of `cfg` with incarnation `id`
+async fn start(cfg: &Config, i: usize, id: u128) -> TribResult<MpscSender<()>> {
+    let (tx, rx): (Sender<bool>, Receiver<bool>) = mpsc::channel();
+    let (shut_tx, shut_rx) = tokio::sync::mpsc::channel(1);
+    let mut kc = cfg.keeper_config(i, Some(tx), Some(shut_rx))?;
+    kc.id = id;
+    tokio::spawn(lab2::serve_keeper(kc));
+    if !rx.recv_timeout(Duration::from_secs(5))? {
+        return Err(Box::new(TribblerError::Unknown(
+            "keeper failed to start".to_string(),
+        )));
+    }
+    Ok(shut_tx)
+}
+
+/// asks the keeper at `addr` who leads, returning the term and the leader
+async fn leader(addr: &str) -> TribResult<(u64, Option<usize>)> {
+    let mut client = KeeperClient::connect(format!("http://{}", addr)).await?;
+    let r = client.leader(LeaderRequest {}).await?.into_inner();
+    Ok((r.term, Some(r.leader as usize).filter(|_| r.known)))
+}
+
+/// Waits until every keeper of `cfg` in `up` agrees that `expected` leads,
+/// and returns the term.
+async fn wait_for_leader(cfg: &Config, up: &[usize], expected: usize) -> TribResult<u64> {
+    let start = Instant::now();
+    loop {
+        let mut seen = vec![];
+        for &i in up {
+            seen.push(leader(&cfg.keepers[i]).await?);
+        }
+        if seen.iter().all(|s| *s == (seen[0].0, Some(expected))) {
+            return Ok(seen[0].0);
+        }
+        if start.elapsed() > Duration::from_secs(10) {
+            return Err(Box::new(TribblerError::Unknown(format!(
+                "keepers did not agree on {} leading: {:?}",
+                expected, seen
+            ))));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_keeper_oldest_leads() -> TribResult<()> {
+    let cfg = config(3);
+    let _a = start(&cfg, 0, 30).await?;
+    let _b = start(&cfg, 1, 10).await?;
+    let _c = start(&cfg, 2, 20).await?;
+    assert!(wait_for_leader(&cfg, &[0, 1, 2], 1).await? > 0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_keeper_failover() -> TribResult<()> {
+    let cfg = config(3);
+    let _b = start(&cfg, 1, 20).await?;
+    let _c = start(&cfg, 2, 30).await?;
+    let t1 = wait_for_leader(&cfg, &[1, 2], 1).await?;
+
+    // an older keeper showing up takes over in a newer term
+    let a = start(&cfg, 0, 10).await?;
+    let t2 = wait_for_leader(&cfg, &[0, 1, 2], 0).await?;
+    assert!(t2 > t1);
+
+    // and once it is gone, the next oldest leads again
+    a.send(()).await?;
+    let t3 = wait_for_leader(&cfg, &[1, 2], 1).await?;
+    assert!(t3 > t2);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_keeper_hybrid_clock() -> TribResult<()> {
+    // the back-end keeps a plain Lamport clock and sees no traffic, so only
+    // the keeper moves its clock on
+    let storage = Arc::new(MemStorage::new());
+    let back = BackBuilder::new().storage(storage.clone()).start().await?;
+    let mut cfg = config(1);
+    cfg.backs = vec![back.addr.clone()];
+    cfg.hybrid_clock = true;
+
+    let now = hlc::now();
+    let _k = start(&cfg, 0, 1).await?;
+    let start = Instant::now();
+    while storage.clock(0).await? < now {
+        assert!(
+            start.elapsed() < WATERMARK_INTERVAL * 3,
+            "the back-end clock did not keep up"
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    Ok(())
+}
@@ -45,6 +45,7 @@ async fn setup(
         storage: storage,
         ready: Some(tx.clone()),
         shutdown: Some(shut_rx),
+        ..Default::default()
     };
 
     let handle = spawn_back(cfg);
@@ -73,6 +74,7 @@ fn pat(prefix: &str, suffix: &str) -> Pattern {
     Pattern {
         prefix: prefix.to_string(),
         suffix: suffix.to_string(),
+        ..Default::default()
     }
 }
 
@@ -183,6 +185,7 @@ async fn test_bad_address() -> TribResult<()> {
         storage: Box::new(MemStorage::default()),
         ready: Some(tx),
         shutdown: None,
+        ..Default::default()
     };
     let handle = spawn_back(cfg);
     if let Ok(ready) = rx.recv_timeout(Duration::from_secs(1)) {
@@ -205,6 +208,7 @@ async fn test_store_before_serve() -> TribResult<()> {
         storage: Box::new(store),
         ready: Some(tx),
         shutdown: None,
+        ..Default::default()
     };
     let _handle = spawn_back(cfg);
     let ready = rx.recv_timeout(Duration::from_secs(1))?;
@@ -224,12 +228,14 @@ async fn test_multi_serve() -> TribResult<()> {
         storage: Box::new(MemStorage::default()),
         ready: Some(tx.clone()),
         shutdown: None,
+        ..Default::default()
     };
     let cfg2 = BackConfig {
         addr: "127.0.0.1:3001".to_string(),
         storage: Box::new(MemStorage::default()),
         ready: Some(tx.clone()),
         shutdown: None,
+        ..Default::default()
     };
     spawn_back(cfg);
     spawn_back(cfg2);
@@ -260,6 +266,7 @@ async fn test_spawn_same_addr() -> TribResult<()> {
         storage: Box::new(MemStorage::default()),
         ready: Some(tx.clone()),
         shutdown: Some(shut_rx),
+        ..Default::default()
     };
     let handle = spawn_back(cfg);
     assert_eq!(true, rx.recv_timeout(Duration::from_secs(2))?);
@@ -271,6 +278,7 @@ async fn test_spawn_same_addr() -> TribResult<()> {
         storage: Box::new(MemStorage::default()),
         ready: Some(tx),
         shutdown: None,
+        ..Default::default()
     };
     let _ = spawn_back(cfg);
     assert_eq!(true, rx.recv_timeout(Duration::from_secs(2))?);
@@ -291,6 +299,7 @@ async fn test_back_spawn_new_storage() -> TribResult<()> {
         storage: Box::new(MemStorage::default()),
         ready: Some(tx.clone()),
         shutdown: Some(shut_rx),
+        ..Default::default()
     };
     let handle = spawn_back(cfg);
     assert_eq!(true, rx.recv_timeout(Duration::from_secs(2))?);
@@ -305,6 +314,7 @@ async fn test_back_spawn_new_storage() -> TribResult<()> {
         storage: Box::new(MemStorage::default()),
         ready: Some(tx),
         shutdown: Some(shut_rx),
+        ..Default::default()
     };
     let _ = spawn_back(cfg);
     assert_eq!(true, rx.recv_timeout(Duration::from_secs(2))?);
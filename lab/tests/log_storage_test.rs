@@ -0,0 +1,216 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::future::join_all;
+use lab::lab2::log_storage::{LogStorage, COMPACT_INTERVAL, LIST_LOG, VALUE_LOG};
+use tribbler::{
+    err::TribResult,
+    storage::{KeyList, KeyString, MemStorage, Pattern, Storage},
+};
+
+mod common;
+
+use common::kv;
+
+/// the number of entries in all the logs of `s`
+async fn log_len(s: &MemStorage) -> TribResult<usize> {
+    let mut n = 0;
+    for log in s.list_keys(&Pattern::default()).await?.0 {
+        n += s.list_get(&log).await?.0.len();
+    }
+    Ok(n)
+}
+
+#[tokio::test]
+async fn test_log_storage_semantics() -> TribResult<()> {
+    let s = LogStorage::new(MemStorage::new());
+    assert_eq!(None, s.get("a1").await?);
+    assert!(s.set(&kv("a1", "x")).await?);
+    s.set(&kv("a2", "y")).await?;
+    s.set(&kv("b1", "z")).await?;
+    s.set(&kv("a2", "")).await?;
+    assert_eq!(Some("x".to_string()), s.get("a1").await?);
+    assert_eq!(None, s.get("a2").await?);
+    let p = Pattern {
+        prefix: "a".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(vec!["a1".to_string()], s.keys(&p).await?.0);
+    let p = Pattern {
+        suffix: "1".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(vec!["a1", "b1"], s.keys(&p).await?.0);
+
+    assert_eq!(
+        (false, Some("x".to_string())),
+        s.cas(&kv("a1", "w"), "y").await?
+    );
+    assert_eq!(
+        (true, Some("w".to_string())),
+        s.cas(&kv("a1", "w"), "x").await?
+    );
+    assert_eq!(
+        (true, Some("new".to_string())),
+        s.cas(&kv("a3", "new"), "").await?
+    );
+    assert_eq!(5, s.incr("n", 5).await?);
+    assert_eq!(3, s.incr("n", -2).await?);
+    assert!(s.incr("a1", 1).await.is_err());
+
+    // values and lists under the same key are apart
+    for v in ["x", "y", "x", "z"] {
+        assert!(s.list_append(&kv("a1", v)).await?);
+    }
+    assert_eq!(vec!["x", "y", "x", "z"], s.list_get("a1").await?.0);
+    assert_eq!(2, s.list_remove(&kv("a1", "x")).await?);
+    assert_eq!(0, s.list_remove(&kv("a1", "x")).await?);
+    assert_eq!(vec!["y", "z"], s.list_get("a1").await?.0);
+    assert_eq!(vec!["z"], s.list_range("a1", -1, -1).await?.0);
+    s.list_append(&kv("b", "x")).await?;
+    s.list_remove(&kv("b", "x")).await?;
+    assert_eq!(vec!["a1"], s.list_keys(&Pattern::default()).await?.0);
+    assert_eq!(Some("w".to_string()), s.get("a1").await?);
+    assert!(s.clock(100).await? >= 100);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_log_storage_ttl() -> TribResult<()> {
+    let s = LogStorage::new(MemStorage::new());
+    let ttl = Duration::from_millis(200);
+    s.set_ttl(&kv("session", "t"), ttl).await?;
+    s.set_ttl(&kv("counter", "1"), ttl).await?;
+    s.set_ttl(&kv("kept", "1"), ttl).await?;
+    // a set clears the expiry, an increment does not
+    s.set(&kv("kept", "2")).await?;
+    assert_eq!(2, s.incr("counter", 1).await?);
+    s.list_append_ttl(&kv("recent", "a"), ttl).await?;
+    s.list_append(&kv("recent", "b")).await?;
+    assert_eq!(Some("t".to_string()), s.get("session").await?);
+    assert_eq!(vec!["a", "b"], s.list_get("recent").await?.0);
+
+    tokio::time::sleep(ttl + Duration::from_millis(100)).await;
+    assert_eq!(None, s.get("session").await?);
+    assert_eq!(None, s.get("counter").await?);
+    assert_eq!(Some("2".to_string()), s.get("kept").await?);
+    assert_eq!(vec!["kept"], s.keys(&Pattern::default()).await?.0);
+    assert!(s.list_get("recent").await?.0.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_log_storage_log_expiry() -> TribResult<()> {
+    let backend = Arc::new(MemStorage::new());
+    let grace = Duration::from_millis(100);
+    let s = LogStorage::new(backend.clone()).with_grace(grace);
+    let ttl = Duration::from_millis(100);
+    s.set_ttl(&kv("session", "t"), ttl).await?;
+    s.set(&kv("gone", "x")).await?;
+    s.set(&kv("gone", "")).await?;
+    // a set without a TTL keeps the log for good
+    s.set_ttl(&kv("kept", "1"), ttl).await?;
+    s.set(&kv("kept", "2")).await?;
+    assert_eq!(
+        vec!["gone@value", "kept@value", "session@value"],
+        backend.list_keys(&Pattern::default()).await?.0
+    );
+
+    // the logs of expired and cleared values go once the grace period is
+    // over
+    tokio::time::sleep(ttl + grace + Duration::from_millis(100)).await;
+    assert_eq!(3, backend.list_keys(&Pattern::default()).await?.0.len());
+    s.compact_dirty().await?;
+    assert_eq!(
+        vec!["kept@value"],
+        backend.list_keys(&Pattern::default()).await?.0
+    );
+    assert_eq!(Some("2".to_string()), s.get("kept").await?);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_log_storage_converge() -> TribResult<()> {
+    let backend = Arc::new(MemStorage::new());
+    let writers = (0..4)
+        .map(|_| Arc::new(LogStorage::new(backend.clone())))
+        .collect::<Vec<_>>();
+    let writes = writers.iter().enumerate().map(|(w, s)| async move {
+        for i in 0..20 {
+            s.set(&kv("k", &format!("{}-{}", w, i))).await?;
+            s.list_append(&kv("l", &format!("{}-{}", w, i))).await?;
+        }
+        s.list_remove(&kv("l", &format!("{}-0", w))).await?;
+        TribResult::Ok(())
+    });
+    for r in join_all(writes).await {
+        r?;
+    }
+    let value = writers[0].get("k").await?;
+    let list = writers[0].list_get("l").await?.0;
+    assert_eq!(76, list.len());
+    for s in writers.iter() {
+        assert_eq!(value, s.get("k").await?);
+        assert_eq!(list, s.list_get("l").await?.0);
+    }
+
+    // the logs read the same whatever order their entries are in
+    let shuffled = MemStorage::new();
+    for log in [format!("k{}", VALUE_LOG), format!("l{}", LIST_LOG)] {
+        for entry in backend.list_get(&log).await?.0.iter().rev() {
+            shuffled.list_append(&kv(&log, entry)).await?;
+        }
+    }
+    let s = LogStorage::new(shuffled);
+    assert_eq!(value, s.get("k").await?);
+    assert_eq!(list, s.list_get("l").await?.0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_log_storage_compaction() -> TribResult<()> {
+    let backend = Arc::new(MemStorage::new());
+    let s = LogStorage::new(backend.clone());
+    for i in 0..10 {
+        s.set(&kv("k", &i.to_string())).await?;
+        s.list_append(&kv("l", &i.to_string())).await?;
+    }
+    s.set(&kv("gone", "x")).await?;
+    s.set(&kv("gone", "")).await?;
+    for i in 0..5 {
+        s.list_remove(&kv("l", &i.to_string())).await?;
+    }
+    assert_eq!(27, log_len(&backend).await?);
+    // 9 values, 5 elements and their 5 removals, and a value cleared
+    assert_eq!(20, s.compact_dirty().await?);
+    assert_eq!(0, s.compact_dirty().await?);
+    assert_eq!(7, log_len(&backend).await?);
+    assert_eq!(Some("9".to_string()), s.get("k").await?);
+    assert_eq!(None, s.get("gone").await?);
+    assert_eq!(vec!["5", "6", "7", "8", "9"], s.list_get("l").await?.0);
+    assert_eq!(vec!["k"], s.keys(&Pattern::default()).await?.0);
+
+    // logs written by others are compacted by a full compaction
+    let other = LogStorage::new(backend.clone());
+    other.set(&kv("k", "10")).await?;
+    assert_eq!(0, s.compact_dirty().await?);
+    assert_eq!(1, s.compact().await?);
+    assert_eq!(Some("10".to_string()), s.get("k").await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_log_storage_background() -> TribResult<()> {
+    let s = Arc::new(LogStorage::new(MemStorage::new()));
+    let compactor = tokio::spawn(s.clone().run());
+    for i in 0..10 {
+        s.set(&kv("k", &i.to_string())).await?;
+    }
+    tokio::time::sleep(COMPACT_INTERVAL * 2).await;
+    assert_eq!(1, log_len(s.inner()).await?);
+    assert_eq!(Some("9".to_string()), s.get("k").await?);
+
+    // it stops once the storage is dropped
+    drop(s);
+    tokio::time::timeout(COMPACT_INTERVAL * 2, compactor).await??;
+    Ok(())
+}
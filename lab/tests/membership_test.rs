@@ -0,0 +1,151 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use lab::{
+    lab1::builder::ClientBuilder,
+    lab2::{
+        self,
+        membership::{read_view, FailureDetector, MembershipEvent, MEMBERSHIP_BIN},
+        raft::Membership,
+    },
+};
+use tribbler::{
+    addr::rand::rand_port,
+    config::{ClientConfig, Config, QuorumConfig},
+    err::{TribResult, TribblerError},
+};
+
+mod common;
+
+use common::{serve, Back};
+
+async fn next_event(
+    events: &mut tokio::sync::mpsc::Receiver<MembershipEvent>,
+) -> TribResult<MembershipEvent> {
+    match tokio::time::timeout(Duration::from_secs(5), events.recv()).await {
+        Ok(Some(event)) => Ok(event),
+        _ => Err(Box::new(TribblerError::Unknown(
+            "no membership event".to_string(),
+        ))),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_membership_detector_events() -> TribResult<()> {
+    let (mut backs, addrs) = serve(3).await?;
+    let detector = Arc::new(FailureDetector::new(&addrs, &ClientBuilder::new())?);
+    assert_eq!(None, detector.alive());
+    let (tx, mut events) = tokio::sync::mpsc::channel(16);
+    tokio::spawn(detector.clone().run(tx));
+
+    let mut joined = vec![];
+    for _ in 0..3 {
+        match next_event(&mut events).await? {
+            MembershipEvent::Join(i) => joined.push(i),
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+    joined.sort_unstable();
+    assert_eq!(vec![0, 1, 2], joined);
+    assert_eq!(Some(vec![0, 1, 2]), detector.alive());
+
+    backs[1].stop().await?;
+    assert_eq!(MembershipEvent::Leave(1), next_event(&mut events).await?);
+    assert_eq!(Some(vec![0, 2]), detector.alive());
+    backs[1].start().await?;
+    assert_eq!(MembershipEvent::Join(1), next_event(&mut events).await?);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_membership_publish() -> TribResult<()> {
+    let (mut backs, addrs) = serve(3).await?;
+    let detector = FailureDetector::new(&addrs, &ClientBuilder::new())?;
+    let view = |version, alive: &[usize]| Membership {
+        version,
+        alive: alive.to_vec(),
+    };
+    // nothing is published before the back-ends are known to be alive
+    assert_eq!(0, detector.publish(&view(1, &[0])).await);
+    detector.probe().await;
+    assert_eq!(3, detector.publish(&view(2, &[0, 1, 2])).await);
+    // an older view does not replace a newer one
+    detector.publish(&view(1, &[0])).await;
+    for back in backs.iter() {
+        assert_eq!(
+            Some(view(2, &[0, 1, 2])),
+            read_view(&back.bin(MEMBERSHIP_BIN)).await?
+        );
+    }
+
+    // bin clients read it back, replicated or not
+    let bc = lab2::new_bin_client(addrs.clone()).await?;
+    let bin = bc.bin(MEMBERSHIP_BIN).await?;
+    assert_eq!(Some(view(2, &[0, 1, 2])), read_view(&*bin).await?);
+    let quorum = QuorumConfig { n: 3, r: 2, w: 2 };
+    let bc = lab2::new_quorum_bin_client(addrs, &ClientConfig::default(), quorum).await?;
+    let bin = bc.bin(MEMBERSHIP_BIN).await?;
+    assert_eq!(Some(view(2, &[0, 1, 2])), read_view(&*bin).await?);
+
+    // a back-end which fails is left out, and the others get the view
+    backs[2].stop().await?;
+    assert_eq!(2, detector.publish(&view(3, &[0, 1, 2])).await);
+    assert_eq!(
+        Some(view(3, &[0, 1, 2])),
+        read_view(&backs[0].bin(MEMBERSHIP_BIN)).await?
+    );
+    Ok(())
+}
+
+/// waits until back-end `back` holds a view newer than version `after` in
+/// which `alive` are alive, and returns its version
+async fn wait_for_view(back: &Back, alive: &[usize], after: u64) -> TribResult<u64> {
+    let start = Instant::now();
+    loop {
+        let view = read_view(&back.bin(MEMBERSHIP_BIN)).await?;
+        match &view {
+            Some(v) if v.alive == alive && v.version > after => return Ok(v.version),
+            _ if start.elapsed() > Duration::from_secs(10) => {
+                return Err(Box::new(TribblerError::Unknown(format!(
+                    "view {:?} did not become {:?}",
+                    view, alive
+                ))))
+            }
+            _ => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_membership_keeper() -> TribResult<()> {
+    let (mut backs, addrs) = serve(3).await?;
+    let cfg = Config {
+        backs: addrs,
+        keepers: vec![format!("127.0.0.1:{}", rand_port())],
+        ..Default::default()
+    };
+    let (tx, rx): (Sender<bool>, Receiver<bool>) = mpsc::channel();
+    let (shut_tx, shut_rx) = tokio::sync::mpsc::channel(1);
+    let keeper = tokio::spawn(lab2::serve_keeper(cfg.keeper_config(
+        0,
+        Some(tx),
+        Some(shut_rx),
+    )?));
+    assert!(rx.recv_timeout(Duration::from_secs(5))?);
+
+    let first = wait_for_view(&backs[0], &[0, 1, 2], 0).await?;
+    backs[2].stop().await?;
+    let second = wait_for_view(&backs[0], &[0, 1], first).await?;
+    // the back-end which was down gets the view too once it is back
+    backs[2].start().await?;
+    wait_for_view(&backs[2], &[0, 1, 2], second).await?;
+
+    shut_tx.send(()).await?;
+    keeper.await??;
+    Ok(())
+}
@@ -0,0 +1,313 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_util::{FutureExt, StreamExt};
+use lab::lab2::{
+    migration::{in_range, Checkpoint, Checkpoints, MemCheckpoints, Migration, Phase},
+    raft::RaftNode,
+};
+use tribbler::{
+    config::Config,
+    digest::{position, HashRange},
+    err::{TribResult, TribblerError},
+    storage::{ChangeKind, KeyList, KeyString, KeyValue, MemStorage, Pattern, Storage},
+};
+
+/// fills `s` with `n` values and lists in each of bins `alice` and `bob`
+async fn fill(s: &MemStorage, n: usize) -> TribResult<()> {
+    for bin in ["alice", "bob"] {
+        for i in 0..n {
+            s.set(&KeyValue::new(
+                &format!("{}::k{:04}", bin, i),
+                &i.to_string(),
+            ))
+            .await?;
+            s.list_append(&KeyValue::new(&format!("{}::l{:04}", bin, i), "x"))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// asserts that `dest` holds what `source` holds for the keys `wanted`, and
+/// nothing else
+async fn assert_migrated(
+    source: &MemStorage,
+    dest: &MemStorage,
+    wanted: impl Fn(&str) -> bool,
+) -> TribResult<()> {
+    let all = Pattern::default();
+    let mut keys = source.keys(&all).await?.0;
+    keys.retain(|k| wanted(k));
+    keys.sort();
+    let mut copied = dest.keys(&all).await?.0;
+    copied.sort();
+    assert_eq!(keys, copied);
+    for key in keys.iter() {
+        assert_eq!(source.get(key).await?, dest.get(key).await?, "{}", key);
+    }
+    let mut lists = source.list_keys(&all).await?.0;
+    lists.retain(|k| wanted(k));
+    lists.sort();
+    let mut copied = dest.list_keys(&all).await?.0;
+    copied.sort();
+    assert_eq!(lists, copied);
+    for key in lists.iter() {
+        assert_eq!(source.list_get(key).await?.0, dest.list_get(key).await?.0);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migration_range() -> TribResult<()> {
+    let (source, dest) = (MemStorage::new(), MemStorage::new());
+    fill(&source, 10).await?;
+    // stale entries in the destination are overwritten
+    dest.set(&KeyValue::new("alice::k0000", "stale")).await?;
+    dest.list_append(&KeyValue::new("alice::l0000", "stale"))
+        .await?;
+
+    let alice = position("alice::");
+    let range = HashRange {
+        first: alice,
+        last: alice,
+    };
+    let migration = Migration::new("alice", &source, &dest, in_range(range));
+    let checkpoints = MemCheckpoints::default();
+    let stats = migration.run(&checkpoints).await?;
+    assert_eq!((10, 10, 10), (stats.values, stats.lists, stats.items));
+    assert!(stats.keys_per_sec() > 0.0);
+    assert_migrated(&source, &dest, |k| k.starts_with("alice::")).await?;
+    assert_eq!(Phase::Tail, checkpoints.load("alice").await?.unwrap().phase);
+
+    migration.finish(&checkpoints).await?;
+    assert_eq!(None, checkpoints.load("alice").await?);
+    assert!(source
+        .keys(&Pattern::default())
+        .await?
+        .0
+        .iter()
+        .all(|k| !k.starts_with("@")));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migration_lists_and_ttls() -> TribResult<()> {
+    let (source, dest) = (MemStorage::new(), MemStorage::new());
+    for v in ["a", "b", "c"] {
+        source
+            .list_append(&KeyValue::new("alice::grown", v))
+            .await?;
+    }
+    for v in ["x", "y", "x", "z"] {
+        source
+            .list_append(&KeyValue::new("alice::mixed", v))
+            .await?;
+    }
+    for (key, v) in [
+        ("grown", "a"),
+        ("grown", "b"),
+        ("mixed", "x"),
+        ("mixed", "z"),
+    ] {
+        dest.list_append(&KeyValue::new(&format!("alice::{}", key), v))
+            .await?;
+    }
+    let ttl = Duration::from_secs(1);
+    source
+        .set_ttl(&KeyValue::new("alice::session", "s"), ttl)
+        .await?;
+    source
+        .list_append_ttl(&KeyValue::new("alice::recent", "r"), ttl)
+        .await?;
+
+    let mut changes = dest.watch(&Pattern::default(), 0).await?;
+    let wanted = |k: &str| k.starts_with("alice::");
+    let migration = Migration::new("alice", &source, &dest, wanted);
+    migration.run(&MemCheckpoints::default()).await?;
+    assert_migrated(&source, &dest, wanted).await?;
+    // the list only appended to gets the new element, the other one is
+    // changed from the first element which differs
+    let mut grown = vec![];
+    while let Some(Some(change)) = changes.next().now_or_never() {
+        let change = change?;
+        if change.key == "alice::grown" {
+            grown.push((change.kind, change.value));
+        }
+    }
+    assert_eq!(vec![(ChangeKind::ListAppend, "c".to_string())], grown);
+
+    // TTLs are not migrated
+    tokio::time::sleep(ttl).await;
+    assert_eq!(None, source.get("alice::session").await?);
+    assert_eq!(Some("s".to_string()), dest.get("alice::session").await?);
+    assert!(source.list_get("alice::recent").await?.0.is_empty());
+    assert_eq!(vec!["r"], dest.list_get("alice::recent").await?.0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_migration_concurrent_writes() -> TribResult<()> {
+    let source = Arc::new(MemStorage::new());
+    let dest = MemStorage::new();
+    fill(&source, 300).await?;
+
+    // writes until stopped, to keys copied already, being copied or not
+    // yet, and new ones
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let (source, stop) = (source.clone(), stop.clone());
+        tokio::spawn(async move {
+            let mut i = 0;
+            while !stop.load(Ordering::SeqCst) {
+                for bin in ["alice", "bob"] {
+                    let key = format!("{}::k{:04}", bin, i % 400);
+                    source.set(&KeyValue::new(&key, &format!("w{}", i))).await?;
+                    let list = format!("{}::l{:04}", bin, i % 400);
+                    source.list_append(&KeyValue::new(&list, "w")).await?;
+                    if i % 3 == 0 {
+                        source.list_remove(&KeyValue::new(&list, "x")).await?;
+                    }
+                }
+                i += 1;
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            TribResult::Ok(())
+        })
+    };
+
+    let wanted = |k: &str| k.starts_with("alice::");
+    let migration = Migration::new("alice", &*source, &dest, wanted);
+    let checkpoints = MemCheckpoints::default();
+    let stats = migration.run(&checkpoints).await?;
+    // the replay of the tail ends while the source is still written to
+    assert!(!writer.is_finished());
+    assert!(stats.replayed > 0);
+    assert_eq!(Phase::Tail, checkpoints.load("alice").await?.unwrap().phase);
+
+    // once the writes stopped, as they would once the range moved over,
+    // running it again copies those made in between
+    stop.store(true, Ordering::SeqCst);
+    writer.await??;
+    migration.run(&checkpoints).await?;
+    assert_migrated(&source, &dest, wanted).await?;
+
+    // running it again has nothing left to copy
+    let stats = migration.run(&checkpoints).await?;
+    assert_eq!((0, 0, 0), (stats.values, stats.lists, stats.replayed));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_migration_other_writes() -> TribResult<()> {
+    let source = Arc::new(MemStorage::new());
+    let dest = MemStorage::new();
+    fill(&source, 10).await?;
+
+    // writes to keys out of the range never stop
+    let writer = {
+        let source = source.clone();
+        tokio::spawn(async move {
+            for i in 0.. {
+                let key = format!("bob::k{:04}", i % 10);
+                source.set(&KeyValue::new(&key, &i.to_string())).await?;
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            TribResult::Ok(())
+        })
+    };
+    let wanted = |k: &str| k.starts_with("alice::");
+    let migration = Migration::new("alice", &*source, &dest, wanted);
+    let checkpoints = MemCheckpoints::default();
+    let stats = tokio::time::timeout(Duration::from_secs(5), migration.run(&checkpoints))
+        .await
+        .map_err(|_| "the run did not end while other keys were written")??;
+    writer.abort();
+    assert_eq!((10, 10, 0), (stats.values, stats.lists, stats.replayed));
+    assert_migrated(&source, &dest, wanted).await?;
+    Ok(())
+}
+
+/// checkpoints which fail to save after a number of saves, as if the keeper
+/// running the migration failed
+struct Failing {
+    saved: MemCheckpoints,
+    left: AtomicUsize,
+}
+
+#[async_trait]
+impl Checkpoints for Failing {
+    async fn load(&self, migration: &str) -> TribResult<Option<Checkpoint>> {
+        self.saved.load(migration).await
+    }
+
+    async fn save(&self, migration: &str, checkpoint: Option<&Checkpoint>) -> TribResult<()> {
+        if self.left.fetch_sub(1, Ordering::SeqCst) == 0 {
+            return Err(Box::new(TribblerError::Unknown(
+                "keeper failed".to_string(),
+            )));
+        }
+        self.saved.save(migration, checkpoint).await
+    }
+}
+
+#[tokio::test]
+async fn test_migration_resume() -> TribResult<()> {
+    let (source, dest) = (MemStorage::new(), MemStorage::new());
+    fill(&source, 250).await?;
+    let wanted = |k: &str| k.starts_with("alice::");
+    let migration = Migration::new("alice", &source, &dest, wanted);
+
+    // the place in the watch and the first 100 values are saved, and the
+    // run fails saving the next 100
+    let failing = Failing {
+        saved: MemCheckpoints::default(),
+        left: AtomicUsize::new(2),
+    };
+    assert!(migration.run(&failing).await.is_err());
+    let saved = failing.saved.load("alice").await?.unwrap();
+    assert_eq!(Phase::Values, saved.phase);
+    assert_eq!("alice::k0099", saved.after);
+
+    // written while nobody was migrating, to a key copied already
+    source.set(&KeyValue::new("alice::k0000", "new")).await?;
+    let again = Migration::new("alice", &source, &dest, wanted);
+    let stats = again.run(&failing.saved).await?;
+    assert_eq!((150, 250, 1), (stats.values, stats.lists, stats.replayed));
+    assert_migrated(&source, &dest, wanted).await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_migration_raft_checkpoints() -> TribResult<()> {
+    let cfg = Config {
+        keepers: vec!["127.0.0.1:0".to_string()],
+        ..Default::default()
+    };
+    let kc = cfg.keeper_config(0, None, None)?;
+    let node = RaftNode::new(&kc, Box::new(MemStorage::new())).await?;
+    tokio::spawn(node.clone().run());
+    let start = Instant::now();
+    while !node.is_leader().await {
+        assert!(start.elapsed() < Duration::from_secs(10), "no leader");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let (source, dest) = (MemStorage::new(), MemStorage::new());
+    fill(&source, 5).await?;
+    let migration = Migration::new("all", &source, &dest, |_| true);
+    migration.run(&node).await?;
+    let saved = node.load("all").await?.unwrap();
+    assert_eq!(Phase::Tail, saved.phase);
+    assert!(node.state().checkpoints.contains_key("all"));
+    migration.finish(&node).await?;
+    assert_eq!(None, node.load("all").await?);
+    assert_migrated(&source, &dest, |k| !k.starts_with("@")).await?;
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use std::time::Duration;
+
+use lab::{
+    lab1::builder::{ClientBuilder, RetryPolicy},
+    lab2::{self, quorum::QuorumClient},
+};
+use tribbler::{
+    config::{AuthConfig, ClientConfig, QuorumConfig},
+    err::{ErrorCode, TribResult},
+    ring::{Ring, DEFAULT_VNODES},
+    storage::{BinStorage, KeyList, KeyString, Pattern, Storage},
+};
+
+mod common;
+
+use common::{kv, serve, BackBuilder};
+
+/// indices of the back-ends holding `bin`, in ring order
+fn replicas(addrs: &[String], bin: &str, n: usize) -> Vec<usize> {
+    Ring::new(addrs, DEFAULT_VNODES).successors(bin, n)
+}
+
+fn quorum(n: usize, r: usize, w: usize) -> QuorumConfig {
+    QuorumConfig { n, r, w }
+}
+
+fn client(addrs: &[String], q: QuorumConfig) -> TribResult<QuorumClient> {
+    QuorumClient::new(addrs, &ClientBuilder::new().retry(RetryPolicy::none()), q)
+}
+
+fn code<T>(r: TribResult<T>) -> ErrorCode {
+    match r {
+        Err(e) => ErrorCode::of(&*e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_quorum_basic() -> TribResult<()> {
+    let (_backs, addrs) = serve(3).await?;
+    let bc = lab2::new_quorum_bin_client(addrs, &ClientConfig::default(), quorum(3, 2, 2)).await?;
+    let alice = bc.bin("alice").await?;
+    let bob = bc.bin("bob").await?;
+
+    assert_eq!(None, alice.get("k").await?);
+    assert!(alice.set(&kv("k", "v1")).await?);
+    assert_eq!(Some("v1".to_string()), alice.get("k").await?);
+    assert_eq!(None, bob.get("k").await?);
+    assert!(alice.set(&kv("k", "v2")).await?);
+    assert_eq!(Some("v2".to_string()), alice.get("k").await?);
+    assert_eq!(
+        vec!["k".to_string()],
+        alice.keys(&Pattern::default()).await?.0
+    );
+    assert!(alice.set(&kv("k", "")).await?);
+    assert_eq!(None, alice.get("k").await?);
+    assert!(alice.keys(&Pattern::default()).await?.0.is_empty());
+
+    assert_eq!(
+        (true, Some("a".to_string())),
+        alice.cas(&kv("c", "a"), "").await?
+    );
+    assert_eq!(
+        (false, Some("a".to_string())),
+        alice.cas(&kv("c", "b"), "x").await?
+    );
+    assert_eq!(5, alice.incr("n", 5).await?);
+    assert_eq!(3, alice.incr("n", -2).await?);
+    assert_eq!(ErrorCode::Invalid, code(alice.incr("c", 1).await));
+
+    for v in ["a", "b", "a", "c"] {
+        assert!(alice.list_append(&kv("l", v)).await?);
+    }
+    assert_eq!(vec!["a", "b", "a", "c"], alice.list_get("l").await?.0);
+    assert_eq!(2, alice.list_remove(&kv("l", "a")).await?);
+    assert_eq!(vec!["b", "c"], alice.list_get("l").await?.0);
+    assert_eq!(
+        vec!["l".to_string()],
+        alice.list_keys(&Pattern::default()).await?.0
+    );
+    assert_eq!(1, alice.list_remove(&kv("l", "b")).await?);
+    assert_eq!(1, alice.list_remove(&kv("l", "c")).await?);
+    assert!(alice.list_keys(&Pattern::default()).await?.0.is_empty());
+
+    let c1 = alice.clock(0).await?;
+    let c2 = bob.clock(c1 + 10).await?;
+    assert!(c2 >= c1 + 10);
+    assert!(alice.clock(0).await? > c2);
+
+    assert_eq!(
+        ErrorCode::Invalid,
+        code(bc.bin(lab2::quorum::HINTS_BIN).await)
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_quorum_config() -> TribResult<()> {
+    let addrs = vec!["127.0.0.1:3000".to_string(), "127.0.0.1:3001".to_string()];
+    for q in [
+        quorum(3, 2, 2),
+        quorum(0, 1, 1),
+        quorum(2, 3, 1),
+        quorum(2, 1, 0),
+    ] {
+        assert_eq!(ErrorCode::Invalid, code(client(&addrs, q)));
+    }
+    client(&addrs, quorum(2, 1, 2))?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_quorum_replica_down() -> TribResult<()> {
+    let (mut backs, addrs) = serve(3).await?;
+    let bc = client(&addrs, quorum(3, 2, 2))?;
+    let alice = bc.bin("alice").await?;
+    alice.set(&kv("k", "v1")).await?;
+    alice.list_append(&kv("l", "a")).await?;
+    alice.list_append(&kv("l", "b")).await?;
+
+    let down = replicas(&addrs, "alice", 3)[0];
+    backs[down].stop().await?;
+    assert_eq!(Some("v1".to_string()), alice.get("k").await?);
+    alice.set(&kv("k", "v2")).await?;
+    assert_eq!(Some("v2".to_string()), alice.get("k").await?);
+    // the removal is kept as a tombstone, so the replica which missed it
+    // does not bring the element back
+    assert_eq!(1, alice.list_remove(&kv("l", "a")).await?);
+    backs[down].start().await?;
+    assert_eq!(vec!["b"], alice.list_get("l").await?.0);
+    assert_eq!(Some("v2".to_string()), alice.get("k").await?);
+
+    // with every other replica down too, there is no quorum left
+    for &i in replicas(&addrs, "alice", 3)[1..].iter() {
+        backs[i].stop().await?;
+    }
+    assert_eq!(ErrorCode::Unavailable, code(alice.get("k").await));
+    assert_eq!(
+        ErrorCode::Unavailable,
+        code(alice.set(&kv("k", "v3")).await)
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_quorum_refusing_replica() -> TribResult<()> {
+    let (_backs, mut addrs) = serve(2).await?;
+    // a third back-end which refuses every call of this client, which has
+    // no token
+    let refusing = BackBuilder::new()
+        .auth(AuthConfig {
+            read_write: vec!["front".to_string()],
+            ..Default::default()
+        })
+        .start()
+        .await?;
+    addrs.push(refusing.addr);
+
+    // the refusal counts as a missing replica, so quorums of two are met
+    let alice = client(&addrs, quorum(3, 2, 2))?.bin("alice").await?;
+    alice.set(&kv("k", "v1")).await?;
+    assert_eq!(Some("v1".to_string()), alice.get("k").await?);
+    alice.list_append(&kv("l", "a")).await?;
+    assert_eq!(vec!["a"], alice.list_get("l").await?.0);
+
+    // while a quorum of three fails the way the replica refused the call
+    let alice = client(&addrs, quorum(3, 3, 3))?.bin("alice").await?;
+    assert_eq!(ErrorCode::Invalid, code(alice.get("k").await));
+    assert_eq!(ErrorCode::Invalid, code(alice.set(&kv("k", "v2")).await));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_quorum_read_repair() -> TribResult<()> {
+    let (backs, addrs) = serve(3).await?;
+    let bc = client(&addrs, quorum(3, 3, 2))?;
+    let alice = bc.bin("alice").await?;
+    alice.set(&kv("k", "v1")).await?;
+    alice.list_append(&kv("l", "a")).await?;
+
+    // one replica lost its copy
+    let stale = &backs[replicas(&addrs, "alice", 3)[2]];
+    stale.bin("alice").set(&kv("k", "")).await?;
+    let entries = stale.bin("alice").list_get("l").await?.0;
+    stale
+        .bin("alice")
+        .list_remove(&kv("l", &entries[0]))
+        .await?;
+
+    assert_eq!(Some("v1".to_string()), alice.get("k").await?);
+    assert_eq!(vec!["a"], alice.list_get("l").await?.0);
+    // and got it back from the others
+    assert!(stale.bin("alice").get("k").await?.unwrap().ends_with(":v1"));
+    assert_eq!(entries, stale.bin("alice").list_get("l").await?.0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_quorum_hinted_handoff() -> TribResult<()> {
+    let (mut backs, addrs) = serve(4).await?;
+    let bc = client(&addrs, quorum(3, 2, 3))?;
+    let alice = bc.bin("alice").await?;
+    let order = replicas(&addrs, "alice", 4);
+    let (down, fallback) = (order[1], order[3]);
+
+    backs[down].stop().await?;
+    // the fallback takes the writes for the replica which is down, so even
+    // a write quorum of all three replicas is met
+    alice.set(&kv("k", "v1")).await?;
+    alice.list_append(&kv("l", "a")).await?;
+    alice
+        .set_ttl(&kv("gone", "soon"), Duration::from_millis(100))
+        .await?;
+    let hints = backs[fallback].bin(lab2::quorum::HINTS_BIN);
+    assert_eq!(
+        vec![addrs[down].clone()],
+        hints.list_keys(&Pattern::default()).await?.0
+    );
+    assert_eq!(0, bc.handoff().await?);
+
+    backs[down].start().await?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    // the expired write is dropped rather than delivered
+    assert_eq!(2, bc.handoff().await?);
+    assert!(hints.list_keys(&Pattern::default()).await?.0.is_empty());
+    let replica = backs[down].bin("alice");
+    assert!(replica.get("k").await?.unwrap().ends_with(":v1"));
+    assert_eq!(1, replica.list_get("l").await?.0.len());
+    assert_eq!(None, replica.get("gone").await?);
+    assert_eq!(0, bc.handoff().await?);
+    Ok(())
+}
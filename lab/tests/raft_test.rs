@@ -0,0 +1,272 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use lab::lab2::{
+    raft::{Command, KeeperState, Membership, RaftNode},
+    raft_sim::Sim,
+};
+use tonic::transport::Server;
+use tribbler::{
+    addr::rand::rand_port,
+    config::Config,
+    err::{TribResult, TribblerError},
+    storage::MemStorage,
+};
+
+fn view(version: u64, alive: &[usize]) -> Command {
+    Command::Membership(Membership {
+        version,
+        alive: alive.to_vec(),
+    })
+}
+
+/// runs `sim` until a leader is elected, and returns it
+async fn elect(sim: &mut Sim) -> TribResult<usize> {
+    for _ in 0..100 {
+        sim.tick().await?;
+        if let Some(leader) = sim.leader() {
+            return Ok(leader);
+        }
+    }
+    Err(Box::new(TribblerError::Unknown(
+        "no leader elected".to_string(),
+    )))
+}
+
+fn assert_same_state(sim: &Sim, nodes: &[usize]) {
+    for &i in nodes {
+        assert_eq!(sim.state(nodes[0]), sim.state(i), "node {}", i);
+    }
+}
+
+#[tokio::test]
+async fn test_raft_elects_one_leader() -> TribResult<()> {
+    for seed in 0..10 {
+        let mut sim = Sim::new(5, seed);
+        elect(&mut sim).await?;
+        sim.run(50).await?;
+        // node 0 started first, so whoever won the election handed over to
+        // it, which holds on while it hears from the others
+        let leader = 0;
+        assert_eq!(Some(leader), sim.leader());
+        let term = sim.node(leader).unwrap().term();
+        for i in 0..5 {
+            let node = sim.node(i).unwrap();
+            assert_eq!(term, node.term());
+            assert_eq!(Some(leader), node.leader());
+            assert_eq!(i == leader, node.is_leader());
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raft_replicates_commands() -> TribResult<()> {
+    let mut sim = Sim::new(3, 7);
+    let leader = elect(&mut sim).await?;
+    let follower = (leader + 1) % 3;
+    assert_eq!(None, sim.propose(follower, Command::Watermark(1)).await?);
+    sim.propose(leader, view(1, &[0, 1, 2])).await?;
+    sim.propose(
+        leader,
+        Command::Checkpoint {
+            migration: "join-3".to_string(),
+            progress: "alice".to_string(),
+        },
+    )
+    .await?;
+    sim.propose(leader, Command::Watermark(42)).await?;
+    sim.run(5).await?;
+    assert_same_state(&sim, &[0, 1, 2]);
+    let state = sim.state(0);
+    assert_eq!(vec![0, 1, 2], state.membership.alive);
+    assert_eq!(
+        Some("alice"),
+        state.checkpoints.get("join-3").map(|s| s.as_str())
+    );
+    assert_eq!(42, state.watermark);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raft_partitioned_leader() -> TribResult<()> {
+    let mut sim = Sim::new(5, 3);
+    let old = elect(&mut sim).await?;
+    sim.propose(old, Command::Watermark(1)).await?;
+    sim.run(5).await?;
+
+    // the leader is cut off with one follower: what it appends now is never
+    // committed, while the majority elects a leader of its own
+    let minority = [old, (old + 1) % 5];
+    let majority = (2..5).map(|k| (old + k) % 5).collect::<Vec<_>>();
+    sim.partition(&[&minority, &majority]);
+    let (index, _) = sim.propose(old, Command::Watermark(100)).await?.unwrap();
+    sim.run(60).await?;
+    assert!(sim.node(old).unwrap().commit() < index);
+    assert!(!sim.node(old).unwrap().is_leader());
+    let new = sim.leader().unwrap();
+    assert!(majority.contains(&new));
+    sim.propose(new, view(1, &[1, 2])).await?;
+    sim.run(5).await?;
+    assert_same_state(&sim, &majority);
+    assert_eq!(1, sim.state(new).membership.version);
+
+    // once healed, the minority takes the majority's log, and the entry
+    // which was never committed is gone
+    sim.heal();
+    sim.run(60).await?;
+    let leader = sim.leader().unwrap();
+    sim.propose(leader, Command::Watermark(2)).await?;
+    sim.run(5).await?;
+    assert_same_state(&sim, &[0, 1, 2, 3, 4]);
+    assert_eq!(2, sim.state(old).watermark);
+    assert_eq!(1, sim.state(old).membership.version);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raft_older_node_takes_over() -> TribResult<()> {
+    let mut sim = Sim::new(3, 13);
+    sim.partition(&[&[1, 2]]);
+    let leader = elect(&mut sim).await?;
+    assert_ne!(0, leader);
+    sim.propose(leader, Command::Watermark(4)).await?;
+    let term = sim.node(leader).unwrap().term();
+
+    // the oldest node is back: it catches up, then leads in a newer term,
+    // and nothing committed is lost
+    sim.heal();
+    sim.run(30).await?;
+    assert_eq!(Some(0), sim.leader());
+    assert!(sim.node(0).unwrap().term() > term);
+    assert!(!sim.node(leader).unwrap().is_leader());
+    assert_eq!(4, sim.state(0).watermark);
+
+    // once restarted, it is the youngest, so the next oldest takes over for
+    // good
+    sim.crash(0);
+    sim.run(60).await?;
+    sim.restart(0).await?;
+    sim.run(60).await?;
+    assert_eq!(Some(1), sim.leader());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raft_no_quorum() -> TribResult<()> {
+    let mut sim = Sim::new(3, 11);
+    elect(&mut sim).await?;
+    sim.partition(&[]);
+    sim.run(100).await?;
+    // nobody can be elected on their own, however long they try
+    assert_eq!(None, sim.leader());
+    sim.heal();
+    elect(&mut sim).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raft_crash_and_restart() -> TribResult<()> {
+    let mut sim = Sim::new(3, 5);
+    let leader = elect(&mut sim).await?;
+    sim.propose(leader, view(1, &[0])).await?;
+
+    // a follower which missed entries while down catches up
+    let follower = (leader + 1) % 3;
+    sim.crash(follower);
+    sim.propose(leader, Command::Watermark(9)).await?;
+    sim.run(5).await?;
+    sim.restart(follower).await?;
+    sim.run(5).await?;
+    assert_same_state(&sim, &[0, 1, 2]);
+    assert_eq!(9, sim.state(follower).watermark);
+
+    // everyone restarting at once loses nothing committed
+    let before = sim.state(leader).clone();
+    for i in 0..3 {
+        sim.crash(i);
+    }
+    for i in 0..3 {
+        sim.restart(i).await?;
+    }
+    assert_eq!(&KeeperState::default(), sim.state(0));
+    let leader = elect(&mut sim).await?;
+    sim.run(5).await?;
+    assert_same_state(&sim, &[0, 1, 2]);
+    assert_eq!(before.membership, sim.state(leader).membership);
+    assert_eq!(before.watermark, sim.state(leader).watermark);
+    Ok(())
+}
+
+/// waits until `f` holds, for up to 5 seconds
+async fn wait_until<F: Fn() -> bool>(what: &str, f: F) -> TribResult<()> {
+    let start = Instant::now();
+    while !f() {
+        if start.elapsed() > Duration::from_secs(5) {
+            return Err(Box::new(TribblerError::Unknown(format!(
+                "timed out waiting for {}",
+                what
+            ))));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_raft_nodes_over_rpc() -> TribResult<()> {
+    let cfg = Config {
+        keepers: (0..3)
+            .map(|_| format!("127.0.0.1:{}", rand_port()))
+            .collect(),
+        ..Default::default()
+    };
+    let mut nodes = vec![];
+    let mut stores = vec![];
+    for i in 0..3 {
+        let kc = cfg.keeper_config(i, None, None)?;
+        let storage = Arc::new(MemStorage::new());
+        let node = RaftNode::new(&kc, Box::new(storage.clone())).await?;
+        let addr = kc.addr().parse()?;
+        tokio::spawn(Server::builder().add_service(node.service()).serve(addr));
+        tokio::spawn(node.clone().run());
+        nodes.push(node);
+        stores.push(storage);
+    }
+
+    // leadership settles on node 0, whose incarnation is the oldest
+    let start = Instant::now();
+    let leader = loop {
+        let mut leading = vec![];
+        for (i, node) in nodes.iter().enumerate() {
+            if node.is_leader().await {
+                leading.push(i);
+            }
+        }
+        if let [0] = leading[..] {
+            break 0;
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "no leader");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    assert!(nodes[(leader + 1) % 3]
+        .propose(Command::Watermark(1))
+        .await
+        .is_err());
+    nodes[leader].propose(view(3, &[0, 2])).await?;
+    nodes[leader].propose(Command::Watermark(5)).await?;
+    wait_until("the followers to apply", || {
+        nodes.iter().all(|n| n.state() == nodes[leader].state())
+    })
+    .await?;
+    assert_eq!(5, nodes[0].state().watermark);
+
+    // what a node persisted is there for it when it starts again
+    let kc = cfg.keeper_config(leader, None, None)?;
+    let (_, log) = lab::lab2::raft::load(&*stores[leader]).await?;
+    assert!(log.len() >= 3);
+    RaftNode::new(&kc, Box::new(stores[leader].clone())).await?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::{fs, path::Path};
+
+use lab::{lab1, lab2};
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+use tribbler::{
+    config::{ClientConfig, TlsConfig},
+    err::TribResult,
+};
+
+mod common;
+
+use common::{kv, BackBuilder};
+
+/// writes a fresh certificate authority, and a certificate for `localhost`
+/// signed by it, to `dir` and returns the TLS settings using them
+fn make_certs(dir: &Path, mutual: bool) -> TribResult<TlsConfig> {
+    fs::create_dir_all(dir)?;
+    let mut ca_params = CertificateParams::new(vec![]);
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = Certificate::from_params(ca_params)?;
+    let leaf = Certificate::from_params(CertificateParams::new(vec!["localhost".to_string()]))?;
+
+    let write = |name: &str, pem: String| -> TribResult<String> {
+        let path = dir.join(name);
+        fs::write(&path, pem)?;
+        Ok(path.to_string_lossy().to_string())
+    };
+    Ok(TlsConfig {
+        ca_cert: write("ca.pem", ca.serialize_pem()?)?,
+        cert: write("cert.pem", leaf.serialize_pem_with_signer(&ca)?)?,
+        key: write("key.pem", leaf.serialize_private_key_pem())?,
+        mutual,
+        domain: "localhost".to_string(),
+    })
+}
+
+fn secure(tls: &TlsConfig) -> ClientConfig {
+    ClientConfig {
+        tls: Some(tls.clone()),
+        ..Default::default()
+    }
+}
+
+fn cert_dir() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("trib-tls-{}", rand::random::<u64>()))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_tls() -> TribResult<()> {
+    let dir = cert_dir();
+    let tls = make_certs(&dir, false)?;
+    let back = BackBuilder::new().tls(&tls).shutdown().start().await?;
+    let url = back.url();
+
+    let client = lab1::new_client_with(&url, &secure(&tls)).await?;
+    assert!(client.set(&kv("hello", "world")).await?);
+    assert_eq!(Some("world".to_string()), client.get("hello").await?);
+
+    let plain = lab1::new_client(&url).await?;
+    assert!(plain.get("hello").await.is_err());
+
+    back.stop().await?;
+    let _ = fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_mutual_tls() -> TribResult<()> {
+    let dir = cert_dir();
+    let tls = make_certs(&dir, true)?;
+    let back = BackBuilder::new().tls(&tls).shutdown().start().await?;
+    let url = back.url();
+
+    let client = lab1::new_client_with(&url, &secure(&tls)).await?;
+    assert!(client.set(&kv("hello", "world")).await?);
+    assert_eq!(Some("world".to_string()), client.get("hello").await?);
+
+    // trusts the back-end but has no certificate of its own to present
+    let anonymous = TlsConfig {
+        mutual: false,
+        ..tls.clone()
+    };
+    let client = lab1::new_client_with(&url, &secure(&anonymous)).await?;
+    assert!(client.get("hello").await.is_err());
+
+    let bc = lab2::new_bin_client_with(vec![back.addr.clone()], &secure(&tls)).await?;
+    let bin = bc.bin("alice").await?;
+    assert!(bin.set(&kv("hello", "bin")).await?);
+    assert_eq!(Some("bin".to_string()), bin.get("hello").await?);
+
+    back.stop().await?;
+    let _ = fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_tls_bad_certs() -> TribResult<()> {
+    let tls = TlsConfig {
+        ca_cert: "/no/such/ca.pem".to_string(),
+        ..Default::default()
+    };
+    assert!(BackBuilder::new().tls(&tls).start().await.is_err());
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! module containing the token authentication of the storage RPC service.
+//!
+//! Every call to a back-end carries a bearer token in its `authorization`
+//! metadata. [AuthInterceptor] checks the token on the server, and records
+//! the [Role] it grants in the request's extensions, where the service looks
+//! it up with [role] before running calls that modify storage.
+//! [TokenInterceptor] attaches the token on the client.
+//!
+//! Tokens are configured with [crate::config::AuthConfig].
+
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::Interceptor,
+    Request, Status,
+};
+
+use crate::{
+    config::AuthConfig,
+    err::{TribResult, TribblerError},
+};
+
+/// name of the metadata entry carrying the token
+pub const AUTH_METADATA: &str = "authorization";
+
+/// name of the environment variable which holds the token clients present,
+/// overriding [AuthConfig::token]
+pub const TOKEN_ENV: &str = "TRIB_TOKEN";
+
+/// What a token is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// may only make calls which leave storage as it is, e.g. for monitoring
+    ReadOnly,
+    /// may make every call, e.g. for front-ends and keepers
+    ReadWrite,
+}
+
+/// Returns the [Role] that [AuthInterceptor] granted to a request. Requests
+/// to a server without authentication are granted [Role::ReadWrite].
+pub fn role<T>(request: &Request<T>) -> Role {
+    request
+        .extensions()
+        .get::<Role>()
+        .copied()
+        .unwrap_or(Role::ReadWrite)
+}
+
+/// Fails with [Status::permission_denied] unless the request may modify
+/// storage.
+#[allow(clippy::result_large_err)] // for use with `?` in RPC handlers
+pub fn require_write<T>(request: &Request<T>) -> Result<(), Status> {
+    match role(request) {
+        Role::ReadWrite => Ok(()),
+        Role::ReadOnly => Err(Status::permission_denied("token is only allowed to read")),
+    }
+}
+
+/// Server interceptor which rejects requests without a known token. With
+/// no [AuthConfig], every request is let through.
+#[derive(Debug, Clone, Default)]
+pub struct AuthInterceptor {
+    auth: Option<AuthConfig>,
+}
+
+impl AuthInterceptor {
+    pub fn new(auth: Option<AuthConfig>) -> AuthInterceptor {
+        AuthInterceptor { auth }
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let auth = match &self.auth {
+            Some(auth) => auth,
+            None => return Ok(request),
+        };
+        let token = request
+            .metadata()
+            .get(AUTH_METADATA)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing token"))?;
+        let role = auth
+            .role(token)
+            .ok_or_else(|| Status::unauthenticated("unknown token"))?;
+        request.extensions_mut().insert(role);
+        Ok(request)
+    }
+}
+
+/// Client interceptor which attaches a token to every request. With no
+/// token, requests are sent as they are.
+#[derive(Debug, Clone, Default)]
+pub struct TokenInterceptor {
+    value: Option<MetadataValue<Ascii>>,
+}
+
+impl TokenInterceptor {
+    /// Fails if the token cannot be sent as metadata, e.g. when it contains
+    /// a newline.
+    pub fn new(token: Option<&str>) -> TribResult<TokenInterceptor> {
+        let value =
+            match token {
+                Some(t) => Some(MetadataValue::from_str(&format!("Bearer {}", t)).map_err(
+                    |_| TribblerError::Unknown("token is not valid metadata".to_string()),
+                )?),
+                None => None,
+            };
+        Ok(TokenInterceptor { value })
+    }
+}
+
+impl Interceptor for TokenInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.value {
+            request.metadata_mut().insert(AUTH_METADATA, value.clone());
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tonic::{service::Interceptor, Code, Request};
+
+    use super::{role, AuthInterceptor, Role, TokenInterceptor};
+    use crate::config::AuthConfig;
+
+    fn auth() -> AuthInterceptor {
+        AuthInterceptor::new(Some(AuthConfig {
+            read_write: vec!["rw".to_string()],
+            read_only: vec!["ro".to_string()],
+            token: String::new(),
+        }))
+    }
+
+    fn call(token: Option<&str>) -> Result<Role, Code> {
+        let request = TokenInterceptor::new(token)
+            .unwrap()
+            .call(Request::new(()))
+            .unwrap();
+        match auth().call(request) {
+            Ok(r) => Ok(role(&r)),
+            Err(status) => Err(status.code()),
+        }
+    }
+
+    #[test]
+    fn auth_roles() {
+        assert_eq!(Ok(Role::ReadWrite), call(Some("rw")));
+        assert_eq!(Ok(Role::ReadOnly), call(Some("ro")));
+        assert_eq!(Err(Code::Unauthenticated), call(Some("nope")));
+        assert_eq!(Err(Code::Unauthenticated), call(None));
+        let open = AuthInterceptor::new(None).call(Request::new(())).unwrap();
+        assert_eq!(Role::ReadWrite, role(&open));
+        assert!(TokenInterceptor::new(Some("bad\ntoken")).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! module containing the per-call options of storage operations.
+//!
+//! The [crate::storage] traits take no options of their own. Instead, a
+//! caller runs a piece of work inside [CallOptions::scope], and every storage
+//! call made by that work, on the same task, picks up the options with
+//! [CallOptions::current]. RPC clients pass them on to the back-end as
+//! `grpc-timeout` and [REQUEST_ID_METADATA] metadata, where the server makes
+//! them current again while it runs the call, so they follow a request
+//! through every layer it crosses.
+//!
+//! ```
+//! # use std::time::Duration;
+//! # use tribbler::{call::CallOptions, storage::{KeyString, MemStorage}};
+//! # #[tokio::main]
+//! # async fn main() -> tribbler::err::TribResult<()> {
+//! let storage = MemStorage::new();
+//! let value = CallOptions::new()
+//!     .timeout(Duration::from_secs(1))
+//!     .request_id("req-42")
+//!     .scope(async { storage.get("hello").await })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use crate::err::{TribResult, TribblerError};
+
+/// name of the metadata entry carrying [CallOptions::request_id]
+pub const REQUEST_ID_METADATA: &str = "x-request-id";
+
+tokio::task_local! {
+    static OPTIONS: CallOptions;
+}
+
+/// Options of the storage calls made within [CallOptions::scope]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallOptions {
+    /// the time by which calls must be done. Callers give up on calls still
+    /// running then, failing with [crate::err::TribblerError::Timeout], and
+    /// calls reaching a back-end after it are refused, see
+    /// [CallOptions::run].
+    pub deadline: Option<Instant>,
+    /// an identifier to find the calls made for one request in logs
+    pub request_id: Option<String>,
+}
+
+impl CallOptions {
+    /// options with no deadline and no request id
+    pub fn new() -> CallOptions {
+        CallOptions::default()
+    }
+
+    /// Sets the deadline to `timeout` from now.
+    pub fn timeout(self, timeout: Duration) -> CallOptions {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    /// Sets the deadline.
+    pub fn deadline(mut self, deadline: Instant) -> CallOptions {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the request id.
+    pub fn request_id(mut self, id: impl Into<String>) -> CallOptions {
+        self.request_id = Some(id.into());
+        self
+    }
+
+    /// Returns the time left until the deadline, if there is one. The time
+    /// left is zero once the deadline has passed.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|d| d.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns whether the deadline has passed.
+    pub fn expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Returns the options of the scope the current task is running in, or
+    /// empty options outside of any scope.
+    pub fn current() -> CallOptions {
+        OPTIONS.try_with(|o| o.clone()).unwrap_or_default()
+    }
+
+    /// Runs `f` with these options as the [CallOptions::current] ones.
+    ///
+    /// Scopes nest: the inner scope keeps the earlier of the two deadlines,
+    /// and the outer request id unless it sets one of its own.
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        let outer = CallOptions::current();
+        let options = CallOptions {
+            deadline: match (outer.deadline, self.deadline) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            request_id: self.request_id.or(outer.request_id),
+        };
+        OPTIONS.scope(options, f).await
+    }
+
+    /// Runs `f` like [CallOptions::scope] if the deadline has not passed
+    /// yet, and fails with [TribblerError::Timeout] without starting it
+    /// otherwise. The deadline only decides whether `f` starts.
+    pub async fn run<T, F>(self, f: F) -> TribResult<T>
+    where
+        F: Future<Output = TribResult<T>>,
+    {
+        self.scope(async {
+            CallOptions::current().check()?;
+            f.await
+        })
+        .await
+    }
+
+    /// Same as [CallOptions::run], but once started, `f` runs to completion
+    /// on a task of its own even if the caller stops waiting for it, as
+    /// dropping a write half-way through could leave storage in a state no
+    /// caller asked for.
+    pub async fn spawn<T, F>(self, f: F) -> TribResult<T>
+    where
+        T: Send + 'static,
+        F: Future<Output = TribResult<T>> + Send + 'static,
+    {
+        self.scope(async {
+            let options = CallOptions::current();
+            options.check()?;
+            tokio::spawn(OPTIONS.scope(options, f))
+                .await
+                .map_err(|e| e.to_string())?
+        })
+        .await
+    }
+
+    /// fails with [TribblerError::Timeout] once the deadline has passed
+    fn check(&self) -> TribResult<()> {
+        match self.expired() {
+            true => Err(Box::new(TribblerError::Timeout(
+                "deadline passed before the call".to_string(),
+            ))),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Parses the value of a `grpc-timeout` header, e.g. `250m` for 250
+/// milliseconds.
+pub fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use super::{parse_grpc_timeout, CallOptions};
+    use crate::err::TribblerError;
+
+    #[test]
+    fn call_grpc_timeout() {
+        assert_eq!(Some(Duration::from_millis(250)), parse_grpc_timeout("250m"));
+        assert_eq!(Some(Duration::from_secs(7200)), parse_grpc_timeout("2H"));
+        assert_eq!(Some(Duration::from_nanos(5)), parse_grpc_timeout("5n"));
+        assert_eq!(None, parse_grpc_timeout("5"));
+        assert_eq!(None, parse_grpc_timeout("5x"));
+        assert_eq!(None, parse_grpc_timeout("123456789S"));
+    }
+
+    #[tokio::test]
+    async fn call_scope() {
+        assert_eq!(CallOptions::new(), CallOptions::current());
+        let soon = Instant::now() + Duration::from_secs(1);
+        let later = soon + Duration::from_secs(1);
+        let (outer, inner) = CallOptions::new()
+            .deadline(soon)
+            .request_id("a")
+            .scope(async {
+                let inner = CallOptions::new()
+                    .deadline(later)
+                    .scope(async { CallOptions::current() })
+                    .await;
+                (CallOptions::current(), inner)
+            })
+            .await;
+        assert_eq!(Some(soon), outer.deadline);
+        assert_eq!(Some(soon), inner.deadline);
+        assert_eq!(Some("a".to_string()), inner.request_id);
+        assert!(!inner.expired());
+        assert!(CallOptions::new().timeout(Duration::ZERO).expired());
+        assert_eq!(CallOptions::new(), CallOptions::current());
+    }
+
+    #[tokio::test]
+    async fn call_run() {
+        let started = Arc::new(AtomicBool::new(false));
+        let late = {
+            let started = started.clone();
+            CallOptions::new()
+                .deadline(Instant::now())
+                .run(async move {
+                    started.store(true, Ordering::SeqCst);
+                    Ok(())
+                })
+                .await
+        };
+        match late.unwrap_err().downcast_ref::<TribblerError>() {
+            Some(TribblerError::Timeout(_)) => (),
+            e => panic!("expected a timeout, got {:?}", e),
+        }
+        assert!(!started.load(Ordering::SeqCst));
+
+        let fast = CallOptions::new()
+            .timeout(Duration::from_secs(5))
+            .request_id("a")
+            .run(async { Ok(CallOptions::current().request_id) })
+            .await;
+        assert_eq!(Some("a".to_string()), fast.unwrap());
+    }
+
+    #[tokio::test]
+    async fn call_spawn() {
+        let late = CallOptions::new()
+            .deadline(Instant::now())
+            .spawn(async { Ok(()) })
+            .await;
+        assert!(late.is_err());
+
+        // a started call finishes even when its caller gives up on it
+        let done = Arc::new(AtomicBool::new(false));
+        let slow = {
+            let done = done.clone();
+            CallOptions::new().spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                done.store(true, Ordering::SeqCst);
+                Ok(())
+            })
+        };
+        assert!(tokio::time::timeout(Duration::from_millis(10), slow)
+            .await
+            .is_err());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(done.load(Ordering::SeqCst));
+
+        let fast = CallOptions::new()
+            .request_id("a")
+            .spawn(async { Ok(CallOptions::current().request_id) })
+            .await;
+        assert_eq!(Some("a".to_string()), fast.unwrap());
+    }
+}
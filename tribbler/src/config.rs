@@ -9,8 +9,10 @@ use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Receiver;
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
 
-use crate::err::TribResult;
+use crate::auth::{Role, TOKEN_ENV};
+use crate::err::{ErrorCode, TribResult, TribblerError};
 use crate::storage::Storage;
 
 pub const DEFAULT_CONFIG_LOCATION: &str = "bins.json";
@@ -31,6 +33,13 @@ pub struct BackConfig {
     /// graceful shutdown of the server. If no channel is present, then
     /// no graceful shutdown mechanism needs to be implemented.
     pub shutdown: Option<Receiver<()>>,
+    /// When set, the storage is only served over TLS with these settings.
+    pub tls: Option<TlsConfig>,
+    /// When set, every call must carry one of the tokens listed here.
+    pub auth: Option<AuthConfig>,
+    /// How responses are compressed for clients which accept it. Compressed
+    /// requests are accepted either way.
+    pub compression: Compression,
 }
 
 use std::fmt::Debug;
@@ -41,11 +50,29 @@ impl Debug for BackConfig {
             .field("addr", &self.addr)
             .field("ready", &self.ready)
             .field("shutdown", &self.shutdown)
+            .field("tls", &self.tls)
+            .field("auth", &self.auth)
+            .field("compression", &self.compression)
             .finish()
     }
 }
 
-#[derive(Debug)]
+impl Default for BackConfig {
+    /// an empty [MemStorage](crate::storage::MemStorage) served on no
+    /// address in particular, with no channels and no TLS
+    fn default() -> Self {
+        BackConfig {
+            addr: String::new(),
+            storage: Box::new(crate::storage::MemStorage::new()),
+            ready: None,
+            shutdown: None,
+            tls: None,
+            auth: None,
+            compression: Compression::None,
+        }
+    }
+}
+
 /// Configuration representing a single keeper.
 pub struct KeeperConfig {
     /// The addresses of back-ends
@@ -56,6 +83,11 @@ pub struct KeeperConfig {
     pub this: usize,
     /// Non zero incarnation identifier
     pub id: u128,
+    /// Whether the back-ends run hybrid logical clocks (see [crate::hlc]).
+    /// If so, the keeper should sync the back-end clocks to no less than
+    /// [crate::hlc::now], so that they keep up with wall-clock time even
+    /// when a back-end sees no traffic, see [crate::hlc::sync_floor].
+    pub hybrid_clock: bool,
     /// Send a value when the keeper is ready. The distributed key-value
     /// service should be ready to serve when *any* of the keepers is
     /// ready.
@@ -64,12 +96,57 @@ pub struct KeeperConfig {
     /// graceful shutdown of the server. If no channel is present, then
     /// no graceful shutdown mechanism needs to be implemented.
     pub shutdown: Option<Receiver<()>>,
+    /// When set, the back-ends (and other keepers) must be reached over TLS
+    /// with these settings.
+    pub tls: Option<TlsConfig>,
+    /// When set, the keeper presents [AuthConfig::token] on every call it
+    /// makes to the back-ends.
+    pub auth: Option<AuthConfig>,
+    /// How calls to the back-ends are compressed
+    pub compression: Compression,
+    /// When set, bins are replicated as described, and the keeper should
+    /// keep their replicas in sync.
+    pub quorum: Option<QuorumConfig>,
+    /// Where the keeper persists the state it replicates with the other
+    /// keepers, so that it survives a restart. When [None], the state is
+    /// only kept in memory.
+    pub state: Option<Box<dyn Storage>>,
+}
+
+impl Debug for KeeperConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeeperConfig")
+            .field("backs", &self.backs)
+            .field("addrs", &self.addrs)
+            .field("this", &self.this)
+            .field("id", &self.id)
+            .field("hybrid_clock", &self.hybrid_clock)
+            .field("ready", &self.ready)
+            .field("shutdown", &self.shutdown)
+            .field("tls", &self.tls)
+            .field("auth", &self.auth)
+            .field("compression", &self.compression)
+            .field("quorum", &self.quorum)
+            .finish()
+    }
 }
 
 impl KeeperConfig {
     pub fn addr(&self) -> &str {
         &self.addrs[self.this as usize]
     }
+
+    /// Returns the settings the keeper should connect to the back-ends with.
+    pub fn client_config(&self) -> ClientConfig {
+        ClientConfig {
+            tls: self.tls.clone(),
+            token: self
+                .auth
+                .as_ref()
+                .and_then(|a| a.client_token().map(|t| t.to_string())),
+            compression: self.compression,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -77,6 +154,187 @@ impl KeeperConfig {
 pub struct Config {
     pub backs: Vec<String>,
     pub keepers: Vec<String>,
+    /// Whether the back-ends use hybrid logical clocks instead of plain
+    /// Lamport clocks, see [crate::hlc]
+    #[serde(default)]
+    pub hybrid_clock: bool,
+    /// When set, all RPCs between front-ends, keepers and back-ends go over
+    /// TLS with these settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// When set, back-ends only answer calls carrying one of the tokens
+    /// listed here. See [Config::auth] for how the environment overrides it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthConfig>,
+    /// How calls between front-ends, keepers and back-ends are compressed
+    #[serde(default)]
+    pub compression: Compression,
+    /// When set, every bin is replicated on several back-ends and read and
+    /// written by quorum
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quorum: Option<QuorumConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+/// Replication settings of a quorum bin storage: each bin is stored on `n`
+/// back-ends, a read waits for `r` of them and a write for `w` of them.
+///
+/// With `r + w > n`, every read sees the latest completed write. Smaller
+/// quorums are faster and tolerate more failures, but reads may then return
+/// stale values until replicas are repaired.
+pub struct QuorumConfig {
+    /// number of back-ends each bin is stored on
+    pub n: usize,
+    /// number of replicas a read must hear from
+    pub r: usize,
+    /// number of replicas a write must reach
+    pub w: usize,
+}
+
+impl QuorumConfig {
+    /// Checks that the quorums are possible with `backs` back-ends.
+    pub fn validate(&self, backs: usize) -> TribResult<()> {
+        let msg = if self.n == 0 || self.n > backs {
+            format!("cannot store {} replicas on {} back-ends", self.n, backs)
+        } else if self.r == 0 || self.r > self.n || self.w == 0 || self.w > self.n {
+            format!(
+                "quorums must be between 1 and {} (r = {}, w = {})",
+                self.n, self.r, self.w
+            )
+        } else {
+            return Ok(());
+        };
+        Err(Box::new(TribblerError::Status(ErrorCode::Invalid, msg)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+/// Compression of the messages of storage RPCs
+pub enum Compression {
+    /// messages are sent as they are
+    #[default]
+    None,
+    /// messages are compressed with gzip, which pays off for large lists and
+    /// key sets at the cost of some CPU time
+    Gzip,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Settings of a client connecting to back-ends, see [Config::client_config]
+pub struct ClientConfig {
+    /// see [Config::tls]
+    pub tls: Option<TlsConfig>,
+    /// token presented on every call, see [AuthConfig::token]
+    pub token: Option<String>,
+    /// see [Config::compression]
+    pub compression: Compression,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+/// Paths to the PEM files which secure the RPC connections of a tribbler
+/// deployment with TLS
+pub struct TlsConfig {
+    /// certificate of the authority which signed every certificate of the
+    /// deployment. Clients trust back-ends signed by it, and with `mutual`
+    /// set, back-ends trust clients signed by it.
+    pub ca_cert: String,
+    /// certificate presented by servers, and by clients when `mutual` is set
+    #[serde(default)]
+    pub cert: String,
+    /// private key of `cert`
+    #[serde(default)]
+    pub key: String,
+    /// whether back-ends require clients to present a certificate as well
+    /// (mutual TLS)
+    #[serde(default)]
+    pub mutual: bool,
+    /// name the back-end certificates are issued for. Clients verify this
+    /// name instead of the host they connect to, which is needed when
+    /// back-ends are addressed by IP.
+    #[serde(default)]
+    pub domain: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+/// Tokens which grant access to the back-ends, see [crate::auth]
+pub struct AuthConfig {
+    /// tokens which may make every call, for front-ends and keepers
+    #[serde(default)]
+    pub read_write: Vec<String>,
+    /// tokens which may only make calls that leave storage as it is, for
+    /// monitoring
+    #[serde(default)]
+    pub read_only: Vec<String>,
+    /// token this process presents when it calls a back-end
+    #[serde(default)]
+    pub token: String,
+}
+
+impl Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the tokens themselves
+        f.debug_struct("AuthConfig")
+            .field("read_write", &self.read_write.len())
+            .field("read_only", &self.read_only.len())
+            .field("token", &!self.token.is_empty())
+            .finish()
+    }
+}
+
+impl AuthConfig {
+    /// Returns the [Role] granted by `token`, or [None] if it is unknown.
+    pub fn role(&self, token: &str) -> Option<Role> {
+        if self.read_write.iter().any(|t| t == token) {
+            Some(Role::ReadWrite)
+        } else if self.read_only.iter().any(|t| t == token) {
+            Some(Role::ReadOnly)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the token to present to back-ends, if any.
+    pub fn client_token(&self) -> Option<&str> {
+        match self.token.is_empty() {
+            true => None,
+            false => Some(&self.token),
+        }
+    }
+}
+
+impl TlsConfig {
+    fn identity(&self) -> TribResult<Identity> {
+        Ok(Identity::from_pem(
+            fs::read(&self.cert)?,
+            fs::read(&self.key)?,
+        ))
+    }
+
+    fn ca(&self) -> TribResult<Certificate> {
+        Ok(Certificate::from_pem(fs::read(&self.ca_cert)?))
+    }
+
+    /// Loads the settings of a server using this configuration.
+    pub fn server_config(&self) -> TribResult<ServerTlsConfig> {
+        let mut tls = ServerTlsConfig::new().identity(self.identity()?);
+        if self.mutual {
+            tls = tls.client_ca_root(self.ca()?);
+        }
+        Ok(tls)
+    }
+
+    /// Loads the settings of a client using this configuration.
+    pub fn client_config(&self) -> TribResult<ClientTlsConfig> {
+        let mut tls = ClientTlsConfig::new().ca_certificate(self.ca()?);
+        if !self.domain.is_empty() {
+            tls = tls.domain_name(&self.domain);
+        }
+        if self.mutual {
+            tls = tls.identity(self.identity()?);
+        }
+        Ok(tls)
+    }
 }
 
 impl Config {
@@ -116,6 +374,36 @@ impl Config {
         Ok(())
     }
 
+    /// Returns the authentication settings of the deployment: [Config::auth]
+    /// with its token replaced by the [TOKEN_ENV] environment variable when
+    /// that is set. With only the variable set, its token is a shared secret
+    /// granting [Role::ReadWrite].
+    pub fn auth(&self) -> Option<AuthConfig> {
+        let token = std::env::var(TOKEN_ENV).ok().filter(|t| !t.is_empty());
+        match (self.auth.clone(), token) {
+            (Some(auth), None) => Some(auth),
+            (Some(auth), Some(token)) => Some(AuthConfig { token, ..auth }),
+            (None, Some(token)) => Some(AuthConfig {
+                read_write: vec![token.clone()],
+                read_only: vec![],
+                token,
+            }),
+            (None, None) => None,
+        }
+    }
+
+    /// Returns the settings clients of this deployment's back-ends should
+    /// connect with.
+    pub fn client_config(&self) -> ClientConfig {
+        ClientConfig {
+            tls: self.tls.clone(),
+            token: self
+                .auth()
+                .and_then(|a| a.client_token().map(|t| t.to_string())),
+            compression: self.compression,
+        }
+    }
+
     /// gets the total number of backends in the config.
     pub fn back_count(&self) -> usize {
         self.backs.len()
@@ -144,6 +432,9 @@ impl Config {
             storage: store,
             ready,
             shutdown,
+            tls: self.tls.clone(),
+            auth: self.auth(),
+            compression: self.compression,
         }
     }
 
@@ -166,8 +457,14 @@ impl Config {
             id: SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)?
                 .as_nanos(),
+            hybrid_clock: self.hybrid_clock,
             ready,
             shutdown,
+            tls: self.tls.clone(),
+            auth: self.auth(),
+            compression: self.compression,
+            quorum: self.quorum,
+            state: None,
         })
     }
 }
@@ -0,0 +1,265 @@
+//! module containing the Merkle digests a storage gives of its contents,
+//! which keepers compare to find where replicas differ without listing them.
+//!
+//! Every entry, a value or a list under its key, has a [position] on the
+//! [crate::ring::Ring]: the ring hash of the bin the key belongs to. A
+//! [HashRange] of positions, such as an arc of the ring, is therefore held by
+//! the same replicas. The digest of a range combines the hashes of its
+//! entries so that it equals the combined digests of any split of the range
+//! into parts, i.e. it is the root of a Merkle tree over the range whatever
+//! its shape. Two replicas holding the same entries in a range have the same
+//! digest for it; when the digests differ, comparing those of smaller and
+//! smaller parts narrows the difference down to a few keys.
+//!
+//! ```
+//! # use tribbler::{digest::HashRange, storage::{KeyString, KeyValue, MemStorage, Storage}};
+//! # #[tokio::main]
+//! # async fn main() -> tribbler::err::TribResult<()> {
+//! let (a, b) = (MemStorage::new(), MemStorage::new());
+//! a.set(&KeyValue::new("alice::name", "Alice")).await?;
+//! b.set(&KeyValue::new("alice::name", "Alicia")).await?;
+//! let (da, db) = (
+//!     a.digest(HashRange::ALL, 4, 10).await?,
+//!     b.digest(HashRange::ALL, 4, 10).await?,
+//! );
+//! // only the part holding bin "alice" differs
+//! assert_eq!(1, da.iter().zip(&db).filter(|(a, b)| a.hash != b.hash).count());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    err::TribResult,
+    namespace, ring,
+    storage::{Op, OpResult, Pattern, Storage},
+};
+
+/// An inclusive range of positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HashRange {
+    pub first: u64,
+    pub last: u64,
+}
+
+impl HashRange {
+    /// every position
+    pub const ALL: HashRange = HashRange {
+        first: 0,
+        last: u64::MAX,
+    };
+
+    pub fn new(first: u64, last: u64) -> HashRange {
+        HashRange { first, last }
+    }
+
+    pub fn contains(&self, position: u64) -> bool {
+        self.first <= position && position <= self.last
+    }
+
+    /// Splits the range into `parts` consecutive ranges of about the same
+    /// width, or fewer if the range holds fewer positions. `parts` is
+    /// raised to 1 if it is 0.
+    pub fn split(&self, parts: u32) -> Vec<HashRange> {
+        let width = (self.last as u128).saturating_sub(self.first as u128) + 1;
+        let step = width.div_ceil(parts.max(1) as u128);
+        let mut out = vec![];
+        let mut first = self.first as u128;
+        while first <= self.last as u128 {
+            let last = (first + step - 1).min(self.last as u128);
+            out.push(HashRange::new(first as u64, last as u64));
+            first = last + 1;
+        }
+        out
+    }
+}
+
+/// The hash of a single entry of a storage
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyDigest {
+    pub key: String,
+    /// whether the entry is a list, rather than a value
+    pub list: bool,
+    pub hash: u64,
+}
+
+/// The digest of the entries of a storage in a range of positions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeDigest {
+    pub range: HashRange,
+    /// the entry hashes combined, zero if there are none
+    pub hash: u64,
+    /// number of entries
+    pub count: u64,
+    /// the hash of every entry, sorted by key, if there are no more than the
+    /// `max_keys` asked for; empty otherwise
+    pub keys: Vec<KeyDigest>,
+}
+
+/// Returns the position of `key` on the ring: that of the bin it belongs
+/// to (see [namespace::split]), or its own if it is not in a bin.
+pub fn position(key: &str) -> u64 {
+    match namespace::split(key) {
+        Some((bin, _)) => ring::hash(&bin),
+        None => ring::hash(key),
+    }
+}
+
+/// Returns the hash of the value `value` under `key`.
+pub fn value_hash(key: &str, value: &str) -> u64 {
+    ring::hash(&format!("v{}:{}{}:{}", key.len(), key, value.len(), value))
+}
+
+/// Returns the hash of the list `list` under `key`.
+pub fn list_hash(key: &str, list: &[String]) -> u64 {
+    let mut s = format!("l{}:{}", key.len(), key);
+    for item in list {
+        s.push_str(&format!("{}:{}", item.len(), item));
+    }
+    ring::hash(&s)
+}
+
+/// Computes the digests of `storage` the way [Storage::digest] does, from
+/// [crate::storage::KeyString::keys], [crate::storage::KeyList::list_keys]
+/// and a single [Storage::batch] fetching the entries in `range`.
+pub async fn compute<S: Storage + ?Sized>(
+    storage: &S,
+    range: HashRange,
+    parts: u32,
+    max_keys: u32,
+) -> TribResult<Vec<RangeDigest>> {
+    let all = Pattern::default();
+    let in_range = |keys: Vec<String>| {
+        keys.into_iter()
+            .filter(|k| range.contains(position(k)))
+            .collect::<Vec<_>>()
+    };
+    let values = in_range(storage.keys(&all).await?.0);
+    let lists = in_range(storage.list_keys(&all).await?.0);
+    let ops = values
+        .iter()
+        .map(|k| Op::Get(k.clone()))
+        .chain(lists.iter().map(|k| Op::ListGet(k.clone())))
+        .collect::<Vec<_>>();
+    let results = storage.batch(&ops).await?;
+
+    let entries = values
+        .into_iter()
+        .map(|k| (k, false))
+        .chain(lists.into_iter().map(|k| (k, true)));
+    let hashes = entries.zip(results).filter_map(|((key, list), result)| {
+        let hash = match result {
+            OpResult::Value(Some(v)) => value_hash(&key, &v),
+            OpResult::List(l) => list_hash(&key, &l.0),
+            // gone since it was listed
+            _ => return None,
+        };
+        Some(KeyDigest { key, list, hash })
+    });
+    Ok(combine(range, parts, max_keys, hashes))
+}
+
+/// Combines the hashes of the entries in `range` into its digests, split
+/// into `parts` ranges, as [crate::storage::Storage::digest] returns them.
+pub fn combine<I: IntoIterator<Item = KeyDigest>>(
+    range: HashRange,
+    parts: u32,
+    max_keys: u32,
+    hashes: I,
+) -> Vec<RangeDigest> {
+    let mut digests = range
+        .split(parts)
+        .into_iter()
+        .map(|range| RangeDigest {
+            range,
+            hash: 0,
+            count: 0,
+            keys: vec![],
+        })
+        .collect::<Vec<_>>();
+    for key in hashes {
+        let p = position(&key.key);
+        let i = digests.partition_point(|d| d.range.last < p);
+        let d = &mut digests[i];
+        // xor keeps the digest of a range that of its parts combined
+        d.hash ^= key.hash;
+        d.count += 1;
+        d.keys.push(key);
+    }
+    for d in digests.iter_mut() {
+        if d.count > max_keys as u64 {
+            d.keys = vec![];
+        } else {
+            d.keys
+                .sort_by(|a, b| (&a.key, a.list).cmp(&(&b.key, b.list)));
+        }
+    }
+    digests
+}
+
+#[cfg(test)]
+mod test {
+    use crate::storage::{KeyList, KeyString, KeyValue, MemStorage, Storage};
+
+    use super::{position, HashRange};
+
+    #[test]
+    fn digest_split() {
+        let parts = HashRange::ALL.split(4);
+        assert_eq!(4, parts.len());
+        assert_eq!(0, parts[0].first);
+        assert_eq!(u64::MAX, parts[3].last);
+        for w in parts.windows(2) {
+            assert_eq!(w[0].last + 1, w[1].first);
+        }
+        assert_eq!(vec![HashRange::new(5, 5)], HashRange::new(5, 5).split(8));
+        assert_eq!(3, HashRange::new(0, 2).split(8).len());
+        assert_eq!(vec![HashRange::new(1, 9)], HashRange::new(1, 9).split(0));
+    }
+
+    #[tokio::test]
+    async fn digest_compare() -> crate::err::TribResult<()> {
+        let (a, b) = (MemStorage::new(), MemStorage::new());
+        for s in [&a, &b] {
+            for i in 0..50 {
+                s.set(&KeyValue::new(&format!("bin{}::k", i), "v")).await?;
+                s.list_append(&KeyValue::new(&format!("bin{}::l", i), "x"))
+                    .await?;
+            }
+        }
+        let whole = a.digest(HashRange::ALL, 1, 0).await?;
+        assert_eq!(whole, b.digest(HashRange::ALL, 1, 0).await?);
+        assert_eq!(100, whole[0].count);
+        assert!(whole[0].keys.is_empty());
+
+        b.list_append(&KeyValue::new("bin7::l", "y")).await?;
+        let (da, db) = (
+            a.digest(HashRange::ALL, 16, 100).await?,
+            b.digest(HashRange::ALL, 16, 100).await?,
+        );
+        let differ = da
+            .iter()
+            .zip(&db)
+            .filter(|(a, b)| a.hash != b.hash)
+            .collect::<Vec<_>>();
+        assert_eq!(1, differ.len());
+        let (pa, pb) = differ[0];
+        assert!(pa.range.contains(position("bin7::l")));
+        assert_eq!(pa.count, pb.count);
+        let changed = pa
+            .keys
+            .iter()
+            .zip(&pb.keys)
+            .filter(|(a, b)| a != b)
+            .map(|(a, _)| (a.key.as_str(), a.list))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("bin7::l", true)], changed);
+
+        // the digest of a range is that of its parts combined
+        let whole = b.digest(HashRange::ALL, 1, 0).await?[0].hash;
+        assert_eq!(whole, db.iter().fold(0, |h, d| h ^ d.hash));
+
+        // entries of the same bin share their position
+        assert_eq!(position("bin7::l"), position("bin7::k"));
+        Ok(())
+    }
+}
@@ -0,0 +1,831 @@
+//! module containing a durable implementation of the [Storage] interface
+//! which keeps its data in a directory on the local disk.
+//!
+//! Every mutation is appended to a write-ahead log (`wal.log`) and flushed
+//! to disk before it is applied to the in-memory state, so a backend that is
+//! restarted on the same directory comes back with all of its acknowledged
+//! writes. Once the log grows past a configurable number of records, the
+//! state is compacted into a snapshot (`snapshot.json`) and the log is
+//! truncated.
+//!
+//! The log is written and flushed on tokio's blocking thread pool, so a slow
+//! disk holds up the writers of this storage but not the executor.
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    digest::{HashRange, RangeDigest},
+    err::{TribResult, TribblerError},
+    storage::{
+        self, ChangeStream, KeyList, KeyString, KeyValue, List, MemStorage, OpResult, Pattern,
+        Scan, Storage,
+    },
+};
+
+/// number of log records after which the log is compacted into a snapshot
+/// when using [DiskStorage::open]
+pub const DEFAULT_SNAPSHOT_EVERY: usize = 10_000;
+
+const WAL_FILE: &str = "wal.log";
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const SNAPSHOT_TMP_FILE: &str = "snapshot.json.tmp";
+
+/// A single mutation recorded in the write-ahead log.
+#[derive(Serialize, Deserialize, Debug)]
+enum Op {
+    Set {
+        key: String,
+        value: String,
+    },
+    ListAppend {
+        key: String,
+        value: String,
+    },
+    ListRemove {
+        key: String,
+        value: String,
+    },
+    /// a [KeyString::set_ttl], with its deadline in milliseconds since the
+    /// Unix epoch
+    SetUntil {
+        key: String,
+        value: String,
+        deadline: u64,
+    },
+    /// a [KeyList::list_append_ttl], with its deadline in milliseconds since
+    /// the Unix epoch
+    ListAppendUntil {
+        key: String,
+        value: String,
+        deadline: u64,
+    },
+    /// the value returned by a call to [Storage::clock]
+    Clock(u64),
+    /// the writes of a [Storage::batch], kept in a single record so that
+    /// recovery either replays all of them or none
+    Batch(Vec<Op>),
+}
+
+/// A line of the write-ahead log. `seq` is strictly increasing across the
+/// lifetime of the data directory so records which are already part of a
+/// snapshot can be skipped on recovery.
+#[derive(Serialize, Deserialize, Debug)]
+struct Record {
+    seq: u64,
+    op: Op,
+}
+
+/// The compacted state of a [DiskStorage] at log sequence number `seq`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Snapshot {
+    seq: u64,
+    kvs: HashMap<String, String>,
+    lists: HashMap<String, Vec<String>>,
+    /// expiry deadlines of the pairs in `kvs` which have one
+    #[serde(default)]
+    kv_deadlines: HashMap<String, u64>,
+    /// expiry deadlines of the lists in `lists` which have one
+    #[serde(default)]
+    list_deadlines: HashMap<String, u64>,
+    clock: Option<u64>,
+}
+
+/// The writer half of the storage. All mutations go through this while the
+/// surrounding lock is held, which gives the log the same order in which
+/// mutations are applied.
+#[derive(Debug)]
+struct Wal {
+    dir: PathBuf,
+    file: Arc<File>,
+    /// sequence number of the last record written
+    seq: u64,
+    /// number of records in the log since the last snapshot
+    records: usize,
+    /// last value returned by [Storage::clock]
+    clock: Option<u64>,
+}
+
+impl Wal {
+    /// writes `op` to the log as a single record and waits for it to reach
+    /// the disk
+    async fn append(&mut self, op: Op) -> TribResult<()> {
+        // the sequence number is taken before the write, so a record which
+        // reaches the disk after the caller gave up on it is never followed
+        // by one with the same number.
+        self.seq += 1;
+        self.records += 1;
+        let mut buf = serde_json::to_vec(&Record { seq: self.seq, op })?;
+        buf.push(b'\n');
+        let file = self.file.clone();
+        tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let mut file = &*file;
+            file.write_all(&buf)?;
+            file.sync_data()
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        Ok(())
+    }
+}
+
+/// applies a record of the log to `mem`, returning the value of the clock if
+/// the record sets it
+async fn replay(mem: &MemStorage, op: Op) -> TribResult<Option<u64>> {
+    let ops = match op {
+        Op::Batch(ops) => ops,
+        op => vec![op],
+    };
+    let mut clock = None;
+    for op in ops {
+        match op {
+            Op::Set { key, value } => {
+                mem.set(&KeyValue { key, value }).await?;
+            }
+            Op::ListAppend { key, value } => {
+                mem.list_append(&KeyValue { key, value }).await?;
+            }
+            Op::ListRemove { key, value } => {
+                mem.list_remove(&KeyValue { key, value }).await?;
+            }
+            Op::SetUntil {
+                key,
+                value,
+                deadline,
+            } => {
+                mem.set_until(&KeyValue { key, value }, deadline)?;
+            }
+            Op::ListAppendUntil {
+                key,
+                value,
+                deadline,
+            } => {
+                mem.list_append_until(&KeyValue { key, value }, deadline)?;
+            }
+            Op::Clock(c) => {
+                mem.clock(c).await?;
+                clock = Some(c);
+            }
+            Op::Batch(_) => {
+                return Err(Box::new(TribblerError::Unknown(
+                    "nested batch in write-ahead log".to_string(),
+                )));
+            }
+        }
+    }
+    Ok(clock)
+}
+
+/// A [Storage] implementation which persists its key-value pairs, lists and
+/// clock to a directory on disk. It can be used anywhere a [MemStorage] can,
+/// e.g. as the `storage` of a [crate::config::BackConfig].
+#[derive(Debug)]
+pub struct DiskStorage {
+    mem: MemStorage,
+    wal: Mutex<Wal>,
+    snapshot_every: usize,
+}
+
+impl DiskStorage {
+    /// Opens (or creates) a [DiskStorage] in the directory `dir`, recovering
+    /// any state left behind by a previous instance. The log is compacted
+    /// every [DEFAULT_SNAPSHOT_EVERY] records.
+    pub async fn open<P: AsRef<Path>>(dir: P) -> TribResult<DiskStorage> {
+        DiskStorage::open_with(dir, DEFAULT_SNAPSHOT_EVERY).await
+    }
+
+    /// Same as [DiskStorage::open], but compacts the log into a snapshot
+    /// every `snapshot_every` records. A value of `0` disables compaction.
+    pub async fn open_with<P: AsRef<Path>>(
+        dir: P,
+        snapshot_every: usize,
+    ) -> TribResult<DiskStorage> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mem = MemStorage::new();
+        let snapshot = match fs::read(dir.join(SNAPSHOT_FILE)) {
+            Ok(b) => serde_json::from_slice::<Snapshot>(&b)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Snapshot::default(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        for (key, value) in snapshot.kvs.iter() {
+            let kv = KeyValue::new(key, value);
+            match snapshot.kv_deadlines.get(key) {
+                Some(d) => mem.set_until(&kv, *d)?,
+                None => mem.set(&kv).await?,
+            };
+        }
+        for (key, list) in snapshot.lists.iter() {
+            for value in list.iter() {
+                let kv = KeyValue::new(key, value);
+                match snapshot.list_deadlines.get(key) {
+                    Some(d) => mem.list_append_until(&kv, *d)?,
+                    None => mem.list_append(&kv).await?,
+                };
+            }
+        }
+        let mut clock = snapshot.clock;
+        if let Some(c) = clock {
+            mem.clock(c).await?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(dir.join(WAL_FILE))?;
+        let mut seq = snapshot.seq;
+        let mut records = 0;
+        let mut valid_len = 0;
+        let mut reader = BufReader::new(&file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let n = reader.read_line(&mut line)?;
+            if n == 0 {
+                break;
+            }
+            let record = match serde_json::from_str::<Record>(&line) {
+                Ok(r) if line.ends_with('\n') => r,
+                // a torn write at the tail of the log is a record which was
+                // never acknowledged, so it is safe to drop it.
+                _ => {
+                    let rest = reader.fill_buf()?.len();
+                    if rest > 0 {
+                        return Err(Box::new(TribblerError::Unknown(format!(
+                            "corrupted write-ahead log in {}",
+                            dir.display()
+                        ))));
+                    }
+                    break;
+                }
+            };
+            valid_len += n as u64;
+            if record.seq <= seq {
+                continue;
+            }
+            seq = record.seq;
+            records += 1;
+            if let Some(c) = replay(&mem, record.op).await? {
+                clock = Some(c);
+            }
+        }
+        drop(reader);
+        file.set_len(valid_len)?;
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(DiskStorage {
+            mem,
+            wal: Mutex::new(Wal {
+                dir,
+                file: Arc::new(file),
+                seq,
+                records,
+                clock,
+            }),
+            snapshot_every,
+        })
+    }
+
+    /// Switches the storage to a hybrid logical clock, see
+    /// [MemStorage::with_hybrid_clock].
+    pub fn with_hybrid_clock(mut self) -> DiskStorage {
+        self.mem = self.mem.with_hybrid_clock();
+        self
+    }
+
+    /// Writes a snapshot of the current state and truncates the write-ahead
+    /// log. This happens automatically as the log grows, but may also be
+    /// called explicitly, e.g. before a planned shutdown.
+    pub async fn compact(&self) -> TribResult<()> {
+        let mut wal = self.wal.lock().await;
+        self.compact_locked(&mut wal).await
+    }
+
+    async fn compact_locked(&self, wal: &mut Wal) -> TribResult<()> {
+        let mut snapshot = Snapshot {
+            seq: wal.seq,
+            clock: wal.clock,
+            ..Default::default()
+        };
+        for key in self.mem.keys(&Pattern::default()).await?.0 {
+            if let Some(value) = self.mem.get(&key).await? {
+                if let Some(d) = self.mem.deadline(&key)? {
+                    snapshot.kv_deadlines.insert(key.clone(), d);
+                }
+                snapshot.kvs.insert(key, value);
+            }
+        }
+        for key in self.mem.list_keys(&Pattern::default()).await?.0 {
+            let list = self.mem.list_get(&key).await?;
+            if let Some(d) = self.mem.list_deadline(&key)? {
+                snapshot.list_deadlines.insert(key.clone(), d);
+            }
+            snapshot.lists.insert(key, list.0);
+        }
+
+        let buf = serde_json::to_vec(&snapshot)?;
+        let dir = wal.dir.clone();
+        let file = wal.file.clone();
+        tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let tmp = dir.join(SNAPSHOT_TMP_FILE);
+            let mut handle = File::create(&tmp)?;
+            handle.write_all(&buf)?;
+            handle.sync_all()?;
+            fs::rename(&tmp, dir.join(SNAPSHOT_FILE))?;
+            File::open(&dir)?.sync_all()?;
+
+            let mut file = &*file;
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.sync_all()
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        wal.records = 0;
+        Ok(())
+    }
+
+    /// appends `op` to the log, compacting it first if it has grown too
+    /// large. The caller applies `op` to `self.mem` after this returns.
+    async fn log(&self, wal: &mut Wal, op: Op) -> TribResult<()> {
+        if self.snapshot_every > 0 && wal.records >= self.snapshot_every {
+            self.compact_locked(wal).await?;
+        }
+        wal.append(op).await
+    }
+}
+
+#[async_trait]
+impl KeyString for DiskStorage {
+    async fn get(&self, key: &str) -> TribResult<Option<String>> {
+        self.mem.get(key).await
+    }
+
+    async fn set(&self, kv: &KeyValue) -> TribResult<bool> {
+        let mut wal = self.wal.lock().await;
+        self.log(
+            &mut wal,
+            Op::Set {
+                key: kv.key.clone(),
+                value: kv.value.clone(),
+            },
+        )
+        .await?;
+        self.mem.set(kv).await
+    }
+
+    async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        let deadline = storage::deadline_after(ttl);
+        let mut wal = self.wal.lock().await;
+        self.log(
+            &mut wal,
+            Op::SetUntil {
+                key: kv.key.clone(),
+                value: kv.value.clone(),
+                deadline,
+            },
+        )
+        .await?;
+        self.mem.set_until(kv, deadline)
+    }
+
+    async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)> {
+        // every write holds the log lock, so the value can't change between
+        // the comparison and the swap.
+        let mut wal = self.wal.lock().await;
+        let current = self.mem.get(&kv.key).await?;
+        if current.as_deref().unwrap_or("") != old {
+            return Ok((false, current));
+        }
+        self.log(
+            &mut wal,
+            Op::Set {
+                key: kv.key.clone(),
+                value: kv.value.clone(),
+            },
+        )
+        .await?;
+        self.mem.cas(kv, old).await
+    }
+
+    async fn incr(&self, key: &str, delta: i64) -> TribResult<i64> {
+        // the result is logged as a plain write, keeping the expiry of the
+        // key, so replaying it doesn't depend on when the key expires.
+        let mut wal = self.wal.lock().await;
+        let current = self.mem.get(key).await?;
+        let value = storage::incr_value(key, current.as_deref(), delta)?;
+        let kv = KeyValue::new(key, &value.to_string());
+        let deadline = match current {
+            Some(_) => self.mem.deadline(key)?,
+            None => None,
+        };
+        match deadline {
+            Some(deadline) => {
+                self.log(
+                    &mut wal,
+                    Op::SetUntil {
+                        key: kv.key.clone(),
+                        value: kv.value.clone(),
+                        deadline,
+                    },
+                )
+                .await?;
+                self.mem.set_until(&kv, deadline)?;
+            }
+            None => {
+                self.log(
+                    &mut wal,
+                    Op::Set {
+                        key: kv.key.clone(),
+                        value: kv.value.clone(),
+                    },
+                )
+                .await?;
+                self.mem.set(&kv).await?;
+            }
+        }
+        Ok(value)
+    }
+
+    async fn keys(&self, p: &Pattern) -> TribResult<List> {
+        self.mem.keys(p).await
+    }
+
+    async fn scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        self.mem.scan(p, cursor, limit).await
+    }
+}
+
+#[async_trait]
+impl KeyList for DiskStorage {
+    async fn list_get(&self, key: &str) -> TribResult<List> {
+        self.mem.list_get(key).await
+    }
+
+    async fn list_append(&self, kv: &KeyValue) -> TribResult<bool> {
+        let mut wal = self.wal.lock().await;
+        self.log(
+            &mut wal,
+            Op::ListAppend {
+                key: kv.key.clone(),
+                value: kv.value.clone(),
+            },
+        )
+        .await?;
+        self.mem.list_append(kv).await
+    }
+
+    async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        let deadline = storage::deadline_after(ttl);
+        let mut wal = self.wal.lock().await;
+        self.log(
+            &mut wal,
+            Op::ListAppendUntil {
+                key: kv.key.clone(),
+                value: kv.value.clone(),
+                deadline,
+            },
+        )
+        .await?;
+        self.mem.list_append_until(kv, deadline)
+    }
+
+    async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32> {
+        let mut wal = self.wal.lock().await;
+        self.log(
+            &mut wal,
+            Op::ListRemove {
+                key: kv.key.clone(),
+                value: kv.value.clone(),
+            },
+        )
+        .await?;
+        self.mem.list_remove(kv).await
+    }
+
+    async fn list_keys(&self, p: &Pattern) -> TribResult<List> {
+        self.mem.list_keys(p).await
+    }
+
+    async fn list_scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        self.mem.list_scan(p, cursor, limit).await
+    }
+
+    async fn list_range(&self, key: &str, start: i64, end: i64) -> TribResult<List> {
+        self.mem.list_range(key, start, end).await
+    }
+
+    async fn list_len(&self, key: &str) -> TribResult<u64> {
+        self.mem.list_len(key).await
+    }
+}
+
+#[async_trait]
+impl Storage for DiskStorage {
+    async fn clock(&self, at_least: u64) -> TribResult<u64> {
+        let mut wal = self.wal.lock().await;
+        let ret = self.mem.clock(at_least).await?;
+        self.log(&mut wal, Op::Clock(ret)).await?;
+        wal.clock = Some(ret);
+        Ok(ret)
+    }
+
+    async fn batch(&self, ops: &[storage::Op]) -> TribResult<Vec<OpResult>> {
+        // a batch the wrapped storage rejects must not reach the log either,
+        // or it would be applied when the log is replayed.
+        for op in ops {
+            if let storage::Op::Keys(p) | storage::Op::ListKeys(p) = op {
+                p.matcher()?;
+            }
+        }
+        let mut wal = self.wal.lock().await;
+        let writes = ops
+            .iter()
+            .filter_map(|op| match op {
+                storage::Op::Set(kv) => Some(Op::Set {
+                    key: kv.key.clone(),
+                    value: kv.value.clone(),
+                }),
+                storage::Op::ListAppend(kv) => Some(Op::ListAppend {
+                    key: kv.key.clone(),
+                    value: kv.value.clone(),
+                }),
+                storage::Op::ListRemove(kv) => Some(Op::ListRemove {
+                    key: kv.key.clone(),
+                    value: kv.value.clone(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<Op>>();
+        if !writes.is_empty() {
+            self.log(&mut wal, Op::Batch(writes)).await?;
+        }
+        self.mem.batch(ops).await
+    }
+
+    async fn watch(&self, p: &Pattern, resume: u64) -> TribResult<ChangeStream> {
+        self.mem.watch(p, resume).await
+    }
+
+    async fn digest(
+        &self,
+        range: HashRange,
+        parts: u32,
+        max_keys: u32,
+    ) -> TribResult<Vec<RangeDigest>> {
+        self.mem.digest(range, parts, max_keys).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs::OpenOptions, io::Write, path::PathBuf, time::Duration};
+
+    use crate::{
+        err::TribResult,
+        storage::{KeyList, KeyString, KeyValue, Op, Pattern, Storage},
+    };
+
+    use super::{DiskStorage, SNAPSHOT_FILE, WAL_FILE};
+
+    fn test_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("trib-disk-{}", rand::random::<u64>()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn disk_recover() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open(&dir).await?;
+            s.set(&KeyValue::new("a", "1")).await?;
+            s.set(&KeyValue::new("b", "2")).await?;
+            s.set(&KeyValue::new("b", "")).await?;
+            s.list_append(&KeyValue::new("l", "x")).await?;
+            s.list_append(&KeyValue::new("l", "y")).await?;
+            s.list_append(&KeyValue::new("l", "x")).await?;
+            assert_eq!(2, s.list_remove(&KeyValue::new("l", "x")).await?);
+            assert_eq!(1234, s.clock(1234).await?);
+        }
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(Some("1".to_string()), s.get("a").await?);
+        assert_eq!(None, s.get("b").await?);
+        assert_eq!(vec!["y".to_string()], s.list_get("l").await?.0);
+        assert_eq!(1235, s.clock(0).await?);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_incr() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open(&dir).await?;
+            assert_eq!(2, s.incr("c", 2).await?);
+            assert_eq!(-1, s.incr("c", -3).await?);
+            s.set(&KeyValue::new("s", "abc")).await?;
+            assert!(s.incr("s", 1).await.is_err());
+        }
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(Some("-1".to_string()), s.get("c").await?);
+        assert_eq!(Some("abc".to_string()), s.get("s").await?);
+        assert_eq!(9, s.incr("c", 10).await?);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_ttl() -> TribResult<()> {
+        let dir = test_dir();
+        let (short, long) = (Duration::from_millis(50), Duration::from_secs(3600));
+        {
+            let s = DiskStorage::open(&dir).await?;
+            s.set_ttl(&KeyValue::new("a", "1"), short).await?;
+            s.set_ttl(&KeyValue::new("b", "2"), long).await?;
+            s.list_append_ttl(&KeyValue::new("l", "x"), short).await?;
+            s.list_append_ttl(&KeyValue::new("m", "y"), long).await?;
+        }
+        {
+            let s = DiskStorage::open(&dir).await?;
+            s.compact().await?;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(vec!["b"], s.keys(&Pattern::default()).await?.0);
+        assert_eq!(vec!["m"], s.list_keys(&Pattern::default()).await?.0);
+        assert!(s.mem.deadline("b")?.is_some());
+        assert!(s.mem.list_deadline("m")?.is_some());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_cas() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open(&dir).await?;
+            assert!(s.cas(&KeyValue::new("a", "1"), "").await?.0);
+            assert!(!s.cas(&KeyValue::new("a", "2"), "").await?.0);
+            assert!(s.cas(&KeyValue::new("a", "3"), "1").await?.0);
+        }
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(Some("3".to_string()), s.get("a").await?);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_batch() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open(&dir).await?;
+            s.batch(&[
+                Op::Set(KeyValue::new("a", "1")),
+                Op::ListAppend(KeyValue::new("l", "x")),
+                Op::Get("a".to_string()),
+                Op::ListAppend(KeyValue::new("l", "y")),
+                Op::ListRemove(KeyValue::new("l", "x")),
+            ])
+            .await?;
+        }
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(Some("1".to_string()), s.get("a").await?);
+        assert_eq!(vec!["y".to_string()], s.list_get("l").await?.0);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_fresh_clock() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open(&dir).await?;
+            s.set(&KeyValue::new("a", "1")).await?;
+        }
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(0, s.clock(0).await?);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_snapshot() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open_with(&dir, 4).await?;
+            for i in 0..10 {
+                s.list_append(&KeyValue::new("l", &i.to_string())).await?;
+            }
+            s.set(&KeyValue::new("k", "v")).await?;
+            s.clock(10).await?;
+        }
+        assert!(dir.join(SNAPSHOT_FILE).exists());
+        let s = DiskStorage::open_with(&dir, 4).await?;
+        assert_eq!(10, s.list_get("l").await?.0.len());
+        assert_eq!("9", s.list_get("l").await?.0[9]);
+        assert_eq!(Some("v".to_string()), s.get("k").await?);
+        assert_eq!(11, s.clock(0).await?);
+        assert_eq!(1, s.keys(&Pattern::default()).await?.0.len());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_compact_is_idempotent() -> TribResult<()> {
+        let dir = test_dir();
+        let s = DiskStorage::open(&dir).await?;
+        s.list_append(&KeyValue::new("l", "x")).await?;
+        // keep a copy of the log as if the process crashed between writing
+        // the snapshot and truncating the log.
+        let log = std::fs::read(dir.join(WAL_FILE))?;
+        s.compact().await?;
+        drop(s);
+        std::fs::write(dir.join(WAL_FILE), log)?;
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(1, s.list_get("l").await?.0.len());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_torn_tail() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open(&dir).await?;
+            s.set(&KeyValue::new("a", "1")).await?;
+        }
+        let mut f = OpenOptions::new().append(true).open(dir.join(WAL_FILE))?;
+        f.write_all(b"{\"seq\":2,\"op\":{\"Set\":{\"ke")?;
+        drop(f);
+        {
+            let s = DiskStorage::open(&dir).await?;
+            assert_eq!(Some("1".to_string()), s.get("a").await?);
+            s.set(&KeyValue::new("b", "2")).await?;
+        }
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(Some("2".to_string()), s.get("b").await?);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+    #[tokio::test]
+    async fn disk_torn_batch() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open(&dir).await?;
+            s.set(&KeyValue::new("a", "1")).await?;
+            s.batch(&[
+                Op::Set(KeyValue::new("b", "2")),
+                Op::ListAppend(KeyValue::new("l", "x")),
+                Op::Set(KeyValue::new("c", "3")),
+            ])
+            .await?;
+        }
+        // cut the log in the middle of the batch, after its first write
+        let log = std::fs::read(dir.join(WAL_FILE))?;
+        let cut = log.windows(3).position(|w| w == b"\"l\"").unwrap();
+        std::fs::write(dir.join(WAL_FILE), &log[..cut])?;
+        {
+            let s = DiskStorage::open(&dir).await?;
+            assert_eq!(Some("1".to_string()), s.get("a").await?);
+            assert_eq!(None, s.get("b").await?);
+            assert!(s.list_get("l").await?.0.is_empty());
+            assert_eq!(None, s.get("c").await?);
+            s.set(&KeyValue::new("d", "4")).await?;
+        }
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(vec!["a", "d"], s.keys(&Pattern::default()).await?.0);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_rejected_batch() -> TribResult<()> {
+        let dir = test_dir();
+        {
+            let s = DiskStorage::open(&dir).await?;
+            let p = Pattern {
+                regex: "(".to_string(),
+                ..Default::default()
+            };
+            let ops = [Op::Set(KeyValue::new("a", "1")), Op::Keys(p)];
+            assert!(s.batch(&ops).await.is_err());
+            assert_eq!(None, s.get("a").await?);
+        }
+        let s = DiskStorage::open(&dir).await?;
+        assert_eq!(None, s.get("a").await?);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
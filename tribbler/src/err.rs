@@ -14,6 +14,12 @@ pub enum TribblerError {
     InvalidUsername(String),
     /// generic error for anything that occurs with RPC communication
     RpcError(String),
+    /// an error with a machine-readable [ErrorCode], e.g. one returned by a
+    /// back-end
+    Status(ErrorCode, String),
+    /// raised when a call did not finish before its deadline, see
+    /// [crate::call::CallOptions]
+    Timeout(String),
     /// raised when too a user tries to follow more than
     /// [crate::trib::MAX_FOLLOWING]
     FollowingTooMany,
@@ -38,6 +44,8 @@ impl Display for TribblerError {
             TribblerError::UsernameTaken(x) => format!("username \"{}\" already taken", x),
             TribblerError::InvalidUsername(x) => format!("username \"{}\" is invalid", x),
             TribblerError::RpcError(x) => format!("rpc error: {}", x),
+            TribblerError::Status(_, x) => x.clone(),
+            TribblerError::Timeout(x) => format!("timed out: {}", x),
             TribblerError::FollowingTooMany => "following too many users".to_string(),
             TribblerError::AlreadyFollowing(who, whom) => {
                 format!("{} already following {}", who, whom)
@@ -54,9 +62,115 @@ impl Display for TribblerError {
 
 impl std::error::Error for TribblerError {}
 
+/// The kind of a failure, telling callers how to react to it, e.g. whether
+/// to retry a call elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// the service could not be reached. Trying again, or trying another
+    /// replica, may succeed.
+    Unavailable,
+    /// the call did not finish before its deadline
+    Deadline,
+    /// the call was refused because of its arguments. Trying again will
+    /// fail the same way.
+    Invalid,
+    /// the call refers to something which does not exist
+    NotFound,
+    /// the service failed to carry out the call
+    Internal,
+}
+
+impl ErrorCode {
+    /// Returns the code of `e`, which is [ErrorCode::Internal] unless `e` is
+    /// a [TribblerError].
+    pub fn of(e: &(dyn Error + 'static)) -> ErrorCode {
+        match e.downcast_ref::<TribblerError>() {
+            Some(e) => e.code(),
+            None => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<tonic::Code> for ErrorCode {
+    fn from(code: tonic::Code) -> Self {
+        use tonic::Code;
+        match code {
+            Code::Unavailable | Code::Cancelled | Code::Aborted | Code::ResourceExhausted => {
+                ErrorCode::Unavailable
+            }
+            Code::DeadlineExceeded => ErrorCode::Deadline,
+            Code::InvalidArgument
+            | Code::FailedPrecondition
+            | Code::OutOfRange
+            | Code::AlreadyExists
+            | Code::PermissionDenied
+            | Code::Unauthenticated => ErrorCode::Invalid,
+            Code::NotFound => ErrorCode::NotFound,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<&tonic::Status> for ErrorCode {
+    fn from(status: &tonic::Status) -> Self {
+        // tonic reports failures of the connection itself as unknown, with
+        // the cause as the source
+        if status.code() == tonic::Code::Unknown && status.source().is_some() {
+            return ErrorCode::Unavailable;
+        }
+        status.code().into()
+    }
+}
+
+impl From<ErrorCode> for tonic::Code {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::Unavailable => tonic::Code::Unavailable,
+            ErrorCode::Deadline => tonic::Code::DeadlineExceeded,
+            ErrorCode::Invalid => tonic::Code::InvalidArgument,
+            ErrorCode::NotFound => tonic::Code::NotFound,
+            ErrorCode::Internal => tonic::Code::Internal,
+        }
+    }
+}
+
+impl TribblerError {
+    /// Returns the [ErrorCode] of the error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            TribblerError::Status(code, _) => *code,
+            TribblerError::RpcError(_) => ErrorCode::Unavailable,
+            TribblerError::Timeout(_) => ErrorCode::Deadline,
+            TribblerError::UserDoesNotExist(_) | TribblerError::NotFollowing(_, _) => {
+                ErrorCode::NotFound
+            }
+            TribblerError::UsernameTaken(_)
+            | TribblerError::InvalidUsername(_)
+            | TribblerError::FollowingTooMany
+            | TribblerError::AlreadyFollowing(_, _)
+            | TribblerError::TribTooLong
+            | TribblerError::WhoWhom(_) => ErrorCode::Invalid,
+            TribblerError::MaxedSeq | TribblerError::Unknown(_) => ErrorCode::Internal,
+        }
+    }
+}
+
 impl From<tonic::Status> for TribblerError {
     fn from(v: tonic::Status) -> Self {
-        TribblerError::RpcError(format!("{:?}", v))
+        match ErrorCode::from(&v) {
+            ErrorCode::Deadline => TribblerError::Timeout(v.message().to_string()),
+            code => TribblerError::Status(code, v.message().to_string()),
+        }
+    }
+}
+
+impl From<TribblerError> for tonic::Status {
+    fn from(e: TribblerError) -> Self {
+        match e {
+            TribblerError::Timeout(x) => tonic::Status::deadline_exceeded(x),
+            TribblerError::Status(code, x) => tonic::Status::new(code.into(), x),
+            e => tonic::Status::new(e.code().into(), e.to_string()),
+        }
     }
 }
 
@@ -74,3 +188,47 @@ impl From<Box<dyn Error>> for TribblerError {
         TribblerError::Unknown(x.to_string())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ErrorCode, TribblerError};
+
+    #[test]
+    fn err_codes() {
+        for code in [
+            ErrorCode::Unavailable,
+            ErrorCode::Deadline,
+            ErrorCode::Invalid,
+            ErrorCode::NotFound,
+            ErrorCode::Internal,
+        ] {
+            assert_eq!(code, ErrorCode::from(tonic::Code::from(code)));
+            let status = tonic::Status::from(TribblerError::Status(code, "x".to_string()));
+            assert_eq!(code, TribblerError::from(status).code());
+        }
+        assert_eq!(ErrorCode::Internal, ErrorCode::from(tonic::Code::Unknown));
+        assert_eq!(
+            ErrorCode::Unavailable,
+            ErrorCode::from(tonic::Code::Cancelled)
+        );
+    }
+
+    #[test]
+    fn err_round_trip() {
+        let e = TribblerError::UserDoesNotExist("alice".to_string());
+        let back = TribblerError::from(tonic::Status::from(e.clone()));
+        assert_eq!(ErrorCode::NotFound, back.code());
+        assert_eq!(e.to_string(), back.to_string());
+
+        let e = TribblerError::Timeout("too slow".to_string());
+        match TribblerError::from(tonic::Status::from(e)) {
+            TribblerError::Timeout(x) => assert_eq!("too slow", x),
+            e => panic!("expected a timeout, got {:?}", e),
+        }
+
+        let e: Box<dyn std::error::Error> = Box::new(TribblerError::TribTooLong);
+        assert_eq!(ErrorCode::Invalid, ErrorCode::of(&*e));
+        let e: Box<dyn std::error::Error> = "plain".into();
+        assert_eq!(ErrorCode::Internal, ErrorCode::of(&*e));
+    }
+}
@@ -0,0 +1,75 @@
+//! module containing the encoding of hybrid logical clock values.
+//!
+//! A hybrid logical clock packs a wall-clock time in milliseconds since the
+//! Unix epoch into the high bits of a `u64`, and a logical counter into the
+//! low [LOGICAL_BITS] bits. Values still only ever move forward like a plain
+//! Lamport clock, so they can be passed to and returned from
+//! [crate::storage::Storage::clock] as they are, but they also stay close to
+//! real time: [physical] recovers the time at which a value was issued.
+//!
+//! Hybrid clocks are opt-in, see [crate::storage::MemStorage::with_hybrid_clock]
+//! and [crate::config::Config::hybrid_clock].
+
+use crate::storage;
+
+/// number of low bits of a clock value holding the logical counter
+pub const LOGICAL_BITS: u32 = 16;
+
+/// Returns the clock value made of `physical` milliseconds since the Unix
+/// epoch and the logical counter `logical`, which must fit in
+/// [LOGICAL_BITS] bits.
+pub fn from_parts(physical: u64, logical: u64) -> u64 {
+    (physical << LOGICAL_BITS) | (logical & ((1 << LOGICAL_BITS) - 1))
+}
+
+/// Returns the physical part of a clock value, in milliseconds since the
+/// Unix epoch.
+pub fn physical(clock: u64) -> u64 {
+    clock >> LOGICAL_BITS
+}
+
+/// Returns the logical part of a clock value.
+pub fn logical(clock: u64) -> u64 {
+    clock & ((1 << LOGICAL_BITS) - 1)
+}
+
+/// Returns the smallest clock value of the current wall-clock time. A
+/// hybrid clock never hands out anything smaller than this.
+pub fn now() -> u64 {
+    from_parts(storage::now_millis(), 0)
+}
+
+/// Returns the clock value a keeper syncing the back-end clocks to
+/// `watermark`, the largest clock it knows of, should ask them for: with
+/// `hybrid` clocks, no less than [now] either, so that a back-end which
+/// sees no traffic still keeps up with wall-clock time.
+pub fn sync_floor(watermark: u64, hybrid: bool) -> u64 {
+    match hybrid {
+        true => watermark.max(now()),
+        false => watermark,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_parts, logical, now, physical, sync_floor};
+
+    #[test]
+    fn hlc_parts() {
+        let c = from_parts(1_650_000_000_000, 7);
+        assert_eq!(1_650_000_000_000, physical(c));
+        assert_eq!(7, logical(c));
+        assert!(from_parts(1_650_000_000_000, 8) > c);
+        assert!(from_parts(1_650_000_000_001, 0) > c);
+        assert!(now() > c);
+        assert_eq!(0, logical(now()));
+    }
+
+    #[test]
+    fn hlc_sync_floor() {
+        let ahead = now() + from_parts(1000, 0);
+        assert_eq!(5, sync_floor(5, false));
+        assert!(sync_floor(5, true) >= now() - from_parts(1000, 0));
+        assert_eq!(ahead, sync_floor(ahead, true));
+    }
+}
@@ -13,11 +13,19 @@
     html_favicon_url = "https://upload.wikimedia.org/wikipedia/commons/thumb/f/f8/Creative-Tail-Animal-penguin.svg/128px-Creative-Tail-Animal-penguin.svg.png?20160314145218"
 )]
 pub mod addr;
+pub mod auth;
+pub mod call;
 pub mod colon;
 pub mod config;
+pub mod digest;
+pub mod disk;
 pub mod err;
+pub mod hlc;
+pub mod namespace;
 pub mod ref_impl;
+pub mod ring;
 /// protobuf-generated RPC stubs and message structs
+#[allow(non_camel_case_types)]
 pub mod rpc;
 pub mod storage;
 pub mod trib;
@@ -0,0 +1,429 @@
+//! module containing [Bin], which keeps many bins apart in one [Storage].
+//!
+//! Every key of a bin is stored under the bin's prefix, its name escaped with
+//! [crate::colon::escape] followed by `::`. Escaped names never contain a
+//! `:`, so no bin's prefix is the prefix of another's, and [split] can tell
+//! a stored key's bin from the rest of it.
+//!
+//! ```
+//! # use tribbler::{namespace::Bin, storage::{KeyString, KeyValue, MemStorage}};
+//! # #[tokio::main]
+//! # async fn main() -> tribbler::err::TribResult<()> {
+//! let alice = Bin::new("alice", MemStorage::new());
+//! alice.set(&KeyValue::new("name", "Alice")).await?;
+//! assert_eq!(Some("Alice".to_string()), alice.get("name").await?);
+//! assert_eq!(
+//!     Some("Alice".to_string()),
+//!     alice.inner().get("alice::name").await?
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio_stream::StreamExt;
+
+use crate::{
+    colon,
+    err::TribResult,
+    storage::{
+        ChangeStream, KeyList, KeyString, KeyValue, List, Matcher, Op, OpResult, Pattern, Scan,
+        Storage,
+    },
+};
+
+/// separator between the escaped bin name and the key
+const SEPARATOR: &str = "::";
+
+/// Returns the prefix of the keys of bin `name`.
+pub fn prefix(name: &str) -> String {
+    format!("{}{}", colon::escape(name), SEPARATOR)
+}
+
+/// Splits a key stored by a [Bin] into the name of the bin and the key
+/// within it, or returns [None] if `key` was not stored by a [Bin].
+pub fn split(key: &str) -> Option<(String, &str)> {
+    let (name, key) = key.split_once(SEPARATOR)?;
+    Some((colon::unescape(name), key))
+}
+
+/// escapes the characters of `s` which are special in a [Pattern::glob]
+fn escape_glob(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A [Storage] holding a single bin, whose keys are stored in the wrapped
+/// storage under the bin's [prefix]. Keys and patterns are translated on the
+/// way in, and keys in results on the way out, so the bin looks like a
+/// storage of its own.
+///
+/// The prefix, suffix and glob of a pattern are matched by the wrapped
+/// storage. A regex can not be translated to match after the prefix, so it
+/// is matched against the keys coming back instead; a page of
+/// [KeyString::scan] may then hold fewer keys than asked for even though
+/// more follow.
+#[derive(Debug, Clone)]
+pub struct Bin<S> {
+    prefix: String,
+    storage: S,
+}
+
+impl<S> Bin<S> {
+    /// Wraps `storage` to hold the bin `name`.
+    pub fn new(name: &str, storage: S) -> Bin<S> {
+        Bin {
+            prefix: prefix(name),
+            storage,
+        }
+    }
+
+    /// the prefix of the keys of this bin in the wrapped storage
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// the wrapped storage
+    pub fn inner(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns the key `key` of this bin is stored as.
+    pub fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Returns the key of this bin stored as `key`, or [None] if `key` does
+    /// not belong to this bin.
+    pub fn strip<'a>(&self, key: &'a str) -> Option<&'a str> {
+        key.strip_prefix(self.prefix.as_str())
+    }
+
+    fn kv(&self, kv: &KeyValue) -> KeyValue {
+        KeyValue {
+            key: self.key(&kv.key),
+            value: kv.value.clone(),
+        }
+    }
+
+    /// Returns the pattern matching the keys of the wrapped storage which
+    /// belong to this bin and match `p`, leaving out its regex.
+    pub fn pattern(&self, p: &Pattern) -> Pattern {
+        Pattern {
+            prefix: self.key(&p.prefix),
+            suffix: p.suffix.clone(),
+            glob: match p.glob.as_str() {
+                "" => String::new(),
+                glob => format!("{}{}", escape_glob(&self.prefix), glob),
+            },
+            regex: String::new(),
+        }
+    }
+
+    /// compiles the part of `p` which [Bin::pattern] leaves out
+    fn regex(p: &Pattern) -> TribResult<Matcher> {
+        Pattern {
+            regex: p.regex.clone(),
+            ..Default::default()
+        }
+        .matcher()
+    }
+
+    /// strips the prefix off keys returned for `p` by the wrapped storage
+    fn list(&self, l: List, p: &Pattern) -> TribResult<List> {
+        Ok(self.filter(l, &Bin::<S>::regex(p)?))
+    }
+
+    /// same as [Bin::list], with the regex of the pattern already compiled
+    fn filter(&self, l: List, regex: &Matcher) -> List {
+        List(
+            l.0.iter()
+                .filter_map(|k| self.strip(k))
+                .filter(|k| regex.matches(k))
+                .map(|k| k.to_string())
+                .collect(),
+        )
+    }
+
+    fn page(&self, s: Scan, p: &Pattern) -> TribResult<Scan> {
+        Ok(Scan {
+            keys: self.list(s.keys, p)?,
+            cursor: s.cursor,
+        })
+    }
+
+    fn op(&self, op: &Op) -> Op {
+        match op {
+            Op::Get(key) => Op::Get(self.key(key)),
+            Op::Set(kv) => Op::Set(self.kv(kv)),
+            Op::Keys(p) => Op::Keys(self.pattern(p)),
+            Op::ListGet(key) => Op::ListGet(self.key(key)),
+            Op::ListAppend(kv) => Op::ListAppend(self.kv(kv)),
+            Op::ListRemove(kv) => Op::ListRemove(self.kv(kv)),
+            Op::ListKeys(p) => Op::ListKeys(self.pattern(p)),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Storage> KeyString for Bin<S> {
+    async fn get(&self, key: &str) -> TribResult<Option<String>> {
+        self.storage.get(&self.key(key)).await
+    }
+
+    async fn set(&self, kv: &KeyValue) -> TribResult<bool> {
+        self.storage.set(&self.kv(kv)).await
+    }
+
+    async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        self.storage.set_ttl(&self.kv(kv), ttl).await
+    }
+
+    async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)> {
+        self.storage.cas(&self.kv(kv), old).await
+    }
+
+    async fn incr(&self, key: &str, delta: i64) -> TribResult<i64> {
+        self.storage.incr(&self.key(key), delta).await
+    }
+
+    async fn keys(&self, p: &Pattern) -> TribResult<List> {
+        let keys = self.storage.keys(&self.pattern(p)).await?;
+        self.list(keys, p)
+    }
+
+    async fn scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        let s = self.storage.scan(&self.pattern(p), cursor, limit).await?;
+        self.page(s, p)
+    }
+}
+
+#[async_trait]
+impl<S: Storage> KeyList for Bin<S> {
+    async fn list_get(&self, key: &str) -> TribResult<List> {
+        self.storage.list_get(&self.key(key)).await
+    }
+
+    async fn list_append(&self, kv: &KeyValue) -> TribResult<bool> {
+        self.storage.list_append(&self.kv(kv)).await
+    }
+
+    async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        self.storage.list_append_ttl(&self.kv(kv), ttl).await
+    }
+
+    async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32> {
+        self.storage.list_remove(&self.kv(kv)).await
+    }
+
+    async fn list_keys(&self, p: &Pattern) -> TribResult<List> {
+        let keys = self.storage.list_keys(&self.pattern(p)).await?;
+        self.list(keys, p)
+    }
+
+    async fn list_scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        let s = self
+            .storage
+            .list_scan(&self.pattern(p), cursor, limit)
+            .await?;
+        self.page(s, p)
+    }
+
+    async fn list_range(&self, key: &str, start: i64, end: i64) -> TribResult<List> {
+        self.storage.list_range(&self.key(key), start, end).await
+    }
+
+    async fn list_len(&self, key: &str) -> TribResult<u64> {
+        self.storage.list_len(&self.key(key)).await
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for Bin<S> {
+    async fn clock(&self, at_least: u64) -> TribResult<u64> {
+        self.storage.clock(at_least).await
+    }
+
+    async fn batch(&self, ops: &[Op]) -> TribResult<Vec<OpResult>> {
+        // patterns are compiled up front so an invalid one fails the whole
+        // batch before the wrapped storage applies any of it.
+        let regexes = ops
+            .iter()
+            .map(|op| match op {
+                Op::Keys(p) | Op::ListKeys(p) => Bin::<S>::regex(p).map(Some),
+                _ => Ok(None),
+            })
+            .collect::<TribResult<Vec<Option<Matcher>>>>()?;
+        let binned = ops.iter().map(|op| self.op(op)).collect::<Vec<Op>>();
+        let results = self.storage.batch(&binned).await?;
+        Ok(regexes
+            .iter()
+            .zip(results)
+            .map(|(regex, r)| match (regex, r) {
+                (Some(regex), OpResult::List(l)) => OpResult::List(self.filter(l, regex)),
+                (_, r) => r,
+            })
+            .collect())
+    }
+
+    async fn watch(&self, p: &Pattern, resume: u64) -> TribResult<ChangeStream> {
+        let regex = Bin::<S>::regex(p)?;
+        let changes = self.storage.watch(&self.pattern(p), resume).await?;
+        let strip = self.prefix.len();
+        Ok(Box::pin(changes.filter_map(move |c| match c {
+            Ok(mut c) => {
+                c.key = c.key[strip..].to_string();
+                regex.matches(&c.key).then_some(Ok(c))
+            }
+            Err(e) => Some(Err(e)),
+        })))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio_stream::StreamExt;
+
+    use super::{prefix, split, Bin};
+    use crate::{
+        err::TribResult,
+        storage::{KeyList, KeyString, KeyValue, MemStorage, Op, OpResult, Pattern, Storage},
+    };
+
+    fn pat(prefix: &str, glob: &str, regex: &str) -> Pattern {
+        Pattern {
+            prefix: prefix.to_string(),
+            glob: glob.to_string(),
+            regex: regex.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn namespace_split() {
+        for name in ["alice", "a:b", "a|;b", "::", "|", ""] {
+            let key = format!("{}{}", prefix(name), "k::v");
+            assert_eq!(Some((name.to_string(), "k::v")), split(&key));
+        }
+        assert_eq!("a|;b::", prefix("a:b"));
+        assert_eq!("a||;b::", prefix("a|;b"));
+        assert_eq!(None, split("plain"));
+    }
+
+    #[tokio::test]
+    async fn namespace_bins_apart() -> TribResult<()> {
+        // names which escape to one another's prefix if escaping went wrong
+        let names = ["a", "a:b", "a|;b", "a::b", "a*", "a["];
+        let storage = Arc::new(MemStorage::new());
+        for name in names {
+            let bin = Bin::new(name, storage.clone());
+            bin.set(&KeyValue::new("k", name)).await?;
+            bin.set(&KeyValue::new("b::k", name)).await?;
+            bin.list_append(&KeyValue::new("l", name)).await?;
+        }
+        for name in names {
+            let bin = Bin::new(name, storage.clone());
+            assert_eq!(Some(name.to_string()), bin.get("k").await?);
+            assert_eq!(Some(name.to_string()), bin.get("b::k").await?);
+            assert_eq!(vec![name.to_string()], bin.list_get("l").await?.0);
+            let mut keys = bin.keys(&Pattern::default()).await?.0;
+            keys.sort();
+            assert_eq!(vec!["b::k".to_string(), "k".to_string()], keys);
+            assert_eq!(
+                vec!["l".to_string()],
+                bin.list_keys(&Pattern::default()).await?.0
+            );
+            assert_eq!(
+                vec!["b::k".to_string()],
+                bin.keys(&pat("b", "", "")).await?.0
+            );
+        }
+        assert_eq!(
+            names.len() * 2,
+            storage.keys(&Pattern::default()).await?.0.len()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn namespace_patterns() -> TribResult<()> {
+        let storage = Arc::new(MemStorage::new());
+        let other = Bin::new("a*", storage.clone());
+        other.set(&KeyValue::new("x1", "v")).await?;
+        let bin = Bin::new("a", storage.clone());
+        for k in ["x1", "x2", "y1", "^x"] {
+            bin.set(&KeyValue::new(k, "v")).await?;
+        }
+        let mut keys = bin.keys(&pat("", "x?", "")).await?.0;
+        keys.sort();
+        assert_eq!(vec!["x1".to_string(), "x2".to_string()], keys);
+        // the regex is anchored at the start of the key within the bin
+        let mut keys = bin.keys(&pat("", "", "^x")).await?.0;
+        keys.sort();
+        assert_eq!(vec!["x1".to_string(), "x2".to_string()], keys);
+        assert_eq!(
+            vec!["y1".to_string()],
+            bin.keys(&pat("", "*1", "y")).await?.0
+        );
+        assert_eq!(
+            vec!["x1".to_string()],
+            other.keys(&pat("", "*", "")).await?.0
+        );
+
+        let page = bin.scan(&pat("x", "", ""), "", 1).await?;
+        assert_eq!(vec!["x1".to_string()], page.keys.0);
+        let page = bin.scan(&pat("x", "", ""), &page.cursor, 1).await?;
+        assert_eq!(vec!["x2".to_string()], page.keys.0);
+        assert!(bin.keys(&pat("", "", "(")).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn namespace_batch_and_watch() -> TribResult<()> {
+        let storage = Arc::new(MemStorage::new());
+        let bin = Bin::new("a:b", storage.clone());
+        let other = Bin::new("a", storage.clone());
+        let mut changes = bin.watch(&pat("", "", "^k"), 0).await?;
+        let results = bin
+            .batch(&[
+                Op::Set(KeyValue::new("k1", "v")),
+                Op::Set(KeyValue::new("j1", "v")),
+                Op::Keys(pat("", "", "1$")),
+                Op::Get("k1".to_string()),
+            ])
+            .await?;
+        other.set(&KeyValue::new("k2", "v")).await?;
+        bin.set(&KeyValue::new("k3", "v")).await?;
+        match &results[2] {
+            OpResult::List(l) => {
+                let mut keys = l.0.clone();
+                keys.sort();
+                assert_eq!(vec!["j1".to_string(), "k1".to_string()], keys);
+            }
+            r => panic!("expected a list, got {:?}", r),
+        }
+        assert!(matches!(&results[3], OpResult::Value(Some(v)) if v == "v"));
+        assert_eq!("k1", changes.next().await.unwrap()?.key);
+        assert_eq!("k3", changes.next().await.unwrap()?.key);
+        // an invalid pattern fails the batch before any of it is applied
+        assert!(bin
+            .batch(&[
+                Op::Set(KeyValue::new("k4", "v")),
+                Op::ListKeys(pat("", "", "(")),
+            ])
+            .await
+            .is_err());
+        assert_eq!(None, bin.get("k4").await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+//! module containing a consistent-hash ring mapping bins to back-ends.
+//!
+//! Every node (usually a back-end address) is placed on a ring of `u64`
+//! hashes at [Ring::vnodes] points, its virtual nodes. A bin belongs to the
+//! first node found walking clockwise from the hash of its name, and its
+//! replicas to the distinct nodes after that, see [Ring::successors]. Adding
+//! or removing a node thus only moves the bins next to its points, about
+//! `1/n` of them, rather than reshuffling everything like `hash % n` does.
+//!
+//! ```
+//! # use tribbler::ring::Ring;
+//! let backs = vec!["127.0.0.1:3000".to_string(), "127.0.0.1:3001".to_string()];
+//! let mut ring = Ring::new(&backs, 64);
+//! let replicas = ring.successors("alice", 2);
+//! assert_eq!(2, replicas.len());
+//!
+//! // bins of a node marked dead move on to the next node
+//! ring.set_alive(replicas[0], false);
+//! assert_eq!(vec![replicas[1]], ring.successors("alice", 2));
+//! ```
+
+use crate::{config::Config, digest::HashRange};
+
+/// number of virtual nodes per node of a ring made by [Ring::from_config]
+pub const DEFAULT_VNODES: usize = 64;
+
+/// A consistent-hash ring over a list of nodes. Nodes are referred to by
+/// their index in that list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ring {
+    nodes: Vec<String>,
+    alive: Vec<bool>,
+    vnodes: usize,
+    /// hash of each virtual node along with the index of its node, sorted
+    points: Vec<(u64, usize)>,
+}
+
+/// Hashes `s` with FNV-1a followed by the splitmix64 finalizer. Unlike
+/// [std::collections::hash_map::DefaultHasher], the result is the same in
+/// every process and on every platform, so every front-end and keeper agrees
+/// on where bins live.
+pub(crate) fn hash(s: &str) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in s.as_bytes() {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^ (h >> 31)
+}
+
+impl Ring {
+    /// Creates a ring over `nodes`, all alive, with `vnodes` virtual nodes
+    /// each. `vnodes` is raised to 1 if it is 0.
+    pub fn new(nodes: &[String], vnodes: usize) -> Ring {
+        let vnodes = vnodes.max(1);
+        let mut points = nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, node)| (0..vnodes).map(move |v| (hash(&format!("{}#{}", node, v)), i)))
+            .collect::<Vec<_>>();
+        points.sort_unstable();
+        Ring {
+            nodes: nodes.to_vec(),
+            alive: vec![true; nodes.len()],
+            vnodes,
+            points,
+        }
+    }
+
+    /// Creates a ring over the back-ends of `config` with [DEFAULT_VNODES]
+    /// virtual nodes each.
+    pub fn from_config(config: &Config) -> Ring {
+        Ring::new(&config.backs, DEFAULT_VNODES)
+    }
+
+    /// the nodes of the ring, in the order they were given
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    /// number of virtual nodes per node
+    pub fn vnodes(&self) -> usize {
+        self.vnodes
+    }
+
+    /// Marks node `i` as alive or dead. Dead nodes keep their place on the
+    /// ring but are skipped by [Ring::successors].
+    pub fn set_alive(&mut self, i: usize, alive: bool) {
+        self.alive[i] = alive;
+    }
+
+    /// Returns whether node `i` is alive.
+    pub fn is_alive(&self, i: usize) -> bool {
+        self.alive[i]
+    }
+
+    /// Returns the first `n` distinct alive nodes clockwise from `bin`, the
+    /// first being the one `bin` belongs to. Fewer are returned if fewer
+    /// than `n` nodes are alive.
+    pub fn successors(&self, bin: &str, n: usize) -> Vec<usize> {
+        self.walk(self.points.partition_point(|(p, _)| *p < hash(bin)), n)
+    }
+
+    /// returns the first `n` distinct alive nodes from point `start` on
+    fn walk(&self, start: usize, n: usize) -> Vec<usize> {
+        let mut found = Vec::with_capacity(n);
+        for k in 0..self.points.len() {
+            if found.len() == n {
+                break;
+            }
+            let (_, i) = self.points[(start + k) % self.points.len()];
+            if self.alive[i] && !found.contains(&i) {
+                found.push(i);
+            }
+        }
+        found
+    }
+
+    /// Splits the ring into the arcs whose bins have the same first `n`
+    /// distinct alive nodes, and returns each arc along with those nodes.
+    /// Together the arcs cover every position (see
+    /// [crate::digest::position]), the one wrapping around zero being split
+    /// in two.
+    pub fn arcs(&self, n: usize) -> Vec<(HashRange, Vec<usize>)> {
+        let mut arcs: Vec<(HashRange, Vec<usize>)> = vec![];
+        let mut first = 0;
+        for (k, (p, _)) in self.points.iter().enumerate() {
+            let nodes = self.walk(k, n);
+            match arcs.last_mut() {
+                Some((range, last)) if *last == nodes => range.last = *p,
+                _ => arcs.push((HashRange::new(first, *p), nodes)),
+            }
+            first = p.wrapping_add(1);
+        }
+        // positions past the last point belong to the first one
+        if let (Some((p, _)), Some((_, nodes))) = (self.points.last(), arcs.first()) {
+            if *p < u64::MAX {
+                arcs.push((HashRange::new(p + 1, u64::MAX), nodes.clone()));
+            }
+        }
+        arcs
+    }
+
+    /// Returns the alive node `bin` belongs to, or [None] if no node is
+    /// alive.
+    pub fn node(&self, bin: &str) -> Option<usize> {
+        self.successors(bin, 1).first().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{distributions::Alphanumeric, rngs::StdRng, Rng as _, SeedableRng};
+
+    use super::Ring;
+    use crate::digest::position;
+
+    fn nodes(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("127.0.0.1:{}", 3000 + i)).collect()
+    }
+
+    fn bins(count: usize) -> Vec<String> {
+        let mut rng = StdRng::seed_from_u64(42);
+        (0..count)
+            .map(|_| {
+                let len = rng.gen_range(1..16);
+                (&mut rng)
+                    .sample_iter(&Alphanumeric)
+                    .take(len)
+                    .map(char::from)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// owner of every bin, by node name
+    fn owners<'a>(ring: &'a Ring, bins: &[String]) -> Vec<&'a str> {
+        bins.iter()
+            .map(|b| ring.nodes()[ring.node(b).unwrap()].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn ring_successors() {
+        let ring = Ring::new(&nodes(5), 16);
+        for bin in bins(1000) {
+            let s = ring.successors(&bin, 3);
+            assert_eq!(3, s.len());
+            assert!(s[0] != s[1] && s[1] != s[2] && s[0] != s[2]);
+            assert_eq!(Some(s[0]), ring.node(&bin));
+            assert_eq!(s[..2], ring.successors(&bin, 2)[..]);
+            assert_eq!(5, ring.successors(&bin, 10).len());
+        }
+        assert!(Ring::new(&[], 16).successors("a", 3).is_empty());
+        assert_eq!(None, Ring::new(&[], 16).node("a"));
+    }
+
+    #[test]
+    fn ring_balance() {
+        let ring = Ring::new(&nodes(8), 128);
+        let bins = bins(20000);
+        let mut counts = [0usize; 8];
+        for b in &bins {
+            counts[ring.node(b).unwrap()] += 1;
+        }
+        let fair = bins.len() / 8;
+        for c in counts {
+            assert!(c > fair / 2 && c < fair * 3 / 2, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn ring_add_node_moves_few_keys() {
+        let bins = bins(10000);
+        for n in [1, 3, 8] {
+            let before = Ring::new(&nodes(n), 64);
+            let after = Ring::new(&nodes(n + 1), 64);
+            let added = &after.nodes()[n];
+            let mut moved = 0;
+            for (a, b) in owners(&before, &bins)
+                .into_iter()
+                .zip(owners(&after, &bins))
+            {
+                if a != b {
+                    // keys only ever move to the new node
+                    assert_eq!(added, b);
+                    moved += 1;
+                }
+            }
+            // about 1/(n+1) of the keys move
+            let expected = bins.len() / (n + 1);
+            assert!(
+                moved > expected / 2 && moved < expected * 3 / 2,
+                "{} nodes: {} of {} keys moved",
+                n,
+                moved,
+                bins.len()
+            );
+        }
+    }
+
+    #[test]
+    fn ring_remove_node_moves_only_its_keys() {
+        let bins = bins(10000);
+        let all = nodes(6);
+        let before = Ring::new(&all, 64);
+        let mut rest = all.clone();
+        let removed = rest.remove(2);
+        let after = Ring::new(&rest, 64);
+        for (a, b) in owners(&before, &bins)
+            .into_iter()
+            .zip(owners(&after, &bins))
+        {
+            if a != removed {
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn ring_alive_mask() {
+        let bins = bins(5000);
+        let mut ring = Ring::new(&nodes(5), 64);
+        let before = owners(&ring, &bins)
+            .into_iter()
+            .map(|o| o.to_string())
+            .collect::<Vec<_>>();
+        let replicas = bins
+            .iter()
+            .map(|b| ring.successors(b, 3))
+            .collect::<Vec<_>>();
+
+        ring.set_alive(1, false);
+        assert!(!ring.is_alive(1));
+        for ((bin, owner), replicas) in bins.iter().zip(&before).zip(&replicas) {
+            let s = ring.successors(bin, 3);
+            assert!(!s.contains(&1));
+            if replicas[0] == 1 {
+                // the next replica takes over
+                assert_eq!(replicas[1], s[0]);
+            } else {
+                assert_eq!(owner, &ring.nodes()[s[0]]);
+            }
+        }
+
+        // a dead node is the same as a removed one for placement
+        let mut rest = nodes(5);
+        rest.remove(1);
+        let removed = Ring::new(&rest, 64);
+        assert_eq!(owners(&removed, &bins), owners(&ring, &bins));
+
+        ring.set_alive(1, true);
+        assert_eq!(before, owners(&ring, &bins));
+    }
+
+    #[test]
+    fn ring_arcs() {
+        let mut ring = Ring::new(&nodes(5), 16);
+        ring.set_alive(3, false);
+        let arcs = ring.arcs(3);
+        assert_eq!(0, arcs[0].0.first);
+        assert_eq!(u64::MAX, arcs.last().unwrap().0.last);
+        for w in arcs.windows(2) {
+            assert_eq!(w[0].0.last + 1, w[1].0.first);
+        }
+        for bin in bins(1000) {
+            let (_, nodes) = arcs
+                .iter()
+                .find(|(range, _)| range.contains(position(&format!("{}::k", bin))))
+                .unwrap();
+            assert_eq!(&ring.successors(&bin, 3), nodes);
+        }
+    }
+}
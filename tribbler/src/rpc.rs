@@ -6,11 +6,24 @@ pub struct KeyValue {
     pub value: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TtlKeyValue {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub value: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub ttl_ms: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Pattern {
     #[prost(string, tag = "1")]
     pub prefix: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub suffix: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub glob: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub regex: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Bool {
@@ -42,6 +55,181 @@ pub struct ListRemoveResponse {
     #[prost(uint32, tag = "1")]
     pub removed: u32,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct IncrRequest {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(int64, tag = "2")]
+    pub delta: i64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct IncrResponse {
+    #[prost(int64, tag = "1")]
+    pub value: i64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListRangeRequest {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(int64, tag = "2")]
+    pub start: i64,
+    #[prost(int64, tag = "3")]
+    pub end: i64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListLenResponse {
+    #[prost(uint64, tag = "1")]
+    pub len: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScanRequest {
+    #[prost(message, optional, tag = "1")]
+    pub pattern: ::core::option::Option<Pattern>,
+    #[prost(string, tag = "2")]
+    pub cursor: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "3")]
+    pub limit: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScanResponse {
+    #[prost(string, repeated, tag = "1")]
+    pub keys: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, tag = "2")]
+    pub cursor: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CasRequest {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub old: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub value: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CasResponse {
+    #[prost(bool, tag = "1")]
+    pub swapped: bool,
+    #[prost(string, tag = "2")]
+    pub current: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Op {
+    #[prost(oneof = "op::Op", tags = "1, 2, 3, 4, 5, 6, 7")]
+    pub op: ::core::option::Option<op::Op>,
+}
+/// Nested message and enum types in `Op`.
+pub mod op {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Op {
+        #[prost(message, tag = "1")]
+        Get(super::Key),
+        #[prost(message, tag = "2")]
+        Set(super::KeyValue),
+        #[prost(message, tag = "3")]
+        Keys(super::Pattern),
+        #[prost(message, tag = "4")]
+        ListGet(super::Key),
+        #[prost(message, tag = "5")]
+        ListAppend(super::KeyValue),
+        #[prost(message, tag = "6")]
+        ListRemove(super::KeyValue),
+        #[prost(message, tag = "7")]
+        ListKeys(super::Pattern),
+    }
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct OpResult {
+    #[prost(oneof = "op_result::Result", tags = "1, 2, 3, 4")]
+    pub result: ::core::option::Option<op_result::Result>,
+}
+/// Nested message and enum types in `OpResult`.
+pub mod op_result {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Result {
+        #[prost(message, tag = "1")]
+        Value(super::Value),
+        #[prost(message, tag = "2")]
+        Bool(super::Bool),
+        #[prost(message, tag = "3")]
+        List(super::StringList),
+        #[prost(message, tag = "4")]
+        Removed(super::ListRemoveResponse),
+    }
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BatchRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub ops: ::prost::alloc::vec::Vec<Op>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BatchResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub results: ::prost::alloc::vec::Vec<OpResult>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatchRequest {
+    #[prost(message, optional, tag = "1")]
+    pub pattern: ::core::option::Option<Pattern>,
+    #[prost(uint64, tag = "2")]
+    pub resume: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Change {
+    #[prost(uint64, tag = "1")]
+    pub seq: u64,
+    #[prost(enumeration = "ChangeKind", tag = "2")]
+    pub kind: i32,
+    #[prost(string, tag = "3")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub value: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DigestRequest {
+    #[prost(uint64, tag = "1")]
+    pub first: u64,
+    #[prost(uint64, tag = "2")]
+    pub last: u64,
+    #[prost(uint32, tag = "3")]
+    pub parts: u32,
+    #[prost(uint32, tag = "4")]
+    pub max_keys: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KeyDigest {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(bool, tag = "2")]
+    pub list: bool,
+    #[prost(uint64, tag = "3")]
+    pub hash: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RangeDigest {
+    #[prost(uint64, tag = "1")]
+    pub first: u64,
+    #[prost(uint64, tag = "2")]
+    pub last: u64,
+    #[prost(uint64, tag = "3")]
+    pub hash: u64,
+    #[prost(uint64, tag = "4")]
+    pub count: u64,
+    #[prost(message, repeated, tag = "5")]
+    pub keys: ::prost::alloc::vec::Vec<KeyDigest>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DigestResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub ranges: ::prost::alloc::vec::Vec<RangeDigest>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ChangeKind {
+    Set = 0,
+    ListAppend = 1,
+    ListRemove = 2,
+}
 #[doc = r" Generated client implementations."]
 pub mod trib_storage_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -130,6 +318,48 @@ pub mod trib_storage_client {
             let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/set");
             self.inner.unary(request.into_request(), path, codec).await
         }
+        pub async fn set_ttl(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TtlKeyValue>,
+        ) -> Result<tonic::Response<super::Bool>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/setTtl");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn cas(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CasRequest>,
+        ) -> Result<tonic::Response<super::CasResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/cas");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn incr(
+            &mut self,
+            request: impl tonic::IntoRequest<super::IncrRequest>,
+        ) -> Result<tonic::Response<super::IncrResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/incr");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
         pub async fn keys(
             &mut self,
             request: impl tonic::IntoRequest<super::Pattern>,
@@ -144,6 +374,20 @@ pub mod trib_storage_client {
             let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/keys");
             self.inner.unary(request.into_request(), path, codec).await
         }
+        pub async fn scan(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ScanRequest>,
+        ) -> Result<tonic::Response<super::ScanResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/scan");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
         pub async fn list_get(
             &mut self,
             request: impl tonic::IntoRequest<super::Key>,
@@ -172,6 +416,20 @@ pub mod trib_storage_client {
             let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/listAppend");
             self.inner.unary(request.into_request(), path, codec).await
         }
+        pub async fn list_append_ttl(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TtlKeyValue>,
+        ) -> Result<tonic::Response<super::Bool>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/listAppendTtl");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
         pub async fn list_remove(
             &mut self,
             request: impl tonic::IntoRequest<super::KeyValue>,
@@ -200,6 +458,48 @@ pub mod trib_storage_client {
             let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/listKeys");
             self.inner.unary(request.into_request(), path, codec).await
         }
+        pub async fn list_scan(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ScanRequest>,
+        ) -> Result<tonic::Response<super::ScanResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/listScan");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn list_range(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListRangeRequest>,
+        ) -> Result<tonic::Response<super::StringList>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/listRange");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn list_len(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Key>,
+        ) -> Result<tonic::Response<super::ListLenResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/listLen");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
         pub async fn clock(
             &mut self,
             request: impl tonic::IntoRequest<super::Clock>,
@@ -214,6 +514,51 @@ pub mod trib_storage_client {
             let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/clock");
             self.inner.unary(request.into_request(), path, codec).await
         }
+        pub async fn batch(
+            &mut self,
+            request: impl tonic::IntoRequest<super::BatchRequest>,
+        ) -> Result<tonic::Response<super::BatchResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/batch");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn watch(
+            &mut self,
+            request: impl tonic::IntoRequest<super::WatchRequest>,
+        ) -> Result<tonic::Response<tonic::codec::Streaming<super::Change>>, tonic::Status>
+        {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/watch");
+            self.inner
+                .server_streaming(request.into_request(), path, codec)
+                .await
+        }
+        pub async fn digest(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DigestRequest>,
+        ) -> Result<tonic::Response<super::DigestResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/rpc.TribStorage/digest");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
     }
 }
 #[doc = r" Generated server implementations."]
@@ -231,10 +576,26 @@ pub mod trib_storage_server {
             &self,
             request: tonic::Request<super::KeyValue>,
         ) -> Result<tonic::Response<super::Bool>, tonic::Status>;
+        async fn set_ttl(
+            &self,
+            request: tonic::Request<super::TtlKeyValue>,
+        ) -> Result<tonic::Response<super::Bool>, tonic::Status>;
+        async fn cas(
+            &self,
+            request: tonic::Request<super::CasRequest>,
+        ) -> Result<tonic::Response<super::CasResponse>, tonic::Status>;
+        async fn incr(
+            &self,
+            request: tonic::Request<super::IncrRequest>,
+        ) -> Result<tonic::Response<super::IncrResponse>, tonic::Status>;
         async fn keys(
             &self,
             request: tonic::Request<super::Pattern>,
         ) -> Result<tonic::Response<super::StringList>, tonic::Status>;
+        async fn scan(
+            &self,
+            request: tonic::Request<super::ScanRequest>,
+        ) -> Result<tonic::Response<super::ScanResponse>, tonic::Status>;
         async fn list_get(
             &self,
             request: tonic::Request<super::Key>,
@@ -243,6 +604,10 @@ pub mod trib_storage_server {
             &self,
             request: tonic::Request<super::KeyValue>,
         ) -> Result<tonic::Response<super::Bool>, tonic::Status>;
+        async fn list_append_ttl(
+            &self,
+            request: tonic::Request<super::TtlKeyValue>,
+        ) -> Result<tonic::Response<super::Bool>, tonic::Status>;
         async fn list_remove(
             &self,
             request: tonic::Request<super::KeyValue>,
@@ -251,16 +616,44 @@ pub mod trib_storage_server {
             &self,
             request: tonic::Request<super::Pattern>,
         ) -> Result<tonic::Response<super::StringList>, tonic::Status>;
+        async fn list_scan(
+            &self,
+            request: tonic::Request<super::ScanRequest>,
+        ) -> Result<tonic::Response<super::ScanResponse>, tonic::Status>;
+        async fn list_range(
+            &self,
+            request: tonic::Request<super::ListRangeRequest>,
+        ) -> Result<tonic::Response<super::StringList>, tonic::Status>;
+        async fn list_len(
+            &self,
+            request: tonic::Request<super::Key>,
+        ) -> Result<tonic::Response<super::ListLenResponse>, tonic::Status>;
         async fn clock(
             &self,
             request: tonic::Request<super::Clock>,
         ) -> Result<tonic::Response<super::Clock>, tonic::Status>;
+        async fn batch(
+            &self,
+            request: tonic::Request<super::BatchRequest>,
+        ) -> Result<tonic::Response<super::BatchResponse>, tonic::Status>;
+        #[doc = "Server streaming response type for the watch method."]
+        type watchStream: futures_core::Stream<Item = Result<super::Change, tonic::Status>>
+            + Send
+            + 'static;
+        async fn watch(
+            &self,
+            request: tonic::Request<super::WatchRequest>,
+        ) -> Result<tonic::Response<Self::watchStream>, tonic::Status>;
+        async fn digest(
+            &self,
+            request: tonic::Request<super::DigestRequest>,
+        ) -> Result<tonic::Response<super::DigestResponse>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct TribStorageServer<T: TribStorage> {
         inner: _Inner<T>,
-        accept_compression_encodings: (),
-        send_compression_encodings: (),
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
     }
     struct _Inner<T>(Arc<T>);
     impl<T: TribStorage> TribStorageServer<T> {
@@ -279,6 +672,16 @@ pub mod trib_storage_server {
         {
             InterceptedService::new(Self::new(inner), interceptor)
         }
+        #[doc = r" Enable decompressing requests with `gzip`."]
+        pub fn accept_gzip(mut self) -> Self {
+            self.accept_compression_encodings.enable_gzip();
+            self
+        }
+        #[doc = r" Compress responses with `gzip`, if the client supports it."]
+        pub fn send_gzip(mut self) -> Self {
+            self.send_compression_encodings.enable_gzip();
+            self
+        }
     }
     impl<T, B> tonic::codegen::Service<http::Request<B>> for TribStorageServer<T>
     where
@@ -354,6 +757,99 @@ pub mod trib_storage_server {
                     };
                     Box::pin(fut)
                 }
+                "/rpc.TribStorage/setTtl" => {
+                    #[allow(non_camel_case_types)]
+                    struct setTtlSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::TtlKeyValue> for setTtlSvc<T> {
+                        type Response = super::Bool;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TtlKeyValue>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).set_ttl(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = setTtlSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rpc.TribStorage/cas" => {
+                    #[allow(non_camel_case_types)]
+                    struct casSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::CasRequest> for casSvc<T> {
+                        type Response = super::CasResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CasRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).cas(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = casSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rpc.TribStorage/incr" => {
+                    #[allow(non_camel_case_types)]
+                    struct incrSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::IncrRequest> for incrSvc<T> {
+                        type Response = super::IncrResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::IncrRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).incr(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = incrSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/rpc.TribStorage/keys" => {
                     #[allow(non_camel_case_types)]
                     struct keysSvc<T: TribStorage>(pub Arc<T>);
@@ -385,6 +881,37 @@ pub mod trib_storage_server {
                     };
                     Box::pin(fut)
                 }
+                "/rpc.TribStorage/scan" => {
+                    #[allow(non_camel_case_types)]
+                    struct scanSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::ScanRequest> for scanSvc<T> {
+                        type Response = super::ScanResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ScanRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).scan(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = scanSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/rpc.TribStorage/listGet" => {
                     #[allow(non_camel_case_types)]
                     struct listGetSvc<T: TribStorage>(pub Arc<T>);
@@ -444,6 +971,37 @@ pub mod trib_storage_server {
                     };
                     Box::pin(fut)
                 }
+                "/rpc.TribStorage/listAppendTtl" => {
+                    #[allow(non_camel_case_types)]
+                    struct listAppendTtlSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::TtlKeyValue> for listAppendTtlSvc<T> {
+                        type Response = super::Bool;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TtlKeyValue>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).list_append_ttl(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = listAppendTtlSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/rpc.TribStorage/listRemove" => {
                     #[allow(non_camel_case_types)]
                     struct listRemoveSvc<T: TribStorage>(pub Arc<T>);
@@ -506,6 +1064,96 @@ pub mod trib_storage_server {
                     };
                     Box::pin(fut)
                 }
+                "/rpc.TribStorage/listScan" => {
+                    #[allow(non_camel_case_types)]
+                    struct listScanSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::ScanRequest> for listScanSvc<T> {
+                        type Response = super::ScanResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ScanRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).list_scan(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = listScanSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rpc.TribStorage/listRange" => {
+                    #[allow(non_camel_case_types)]
+                    struct listRangeSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::ListRangeRequest> for listRangeSvc<T> {
+                        type Response = super::StringList;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListRangeRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).list_range(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = listRangeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rpc.TribStorage/listLen" => {
+                    #[allow(non_camel_case_types)]
+                    struct listLenSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::Key> for listLenSvc<T> {
+                        type Response = super::ListLenResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(&mut self, request: tonic::Request<super::Key>) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).list_len(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = listLenSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/rpc.TribStorage/clock" => {
                     #[allow(non_camel_case_types)]
                     struct clockSvc<T: TribStorage>(pub Arc<T>);
@@ -534,6 +1182,101 @@ pub mod trib_storage_server {
                     };
                     Box::pin(fut)
                 }
+                "/rpc.TribStorage/batch" => {
+                    #[allow(non_camel_case_types)]
+                    struct batchSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::BatchRequest> for batchSvc<T> {
+                        type Response = super::BatchResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::BatchRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).batch(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = batchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rpc.TribStorage/watch" => {
+                    #[allow(non_camel_case_types)]
+                    struct watchSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::ServerStreamingService<super::WatchRequest> for watchSvc<T> {
+                        type Response = super::Change;
+                        type ResponseStream = T::watchStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatchRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).watch(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = watchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/rpc.TribStorage/digest" => {
+                    #[allow(non_camel_case_types)]
+                    struct digestSvc<T: TribStorage>(pub Arc<T>);
+                    impl<T: TribStorage> tonic::server::UnaryService<super::DigestRequest> for digestSvc<T> {
+                        type Response = super::DigestResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DigestRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).digest(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = digestSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
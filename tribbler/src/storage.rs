@@ -1,9 +1,28 @@
 #![allow(dead_code)]
 //! module containing Tribbler storage-related structs and implementations
 use async_trait::async_trait;
-use std::{collections::HashMap, sync::RwLock};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    ops::Bound,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
 
-use crate::err::TribResult;
+use crate::{
+    digest::{self, HashRange, KeyDigest, RangeDigest},
+    err::{ErrorCode, TribResult, TribblerError},
+    hlc,
+};
 
 #[derive(Debug, Clone)]
 
@@ -27,25 +46,268 @@ impl KeyValue {
 
 #[derive(Debug, Clone, Default)]
 /// A type which represents a pattern that can be used to match on a String.
+///
+/// A string matches when it satisfies every part of the pattern. Empty parts
+/// match everything, so [Pattern::default] matches all strings.
 pub struct Pattern {
     /// exact-match string prefix
     pub prefix: String,
     /// exact-match string suffix
     pub suffix: String,
+    /// shell-style glob which must match the whole string. `*` matches any
+    /// run of characters, `?` any single character, `[abc]`, `[a-z]` and
+    /// `[!abc]` a character class, and `\` escapes the next character.
+    pub glob: String,
+    /// regular expression which must match somewhere in the string. Use `^`
+    /// and `$` to anchor it.
+    pub regex: String,
 }
 
 impl Pattern {
     /// this function returns true the provided string matches the prefix and
-    /// suffix of the given pattern
+    /// suffix of the given pattern, as well as its glob and regex if set. An
+    /// invalid regex matches nothing; use [Pattern::matcher] to detect it.
     pub fn matches(&self, k: &str) -> bool {
-        k.starts_with(&self.prefix) && k.ends_with(&self.suffix)
+        match self.matcher() {
+            Ok(m) => m.matches(k),
+            Err(_) => false,
+        }
+    }
+
+    /// Compiles the pattern into a [Matcher] which can be used to match many
+    /// strings. Returns an error if the regex is invalid.
+    pub fn matcher(&self) -> TribResult<Matcher> {
+        let regex = match self.regex.as_str() {
+            "" => None,
+            r => Some(Regex::new(r)?),
+        };
+        Ok(Matcher {
+            pattern: self.clone(),
+            regex,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A compiled [Pattern], see [Pattern::matcher]
+pub struct Matcher {
+    pattern: Pattern,
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    /// returns true if the provided string matches the pattern
+    pub fn matches(&self, k: &str) -> bool {
+        let p = &self.pattern;
+        k.starts_with(&p.prefix)
+            && k.ends_with(&p.suffix)
+            && (p.glob.is_empty() || glob_matches(&p.glob, k))
+            && self.regex.as_ref().map(|r| r.is_match(k)).unwrap_or(true)
+    }
+}
+
+/// matches `s` against the whole of the glob `pattern`
+fn glob_matches(pattern: &str, s: &str) -> bool {
+    let p = pattern.chars().collect::<Vec<char>>();
+    let s = s.chars().collect::<Vec<char>>();
+    let (mut pi, mut si) = (0, 0);
+    // position of the last `*` seen and of the input it was tried against,
+    // to backtrack to when the rest of the pattern fails to match
+    let mut star: Option<(usize, usize)> = None;
+    while si < s.len() {
+        let step = match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, si));
+                pi += 1;
+                continue;
+            }
+            Some('?') => Some(pi + 1),
+            Some('[') => match_class(&p, pi, s[si]),
+            Some('\\') if pi + 1 < p.len() => (p[pi + 1] == s[si]).then(|| pi + 2),
+            Some(c) => (*c == s[si]).then(|| pi + 1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(next), _) => {
+                pi = next;
+                si += 1;
+            }
+            (None, Some((spi, ssi))) => {
+                pi = spi + 1;
+                si = ssi + 1;
+                star = Some((spi, ssi + 1));
+            }
+            (None, None) => return false,
+        }
     }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// matches `c` against the character class starting at `p[start]`, which is
+/// a `[`. Returns the index right after the class if it matched. An
+/// unterminated class is matched as a literal `[`.
+fn match_class(p: &[char], start: usize, c: char) -> Option<usize> {
+    let mut i = start + 1;
+    let negate = matches!(p.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+    let mut found = false;
+    let mut first = true;
+    loop {
+        match p.get(i) {
+            None => return (c == '[').then(|| start + 1),
+            Some(']') if !first => break,
+            Some(&lo) => {
+                if p.get(i + 1) == Some(&'-') && matches!(p.get(i + 2), Some(hi) if *hi != ']') {
+                    found |= lo <= c && c <= p[i + 2];
+                    i += 3;
+                } else {
+                    found |= lo == c;
+                    i += 1;
+                }
+            }
+        }
+        first = false;
+    }
+    (found != negate).then(|| i + 1)
 }
 
 #[derive(Debug, Clone)]
 /// A wrapper type around a [Vec<String>]
 pub struct List(pub Vec<String>);
 
+#[derive(Debug, Clone)]
+/// A single operation of a [Storage::batch] call. Each variant takes the
+/// same arguments as the [KeyString] or [KeyList] method of the same name.
+pub enum Op {
+    Get(String),
+    Set(KeyValue),
+    Keys(Pattern),
+    ListGet(String),
+    ListAppend(KeyValue),
+    ListRemove(KeyValue),
+    ListKeys(Pattern),
+}
+
+impl Op {
+    /// Returns whether the operation modifies storage.
+    pub fn is_write(&self) -> bool {
+        matches!(self, Op::Set(_) | Op::ListAppend(_) | Op::ListRemove(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The result of a single [Op] in a [Storage::batch] call
+pub enum OpResult {
+    /// result of [Op::Get]
+    Value(Option<String>),
+    /// result of [Op::Set] and [Op::ListAppend]
+    Bool(bool),
+    /// result of [Op::Keys], [Op::ListGet] and [Op::ListKeys]
+    List(List),
+    /// result of [Op::ListRemove]
+    Removed(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The kind of mutation a [Change] describes
+pub enum ChangeKind {
+    /// a [KeyString::set] (or successful [KeyString::cas]). An empty value
+    /// means the key was cleared.
+    Set,
+    /// a [KeyList::list_append]
+    ListAppend,
+    /// a [KeyList::list_remove] which removed at least one element
+    ListRemove,
+}
+
+#[derive(Debug, Clone)]
+/// A single mutation delivered by [Storage::watch]
+pub struct Change {
+    /// sequence number of this change, which doubles as the resume token
+    /// for [Storage::watch]
+    pub seq: u64,
+    /// what kind of mutation happened
+    pub kind: ChangeKind,
+    /// the key or list key which was changed
+    pub key: String,
+    /// the value which was set, appended or removed
+    pub value: String,
+}
+
+/// The stream of changes returned by [Storage::watch]
+pub type ChangeStream = Pin<Box<dyn Stream<Item = TribResult<Change>> + Send>>;
+
+#[derive(Debug, Clone)]
+/// A page of keys returned by [KeyString::scan] and [KeyList::list_scan]
+pub struct Scan {
+    /// the keys in this page, in sorted order
+    pub keys: List,
+    /// opaque cursor to fetch the next page with. Empty when there are no
+    /// more keys.
+    pub cursor: String,
+}
+
+/// Builds a page of at most `limit` keys (or all of them, if `limit` is
+/// zero) out of `keys`, which must be sorted.
+pub(crate) fn scan_page<'a, I: Iterator<Item = &'a String>>(keys: I, limit: u32) -> Scan {
+    let limit = match limit {
+        0 => usize::MAX,
+        l => l as usize,
+    };
+    let mut page = vec![];
+    let mut more = false;
+    for k in keys {
+        if page.len() == limit {
+            more = true;
+            break;
+        }
+        page.push(k.clone());
+    }
+    let cursor = match page.last() {
+        Some(last) if more => encode_cursor(last),
+        _ => String::new(),
+    };
+    Scan {
+        keys: List(page),
+        cursor,
+    }
+}
+
+/// The cursor of a page is the hex-encoded last key of the page, so the
+/// next page starts right after it.
+fn encode_cursor(key: &str) -> String {
+    let mut cursor = String::from("k");
+    for b in key.bytes() {
+        cursor.push_str(&format!("{:02x}", b));
+    }
+    cursor
+}
+
+/// Returns the key a page ends with, or [None] for an empty cursor.
+pub(crate) fn decode_cursor(cursor: &str) -> TribResult<Option<String>> {
+    if cursor.is_empty() {
+        return Ok(None);
+    }
+    let invalid = || {
+        TribblerError::Status(
+            ErrorCode::Invalid,
+            format!("invalid scan cursor \"{}\"", cursor),
+        )
+    };
+    let hex = match cursor.strip_prefix('k') {
+        Some(h) if h.is_ascii() && h.len() % 2 == 0 => h,
+        _ => return Err(Box::new(invalid())),
+    };
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalid())?;
+    Ok(Some(String::from_utf8(bytes).map_err(|_| invalid())?))
+}
+
 #[async_trait]
 /// Key-value pair interfaces
 /// Default value for all keys is empty string
@@ -56,9 +318,48 @@ pub trait KeyString {
     /// Set kv.key to kv.value. return true when no error.
     async fn set(&self, kv: &KeyValue) -> TribResult<bool>;
 
+    /// Same as [KeyString::set], except that the pair expires `ttl` after
+    /// the call and from then on reads as unset, e.g. for session tokens.
+    /// A later [KeyString::set] or successful [KeyString::cas] of the key
+    /// clears the expiry.
+    async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool>;
+
+    /// Atomically sets kv.key to kv.value if and only if its current value
+    /// is `old`. Since unset keys have the empty string as their value, an
+    /// empty `old` makes this a set-if-absent, and an empty kv.value clears
+    /// the key just like [KeyString::set].
+    ///
+    /// Returns whether the swap took place, along with the value of the key
+    /// after the call.
+    async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)>;
+
+    /// Atomically adds `delta` to the integer stored at `key`, and returns
+    /// the result. An unset key counts as zero. Fails without changing
+    /// anything if the current value is not an integer or if the sum
+    /// overflows. An expiry set by [KeyString::set_ttl] is left as it is.
+    async fn incr(&self, key: &str, delta: i64) -> TribResult<i64>;
+
     /// List all the keys of non-empty pairs where the key matches
     /// the given pattern.
     async fn keys(&self, p: &Pattern) -> TribResult<List>;
+
+    /// List the keys of non-empty pairs matching the given pattern one page
+    /// at a time, in sorted order. Pass an empty `cursor` to get the first
+    /// page, and the [Scan::cursor] of the previous page to get the next
+    /// one. Each page holds at most `limit` keys, or all of the remaining
+    /// keys if `limit` is zero.
+    ///
+    /// The default implementation lists every key with [KeyString::keys];
+    /// implementations should override it.
+    async fn scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        let after = decode_cursor(cursor)?;
+        let mut keys = self.keys(p).await?.0;
+        keys.sort();
+        Ok(scan_page(
+            keys.iter().filter(|k| after.as_ref() < Some(k)),
+            limit,
+        ))
+    }
 }
 
 #[async_trait]
@@ -70,6 +371,12 @@ pub trait KeyList {
     /// Append a string to the list. return true when no error.
     async fn list_append(&self, kv: &KeyValue) -> TribResult<bool>;
 
+    /// Same as [KeyList::list_append], except that the whole list expires
+    /// `ttl` after the call and from then on reads as empty. Every call
+    /// pushes the expiry of the list back; [KeyList::list_append] and
+    /// [KeyList::list_remove] leave it as it is.
+    async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool>;
+
     /// Removes all elements that are equal to `kv.value` in list `kv.key`
     /// returns the number of elements removed.
     async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32>;
@@ -77,6 +384,78 @@ pub trait KeyList {
     /// List all the keys of non-empty lists, where the key matches
     /// the given pattern.
     async fn list_keys(&self, p: &Pattern) -> TribResult<List>;
+
+    /// Get the elements from `start` to `end` (both inclusive) of the list.
+    /// Negative indices count from the tail, so `-1` is the last element and
+    /// `list_range(key, -100, -1)` returns the last 100 elements. Indices
+    /// past either end are clamped. Empty if not set or if the range is
+    /// empty.
+    ///
+    /// The default implementation fetches the whole list with
+    /// [KeyList::list_get]; implementations should override it.
+    async fn list_range(&self, key: &str, start: i64, end: i64) -> TribResult<List> {
+        let list = self.list_get(key).await?.0;
+        Ok(List(match range_bounds(list.len(), start, end) {
+            Some((s, e)) => list[s..e].to_vec(),
+            None => vec![],
+        }))
+    }
+
+    /// Same as [KeyString::scan], for the keys of non-empty lists.
+    ///
+    /// The default implementation lists every key with
+    /// [KeyList::list_keys]; implementations should override it.
+    async fn list_scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        let after = decode_cursor(cursor)?;
+        let mut keys = self.list_keys(p).await?.0;
+        keys.sort();
+        Ok(scan_page(
+            keys.iter().filter(|k| after.as_ref() < Some(k)),
+            limit,
+        ))
+    }
+
+    /// Get the length of the list. Zero if not set.
+    ///
+    /// The default implementation fetches the whole list with
+    /// [KeyList::list_get]; implementations should override it.
+    async fn list_len(&self, key: &str) -> TribResult<u64> {
+        Ok(self.list_get(key).await?.0.len() as u64)
+    }
+}
+
+/// Returns the result of a [KeyString::incr] of `key`, whose current value
+/// is `current`.
+pub(crate) fn incr_value(key: &str, current: Option<&str>, delta: i64) -> TribResult<i64> {
+    let current = match current {
+        Some(v) => v.parse::<i64>().map_err(|_| {
+            TribblerError::Status(
+                ErrorCode::Invalid,
+                format!("value of \"{}\" is not an integer", key),
+            )
+        })?,
+        None => 0,
+    };
+    Ok(current.checked_add(delta).ok_or_else(|| {
+        TribblerError::Status(
+            ErrorCode::Invalid,
+            format!("incrementing \"{}\" overflows", key),
+        )
+    })?)
+}
+
+/// Resolves the inclusive, possibly negative, indices of
+/// [KeyList::list_range] into a half-open range of a list of length `len`.
+/// Returns [None] if the range is empty.
+pub(crate) fn range_bounds(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let resolve = |i: i64| if i < 0 { len + i } else { i };
+    let start = resolve(start).max(0);
+    let end = resolve(end).min(len - 1);
+    if start > end {
+        return None;
+    }
+    Some((start as usize, end as usize + 1))
 }
 
 #[async_trait]
@@ -89,6 +468,58 @@ pub trait Storage: KeyString + KeyList + Send + Sync {
     /// be unique, no smaller than `at_least`, and strictly larger than the
     /// value returned last time, unless it was [u64::MAX]
     async fn clock(&self, at_least: u64) -> TribResult<u64>;
+
+    /// Streams every change made to a key or list key which matches `p`.
+    ///
+    /// When `resume` is zero, only changes made after the call are
+    /// delivered. Otherwise it should be the [Change::seq] of the last change
+    /// a previous watcher saw, and the stream starts with the changes made
+    /// since then; an error is returned if those are no longer available.
+    /// An error item in the stream means changes were missed, and the caller
+    /// should resume from the last change it received.
+    async fn watch(&self, p: &Pattern, resume: u64) -> TribResult<ChangeStream> {
+        let _ = (p, resume);
+        Err(Box::new(TribblerError::Unknown(
+            "watch is not supported by this storage".to_string(),
+        )))
+    }
+
+    /// Runs `ops` in order and returns the result of each one, in the same
+    /// order. Implementations should override this so the whole batch is
+    /// applied atomically, and in a single round trip for remote storage.
+    /// The default implementation just issues the calls one by one.
+    async fn batch(&self, ops: &[Op]) -> TribResult<Vec<OpResult>> {
+        let mut results = Vec::with_capacity(ops.len());
+        for op in ops {
+            results.push(match op {
+                Op::Get(key) => OpResult::Value(self.get(key).await?),
+                Op::Set(kv) => OpResult::Bool(self.set(kv).await?),
+                Op::Keys(p) => OpResult::List(self.keys(p).await?),
+                Op::ListGet(key) => OpResult::List(self.list_get(key).await?),
+                Op::ListAppend(kv) => OpResult::Bool(self.list_append(kv).await?),
+                Op::ListRemove(kv) => OpResult::Removed(self.list_remove(kv).await?),
+                Op::ListKeys(p) => OpResult::List(self.list_keys(p).await?),
+            });
+        }
+        Ok(results)
+    }
+
+    /// Returns the digests of the entries whose [crate::digest::position]
+    /// is in `range`, split into `parts` ranges of about the same width. The
+    /// digest of a range lists the hash of each of its entries if there are
+    /// no more than `max_keys` of them.
+    ///
+    /// The default implementation lists every key and fetches the entries in
+    /// `range` with a single [Storage::batch], see
+    /// [crate::digest::compute]; implementations should override it.
+    async fn digest(
+        &self,
+        range: HashRange,
+        parts: u32,
+        max_keys: u32,
+    ) -> TribResult<Vec<RangeDigest>> {
+        digest::compute(self, range, parts, max_keys).await
+    }
 }
 
 /// This is a toy implementation of a backend storage service.
@@ -97,9 +528,53 @@ pub trait Storage: KeyString + KeyList + Send + Sync {
 /// `&mut self`)
 #[derive(Debug, Default)]
 pub struct MemStorage {
-    kvs: RwLock<HashMap<String, String>>,
-    kv_list: RwLock<HashMap<String, List>>,
+    kvs: Arc<RwLock<Table<String>>>,
+    kv_list: Arc<RwLock<Table<List>>>,
     clock: RwLock<u64>,
+    changes: Mutex<ChangeLog>,
+    sweeping: AtomicBool,
+    hybrid_clock: bool,
+}
+
+/// Number of recent changes a [MemStorage] keeps around for watchers which
+/// resume from an earlier [Change::seq]
+pub const WATCH_HISTORY: usize = 1024;
+
+/// How often a [MemStorage] drops the entries which have expired. Expired
+/// entries are invisible to reads as soon as they expire; this only bounds
+/// how long they keep taking up memory.
+pub const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The change-notification hook of a [MemStorage]
+#[derive(Debug)]
+struct ChangeLog {
+    seq: u64,
+    history: VecDeque<Change>,
+    tx: broadcast::Sender<Change>,
+}
+
+impl Default for ChangeLog {
+    fn default() -> Self {
+        ChangeLog {
+            seq: 0,
+            history: VecDeque::with_capacity(WATCH_HISTORY),
+            tx: broadcast::channel(WATCH_HISTORY).0,
+        }
+    }
+}
+
+/// Returns the current wall-clock time in milliseconds since the Unix
+/// epoch, which is what expiry deadlines are measured in.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Returns the deadline of an entry which expires `ttl` from now.
+pub(crate) fn deadline_after(ttl: Duration) -> u64 {
+    now_millis().saturating_add(ttl.as_millis() as u64)
 }
 
 impl MemStorage {
@@ -107,104 +582,410 @@ impl MemStorage {
     pub fn new() -> MemStorage {
         MemStorage::default()
     }
+
+    /// Switches [Storage::clock] to a hybrid logical clock: every value it
+    /// returns is also no smaller than [crate::hlc::now], so clock values
+    /// track wall-clock time while keeping the ordering guarantees of
+    /// [Storage::clock]. See [crate::hlc] for the encoding.
+    pub fn with_hybrid_clock(mut self) -> MemStorage {
+        self.hybrid_clock = true;
+        self
+    }
+
+    /// records a change and notifies watchers. This is called while still
+    /// holding the lock of the data that changed, so changes are numbered
+    /// in the order they were applied.
+    fn publish(&self, kind: ChangeKind, kv: &KeyValue) -> TribResult<()> {
+        let mut log = self.changes.lock().map_err(|e| e.to_string())?;
+        log.seq += 1;
+        let change = Change {
+            seq: log.seq,
+            kind,
+            key: kv.key.clone(),
+            value: kv.value.clone(),
+        };
+        if log.history.len() == WATCH_HISTORY {
+            log.history.pop_front();
+        }
+        log.history.push_back(change.clone());
+        // an error only means nobody is watching
+        let _ = log.tx.send(change);
+        Ok(())
+    }
+
+    /// starts the task which drops expired entries every
+    /// [EXPIRY_SWEEP_INTERVAL], unless it is already running. The task only
+    /// holds weak references, and stops once the storage is dropped.
+    fn start_sweeper(&self) {
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(h) => h,
+            // without a runtime, expired entries are still dropped lazily
+            Err(_) => return,
+        };
+        if self.sweeping.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let kvs = Arc::downgrade(&self.kvs);
+        let kv_list = Arc::downgrade(&self.kv_list);
+        handle.spawn(async move {
+            let mut ticker = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+            loop {
+                ticker.tick().await;
+                match (kvs.upgrade(), kv_list.upgrade()) {
+                    (Some(kvs), Some(kv_list)) => sweep(&kvs, &kv_list),
+                    _ => return,
+                }
+            }
+        });
+    }
+
+    /// Same as [KeyString::set_ttl], with the expiry given as a deadline in
+    /// milliseconds since the Unix epoch.
+    pub(crate) fn set_until(&self, kv: &KeyValue, deadline: u64) -> TribResult<bool> {
+        let mut kvs = self.kvs.write().map_err(|e| e.to_string())?;
+        set_in(&mut kvs, kv);
+        if !kv.value.is_empty() {
+            kvs.set_deadline(&kv.key, deadline);
+        }
+        self.publish(ChangeKind::Set, kv)?;
+        drop(kvs);
+        self.start_sweeper();
+        Ok(true)
+    }
+
+    /// Same as [KeyList::list_append_ttl], with the expiry given as a
+    /// deadline in milliseconds since the Unix epoch.
+    pub(crate) fn list_append_until(&self, kv: &KeyValue, deadline: u64) -> TribResult<bool> {
+        let mut kvl = self.kv_list.write().map_err(|e| e.to_string())?;
+        list_append_in(&mut kvl, kv, now_millis());
+        kvl.set_deadline(&kv.key, deadline);
+        self.publish(ChangeKind::ListAppend, kv)?;
+        drop(kvl);
+        self.start_sweeper();
+        Ok(true)
+    }
+
+    /// Returns the expiry deadline of the pair `key`, if it has one.
+    pub(crate) fn deadline(&self, key: &str) -> TribResult<Option<u64>> {
+        let kvs = self.kvs.read().map_err(|e| e.to_string())?;
+        Ok(kvs.deadlines.get(key).copied())
+    }
+
+    /// Returns the expiry deadline of the list `key`, if it has one.
+    pub(crate) fn list_deadline(&self, key: &str) -> TribResult<Option<u64>> {
+        let kvl = self.kv_list.read().map_err(|e| e.to_string())?;
+        Ok(kvl.deadlines.get(key).copied())
+    }
+}
+
+/// drops the expired entries of both halves of a [MemStorage]
+fn sweep(kvs: &RwLock<Table<String>>, kv_list: &RwLock<Table<List>>) {
+    let now = now_millis();
+    if let Ok(mut kvs) = kvs.write() {
+        kvs.expire(now);
+    }
+    if let Ok(mut kvl) = kv_list.write() {
+        kvl.expire(now);
+    }
+}
+
+/// The entries of one half of a [MemStorage], along with the deadlines of
+/// the ones which expire. Expired entries are skipped by every read, and
+/// dropped by the next write to their key or by the sweeper, whichever
+/// comes first.
+#[derive(Debug)]
+struct Table<V> {
+    entries: BTreeMap<String, V>,
+    /// the same keys, ordered by [digest::position], so a digest visits only
+    /// the keys in its range
+    positions: BTreeSet<(u64, String)>,
+    deadlines: HashMap<String, u64>,
+    /// the same deadlines, ordered by time
+    expiring: BTreeSet<(u64, String)>,
+}
+
+impl<V> Default for Table<V> {
+    fn default() -> Self {
+        Table {
+            entries: BTreeMap::new(),
+            positions: BTreeSet::new(),
+            deadlines: HashMap::new(),
+            expiring: BTreeSet::new(),
+        }
+    }
+}
+
+impl<V> Table<V> {
+    fn is_live(&self, key: &str, now: u64) -> bool {
+        !matches!(self.deadlines.get(key), Some(d) if *d <= now)
+    }
+
+    fn get(&self, key: &str, now: u64) -> Option<&V> {
+        match self.is_live(key, now) {
+            true => self.entries.get(key),
+            false => None,
+        }
+    }
+
+    fn insert(&mut self, key: &str, value: V) {
+        if self.entries.insert(key.to_string(), value).is_none() {
+            self.positions
+                .insert((digest::position(key), key.to_string()));
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.clear_deadline(key);
+        if self.entries.remove(key).is_some() {
+            self.positions
+                .remove(&(digest::position(key), key.to_string()));
+        }
+    }
+
+    /// the live entries whose [digest::position] is in `range`
+    fn in_range(&self, range: HashRange, now: u64) -> impl Iterator<Item = (&String, &V)> {
+        self.positions
+            .range((range.first, String::new())..)
+            .take_while(move |(p, _)| *p <= range.last)
+            .filter(move |(_, k)| self.is_live(k, now))
+            .filter_map(move |(_, k)| self.entries.get_key_value(k))
+    }
+
+    fn set_deadline(&mut self, key: &str, deadline: u64) {
+        self.clear_deadline(key);
+        self.deadlines.insert(key.to_string(), deadline);
+        self.expiring.insert((deadline, key.to_string()));
+    }
+
+    fn clear_deadline(&mut self, key: &str) {
+        if let Some(d) = self.deadlines.remove(key) {
+            self.expiring.remove(&(d, key.to_string()));
+        }
+    }
+
+    /// drops `key` if it has expired, so a write to it starts afresh
+    fn purge(&mut self, key: &str, now: u64) {
+        if !self.is_live(key, now) {
+            self.remove(key);
+        }
+    }
+
+    /// drops every entry which has expired
+    fn expire(&mut self, now: u64) {
+        let pending = self.expiring.split_off(&(now + 1, String::new()));
+        for (_, key) in std::mem::replace(&mut self.expiring, pending) {
+            self.deadlines.remove(&key);
+            self.entries.remove(&key);
+            self.positions.remove(&(digest::position(&key), key));
+        }
+    }
+}
+
+fn set_in(kvs: &mut Table<String>, kv: &KeyValue) -> bool {
+    if kv.value.is_empty() {
+        kvs.remove(&kv.key);
+    } else {
+        kvs.clear_deadline(&kv.key);
+        kvs.insert(&kv.key, kv.value.clone());
+    }
+    true
+}
+
+fn incr_in(kvs: &mut Table<String>, key: &str, delta: i64, now: u64) -> TribResult<i64> {
+    kvs.purge(key, now);
+    let value = incr_value(key, kvs.entries.get(key).map(String::as_str), delta)?;
+    kvs.insert(key, value.to_string());
+    Ok(value)
+}
+
+fn keys_in(kvs: &Table<String>, p: &Matcher, now: u64) -> List {
+    List(
+        kvs.entries
+            .keys()
+            .filter(|k| p.matches(k) && kvs.is_live(k, now))
+            .cloned()
+            .collect::<Vec<String>>(),
+    )
+}
+
+/// scans the live keys of `table` matching `p`, starting right after
+/// `cursor`
+fn scan_in<V>(
+    table: &Table<V>,
+    p: &Pattern,
+    cursor: &str,
+    limit: u32,
+    now: u64,
+) -> TribResult<Scan> {
+    let m = p.matcher()?;
+    // matching keys all sort at or after the prefix, so the scan can start
+    // there and stop at the first key without it.
+    let start = match decode_cursor(cursor)? {
+        Some(after) if after >= p.prefix => Bound::Excluded(after),
+        _ => Bound::Included(p.prefix.clone()),
+    };
+    let keys = table
+        .entries
+        .range((start, Bound::Unbounded))
+        .map(|(k, _)| k)
+        .take_while(|k| k.starts_with(&p.prefix))
+        .filter(|k| m.matches(k) && table.is_live(k, now));
+    Ok(scan_page(keys, limit))
+}
+
+fn list_get_in(kvl: &Table<List>, key: &str, now: u64) -> List {
+    match kvl.get(key, now) {
+        Some(l) => l.clone(),
+        None => List(vec![]),
+    }
+}
+
+fn list_append_in(kvl: &mut Table<List>, kv: &KeyValue, now: u64) -> bool {
+    kvl.purge(&kv.key, now);
+    match kvl.entries.get_mut(&kv.key) {
+        Some(list) => list.0.push(kv.value.clone()),
+        None => kvl.insert(&kv.key, List(vec![kv.value.clone()])),
+    }
+    true
+}
+
+fn list_remove_in(kvl: &mut Table<List>, kv: &KeyValue, now: u64) -> u32 {
+    kvl.purge(&kv.key, now);
+    let mut removed = 0;
+    if let Some(list) = kvl.entries.get_mut(&kv.key) {
+        let begin_size = list.0.len();
+        list.0.retain(|val| *val != kv.value);
+        removed = begin_size - list.0.len();
+        if list.0.is_empty() {
+            kvl.remove(&kv.key);
+        }
+    }
+    removed as u32
+}
+
+fn list_keys_in(kvl: &Table<List>, p: &Matcher, now: u64) -> List {
+    let mut result = kvl
+        .entries
+        .keys()
+        .filter(|k| p.matches(k) && kvl.is_live(k, now))
+        .cloned()
+        .collect::<Vec<String>>();
+    result.sort();
+    List(result)
 }
 
 #[async_trait]
 impl KeyString for MemStorage {
     async fn get(&self, key: &str) -> TribResult<Option<String>> {
-        match self.kvs.read().map_err(|e| e.to_string())?.get(key) {
-            Some(v) => Ok(Some(v.to_string())),
-            None => Ok(None),
-        }
+        let kvs = self.kvs.read().map_err(|e| e.to_string())?;
+        Ok(kvs.get(key, now_millis()).cloned())
     }
 
     async fn set(&self, kv: &KeyValue) -> TribResult<bool> {
         let mut entry = self.kvs.write().map_err(|e| e.to_string())?;
-        if kv.value.is_empty() {
-            entry.remove(&kv.key);
-        } else {
-            entry.insert(kv.key.clone(), kv.value.clone());
-        }
+        set_in(&mut entry, kv);
+        self.publish(ChangeKind::Set, kv)?;
         Ok(true)
     }
 
+    async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        self.set_until(kv, deadline_after(ttl))
+    }
+
+    async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)> {
+        let mut entry = self.kvs.write().map_err(|e| e.to_string())?;
+        entry.purge(&kv.key, now_millis());
+        let current = entry.entries.get(&kv.key);
+        if current.map(String::as_str).unwrap_or("") != old {
+            return Ok((false, current.cloned()));
+        }
+        set_in(&mut entry, kv);
+        self.publish(ChangeKind::Set, kv)?;
+        Ok((true, entry.entries.get(&kv.key).cloned()))
+    }
+
+    async fn incr(&self, key: &str, delta: i64) -> TribResult<i64> {
+        let mut entry = self.kvs.write().map_err(|e| e.to_string())?;
+        let value = incr_in(&mut entry, key, delta, now_millis())?;
+        self.publish(ChangeKind::Set, &KeyValue::new(key, &value.to_string()))?;
+        Ok(value)
+    }
+
     async fn keys(&self, p: &Pattern) -> TribResult<List> {
-        let result = self
-            .kvs
-            .read()
-            .map_err(|e| e.to_string())?
-            .iter()
-            .filter(|(k, _)| p.matches(*k))
-            .map(|(k, _)| k.to_string())
-            .collect::<Vec<String>>();
-        Ok(List(result))
+        let m = p.matcher()?;
+        let kvs = self.kvs.read().map_err(|e| e.to_string())?;
+        Ok(keys_in(&kvs, &m, now_millis()))
+    }
+
+    async fn scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        let kvs = self.kvs.read().map_err(|e| e.to_string())?;
+        scan_in(&kvs, p, cursor, limit, now_millis())
     }
 }
 
 #[async_trait]
 impl KeyList for MemStorage {
     async fn list_get(&self, key: &str) -> TribResult<List> {
-        match self.kv_list.read().map_err(|e| e.to_string())?.get(key) {
-            Some(l) => Ok(l.clone()),
-            None => Ok(List(vec![])),
-        }
+        let kvl = self.kv_list.read().map_err(|e| e.to_string())?;
+        Ok(list_get_in(&kvl, key, now_millis()))
     }
 
     async fn list_append(&self, kv: &KeyValue) -> TribResult<bool> {
         let mut kvl = self.kv_list.write().map_err(|e| e.to_string())?;
-        match kvl.get_mut(&kv.key) {
-            Some(list) => {
-                list.0.push(kv.value.clone());
-                Ok(true)
-            }
-            None => {
-                let list = vec![kv.value.clone()];
-                kvl.insert(kv.key.clone(), List(list));
-                Ok(true)
-            }
-        }
+        list_append_in(&mut kvl, kv, now_millis());
+        self.publish(ChangeKind::ListAppend, kv)?;
+        Ok(true)
+    }
+
+    async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+        self.list_append_until(kv, deadline_after(ttl))
     }
 
     async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32> {
-        let mut removed = 0;
         let mut kvl = self.kv_list.write().map_err(|e| e.to_string())?;
-        kvl.entry(kv.key.clone()).and_modify(|list| {
-            let begin_size = list.0.len();
-            *list = List(
-                list.0
-                    .iter()
-                    .filter(|val| **val != kv.value)
-                    .map(String::from)
-                    .collect::<Vec<String>>(),
-            );
-            let end_size = list.0.len();
-            removed = begin_size - end_size;
-        });
-        if let Some(x) = kvl.get(&kv.key) {
-            if x.0.is_empty() {
-                kvl.remove(&kv.key);
-            }
-        };
-
-        Ok(removed as u32)
+        let removed = list_remove_in(&mut kvl, kv, now_millis());
+        if removed > 0 {
+            self.publish(ChangeKind::ListRemove, kv)?;
+        }
+        Ok(removed)
     }
 
     async fn list_keys(&self, p: &Pattern) -> TribResult<List> {
-        let mut result = vec![];
-        self.kv_list
-            .read()
-            .map_err(|e| e.to_string())?
-            .iter()
-            .filter(|(k, _)| p.matches(*k))
-            .for_each(|(v, _)| result.push((*v).clone()));
-        result.sort();
-        Ok(List(result))
+        let m = p.matcher()?;
+        let kvl = self.kv_list.read().map_err(|e| e.to_string())?;
+        Ok(list_keys_in(&kvl, &m, now_millis()))
+    }
+
+    async fn list_scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+        let kvl = self.kv_list.read().map_err(|e| e.to_string())?;
+        scan_in(&kvl, p, cursor, limit, now_millis())
+    }
+
+    async fn list_range(&self, key: &str, start: i64, end: i64) -> TribResult<List> {
+        let kvl = self.kv_list.read().map_err(|e| e.to_string())?;
+        let list = match kvl.get(key, now_millis()) {
+            Some(l) => &l.0[..],
+            None => &[],
+        };
+        Ok(List(match range_bounds(list.len(), start, end) {
+            Some((s, e)) => list[s..e].to_vec(),
+            None => vec![],
+        }))
+    }
+
+    async fn list_len(&self, key: &str) -> TribResult<u64> {
+        let kvl = self.kv_list.read().map_err(|e| e.to_string())?;
+        Ok(kvl.get(key, now_millis()).map(|l| l.0.len()).unwrap_or(0) as u64)
     }
 }
 
 #[async_trait]
 impl Storage for MemStorage {
     async fn clock(&self, at_least: u64) -> TribResult<u64> {
+        let at_least = match self.hybrid_clock {
+            true => at_least.max(hlc::now()),
+            false => at_least,
+        };
         let mut clk = self.clock.write().map_err(|e| e.to_string())?;
         if *clk < at_least {
             *clk = at_least
@@ -217,8 +998,213 @@ impl Storage for MemStorage {
         }
         Ok(ret)
     }
+
+    async fn batch(&self, ops: &[Op]) -> TribResult<Vec<OpResult>> {
+        // patterns are compiled up front so an invalid one fails the whole
+        // batch before anything is applied.
+        let matchers = ops
+            .iter()
+            .filter_map(|op| match op {
+                Op::Keys(p) | Op::ListKeys(p) => Some(p.matcher()),
+                _ => None,
+            })
+            .collect::<TribResult<Vec<Matcher>>>()?;
+        let mut matchers = matchers.iter();
+        // both locks are always taken in this order, so a batch is applied
+        // atomically with respect to every other operation.
+        let mut kvs = self.kvs.write().map_err(|e| e.to_string())?;
+        let mut kvl = self.kv_list.write().map_err(|e| e.to_string())?;
+        let now = now_millis();
+        let mut results = Vec::with_capacity(ops.len());
+        for op in ops {
+            results.push(match op {
+                Op::Get(key) => OpResult::Value(kvs.get(key, now).cloned()),
+                Op::Set(kv) => {
+                    set_in(&mut kvs, kv);
+                    self.publish(ChangeKind::Set, kv)?;
+                    OpResult::Bool(true)
+                }
+                Op::Keys(_) => OpResult::List(keys_in(&kvs, matchers.next().unwrap(), now)),
+                Op::ListGet(key) => OpResult::List(list_get_in(&kvl, key, now)),
+                Op::ListAppend(kv) => {
+                    list_append_in(&mut kvl, kv, now);
+                    self.publish(ChangeKind::ListAppend, kv)?;
+                    OpResult::Bool(true)
+                }
+                Op::ListRemove(kv) => {
+                    let removed = list_remove_in(&mut kvl, kv, now);
+                    if removed > 0 {
+                        self.publish(ChangeKind::ListRemove, kv)?;
+                    }
+                    OpResult::Removed(removed)
+                }
+                Op::ListKeys(_) => {
+                    OpResult::List(list_keys_in(&kvl, matchers.next().unwrap(), now))
+                }
+            });
+        }
+        Ok(results)
+    }
+
+    async fn digest(
+        &self,
+        range: HashRange,
+        parts: u32,
+        max_keys: u32,
+    ) -> TribResult<Vec<RangeDigest>> {
+        // taken in the same order as by a batch, so the digest is that of a
+        // single state
+        let kvs = self.kvs.read().map_err(|e| e.to_string())?;
+        let kvl = self.kv_list.read().map_err(|e| e.to_string())?;
+        let now = now_millis();
+        let values = kvs.in_range(range, now).map(|(k, v)| KeyDigest {
+            key: k.clone(),
+            list: false,
+            hash: digest::value_hash(k, v),
+        });
+        let lists = kvl.in_range(range, now).map(|(k, l)| KeyDigest {
+            key: k.clone(),
+            list: true,
+            hash: digest::list_hash(k, &l.0),
+        });
+        Ok(digest::combine(range, parts, max_keys, values.chain(lists)))
+    }
+
+    async fn watch(&self, p: &Pattern, resume: u64) -> TribResult<ChangeStream> {
+        let m = p.matcher()?;
+        // holding the lock while subscribing guarantees that no change is
+        // both replayed and delivered live, or missed by both.
+        let log = self.changes.lock().map_err(|e| e.to_string())?;
+        let oldest = log.history.front().map(|c| c.seq).unwrap_or(log.seq + 1);
+        if resume > log.seq || (resume > 0 && resume + 1 < oldest) {
+            return Err(Box::new(TribblerError::Status(
+                ErrorCode::Invalid,
+                format!("cannot resume watch from {}", resume),
+            )));
+        }
+        let backlog = match resume {
+            0 => vec![],
+            _ => log
+                .history
+                .iter()
+                .filter(|c| c.seq > resume && m.matches(&c.key))
+                .cloned()
+                .map(Ok)
+                .collect::<Vec<TribResult<Change>>>(),
+        };
+        let live = BroadcastStream::new(log.tx.subscribe()).filter_map(move |r| match r {
+            Ok(c) if m.matches(&c.key) => Some(Ok(c)),
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(n)) => Some(Err(TribblerError::Unknown(format!(
+                "watcher fell behind by {} changes",
+                n
+            ))
+            .into())),
+        });
+        Ok(Box::pin(tokio_stream::iter(backlog).chain(live)))
+    }
 }
 
+/// Implements the storage traits for a smart pointer to a storage by
+/// forwarding every call, overridden defaults included, to the pointee.
+macro_rules! forward_storage {
+    ($($ptr:ident),*) => {$(
+        #[async_trait]
+        impl<T: KeyString + ?Sized + Send + Sync> KeyString for $ptr<T> {
+            async fn get(&self, key: &str) -> TribResult<Option<String>> {
+                (**self).get(key).await
+            }
+
+            async fn set(&self, kv: &KeyValue) -> TribResult<bool> {
+                (**self).set(kv).await
+            }
+
+            async fn set_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+                (**self).set_ttl(kv, ttl).await
+            }
+
+            async fn cas(&self, kv: &KeyValue, old: &str) -> TribResult<(bool, Option<String>)> {
+                (**self).cas(kv, old).await
+            }
+
+            async fn incr(&self, key: &str, delta: i64) -> TribResult<i64> {
+                (**self).incr(key, delta).await
+            }
+
+            async fn keys(&self, p: &Pattern) -> TribResult<List> {
+                (**self).keys(p).await
+            }
+
+            async fn scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+                (**self).scan(p, cursor, limit).await
+            }
+        }
+
+        #[async_trait]
+        impl<T: KeyList + ?Sized + Send + Sync> KeyList for $ptr<T> {
+            async fn list_get(&self, key: &str) -> TribResult<List> {
+                (**self).list_get(key).await
+            }
+
+            async fn list_append(&self, kv: &KeyValue) -> TribResult<bool> {
+                (**self).list_append(kv).await
+            }
+
+            async fn list_append_ttl(&self, kv: &KeyValue, ttl: Duration) -> TribResult<bool> {
+                (**self).list_append_ttl(kv, ttl).await
+            }
+
+            async fn list_remove(&self, kv: &KeyValue) -> TribResult<u32> {
+                (**self).list_remove(kv).await
+            }
+
+            async fn list_keys(&self, p: &Pattern) -> TribResult<List> {
+                (**self).list_keys(p).await
+            }
+
+            async fn list_range(&self, key: &str, start: i64, end: i64) -> TribResult<List> {
+                (**self).list_range(key, start, end).await
+            }
+
+            async fn list_scan(&self, p: &Pattern, cursor: &str, limit: u32) -> TribResult<Scan> {
+                (**self).list_scan(p, cursor, limit).await
+            }
+
+            async fn list_len(&self, key: &str) -> TribResult<u64> {
+                (**self).list_len(key).await
+            }
+        }
+
+        #[async_trait]
+        impl<T: Storage + ?Sized> Storage for $ptr<T> {
+            async fn clock(&self, at_least: u64) -> TribResult<u64> {
+                (**self).clock(at_least).await
+            }
+
+            async fn watch(&self, p: &Pattern, resume: u64) -> TribResult<ChangeStream> {
+                (**self).watch(p, resume).await
+            }
+
+            async fn batch(&self, ops: &[Op]) -> TribResult<Vec<OpResult>> {
+                (**self).batch(ops).await
+            }
+
+            async fn digest(
+                &self,
+                range: HashRange,
+                parts: u32,
+                max_keys: u32,
+            ) -> TribResult<Vec<RangeDigest>> {
+                (**self).digest(range, parts, max_keys).await
+            }
+        }
+    )*};
+}
+
+// lets a storage be shared, e.g. by several [crate::namespace::Bin]s, or
+// wrapped while boxed
+forward_storage!(Box, Arc);
+
 #[async_trait]
 /// Bin Storage interface
 pub trait BinStorage: Send + Sync {
@@ -228,12 +1214,20 @@ pub trait BinStorage: Send + Sync {
 
 #[cfg(test)]
 mod test {
+    use tokio_stream::StreamExt;
+
     use crate::{
         err::TribResult,
+        hlc,
         storage::{KeyValue, Pattern, Storage},
     };
 
-    use super::{KeyList, KeyString, MemStorage};
+    use std::time::Duration;
+
+    use super::{
+        digest::{self, HashRange},
+        ChangeKind, KeyList, KeyString, MemStorage, Op, OpResult, EXPIRY_SWEEP_INTERVAL,
+    };
 
     async fn setup_test_storage() -> MemStorage {
         let storage = MemStorage::new();
@@ -270,6 +1264,278 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn storage_cas() -> TribResult<()> {
+        let storage = setup_test_storage().await;
+        assert_eq!(
+            (false, Some("test-value".to_string())),
+            storage.cas(&KeyValue::new("test", "v2"), "wrong").await?
+        );
+        assert_eq!(
+            (true, Some("v2".to_string())),
+            storage
+                .cas(&KeyValue::new("test", "v2"), "test-value")
+                .await?
+        );
+        assert_eq!(Some("v2".to_string()), storage.get("test").await?);
+        assert_eq!(
+            (true, None),
+            storage.cas(&KeyValue::new("test", ""), "v2").await?
+        );
+        assert_eq!(None, storage.get("test").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_cas_absent() -> TribResult<()> {
+        let storage = MemStorage::new();
+        assert_eq!(
+            (true, Some("a".to_string())),
+            storage.cas(&KeyValue::new("user", "a"), "").await?
+        );
+        assert_eq!(
+            (false, Some("a".to_string())),
+            storage.cas(&KeyValue::new("user", "b"), "").await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_batch() -> TribResult<()> {
+        let storage = setup_test_storage().await;
+        let r = storage
+            .batch(&[
+                Op::Get("test".to_string()),
+                Op::Set(KeyValue::new("test", "v2")),
+                Op::Get("test".to_string()),
+                Op::ListAppend(KeyValue::new("l", "a")),
+                Op::ListAppend(KeyValue::new("l", "a")),
+                Op::ListGet("l".to_string()),
+                Op::ListRemove(KeyValue::new("l", "a")),
+                Op::ListKeys(Pattern::default()),
+                Op::Keys(Pattern::default()),
+            ])
+            .await?;
+        assert_eq!(9, r.len());
+        assert!(matches!(&r[0], OpResult::Value(Some(v)) if v == "test-value"));
+        assert!(matches!(&r[1], OpResult::Bool(true)));
+        assert!(matches!(&r[2], OpResult::Value(Some(v)) if v == "v2"));
+        assert!(matches!(&r[5], OpResult::List(l) if l.0.len() == 2));
+        assert!(matches!(&r[6], OpResult::Removed(2)));
+        assert!(matches!(&r[7], OpResult::List(l) if l.0 == vec!["test".to_string()]));
+        assert!(matches!(&r[8], OpResult::List(l) if l.0.len() == 1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_watch() -> TribResult<()> {
+        let storage = setup_test_storage().await;
+        let mut w = storage
+            .watch(
+                &Pattern {
+                    prefix: "a".to_string(),
+                    ..Default::default()
+                },
+                0,
+            )
+            .await?;
+        storage.set(&KeyValue::new("b", "skipped")).await?;
+        storage.set(&KeyValue::new("a1", "x")).await?;
+        storage.list_append(&KeyValue::new("a2", "y")).await?;
+        storage.list_remove(&KeyValue::new("a2", "nothing")).await?;
+        storage.list_remove(&KeyValue::new("a2", "y")).await?;
+        let c = w.next().await.unwrap()?;
+        assert_eq!((ChangeKind::Set, "a1", "x"), (c.kind, &*c.key, &*c.value));
+        let c = w.next().await.unwrap()?;
+        assert_eq!(ChangeKind::ListAppend, c.kind);
+        let last = w.next().await.unwrap()?;
+        assert_eq!(ChangeKind::ListRemove, last.kind);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_watch_resume() -> TribResult<()> {
+        let storage = MemStorage::new();
+        storage.set(&KeyValue::new("k", "1")).await?;
+        let mut w = storage.watch(&Pattern::default(), 0).await?;
+        storage.set(&KeyValue::new("k", "2")).await?;
+        let seen = w.next().await.unwrap()?;
+        drop(w);
+        storage.set(&KeyValue::new("k", "3")).await?;
+        storage.list_append(&KeyValue::new("l", "4")).await?;
+        let mut w = storage.watch(&Pattern::default(), seen.seq).await?;
+        assert_eq!("3", w.next().await.unwrap()?.value);
+        assert_eq!("4", w.next().await.unwrap()?.value);
+        assert!(storage.watch(&Pattern::default(), 100).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_incr() -> TribResult<()> {
+        let storage = MemStorage::new();
+        assert_eq!(5, storage.incr("c", 5).await?);
+        assert_eq!(3, storage.incr("c", -2).await?);
+        assert_eq!(Some("3".to_string()), storage.get("c").await?);
+        assert_eq!(-3, storage.incr("c", -6).await?);
+        assert_eq!(0, storage.incr("c", 3).await?);
+        assert_eq!(Some("0".to_string()), storage.get("c").await?);
+
+        storage.set(&KeyValue::new("s", "abc")).await?;
+        assert!(storage.incr("s", 1).await.is_err());
+        assert_eq!(Some("abc".to_string()), storage.get("s").await?);
+        storage
+            .set(&KeyValue::new("m", &i64::MAX.to_string()))
+            .await?;
+        assert!(storage.incr("m", 1).await.is_err());
+
+        let storage = std::sync::Arc::new(storage);
+        let mut handles = vec![];
+        for _ in 0..10 {
+            let storage = storage.clone();
+            handles.push(tokio::spawn(async move {
+                for _ in 0..100 {
+                    storage.incr("n", 1).await.unwrap();
+                }
+            }));
+        }
+        for h in handles {
+            h.await?;
+        }
+        assert_eq!(Some("1000".to_string()), storage.get("n").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_ttl() -> TribResult<()> {
+        let storage = MemStorage::new();
+        let ttl = Duration::from_millis(50);
+        storage.set_ttl(&KeyValue::new("a", "1"), ttl).await?;
+        storage.set_ttl(&KeyValue::new("b", "2"), ttl).await?;
+        storage.set(&KeyValue::new("b", "3")).await?;
+        storage.set_ttl(&KeyValue::new("c", "4"), ttl).await?;
+        assert_eq!(Some("1".to_string()), storage.get("a").await?);
+        assert_eq!(3, storage.keys(&Pattern::default()).await?.0.len());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(None, storage.get("a").await?);
+        // a plain set clears the expiry
+        assert_eq!(Some("3".to_string()), storage.get("b").await?);
+        assert_eq!(vec!["b"], storage.keys(&Pattern::default()).await?.0);
+        assert_eq!(
+            vec!["b"],
+            storage.scan(&Pattern::default(), "", 0).await?.keys.0
+        );
+        // an expired key is unset as far as cas is concerned
+        assert!(!storage.cas(&KeyValue::new("c", "5"), "4").await?.0);
+        assert!(storage.cas(&KeyValue::new("c", "5"), "").await?.0);
+        assert_eq!(Some("5".to_string()), storage.get("c").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_list_ttl() -> TribResult<()> {
+        let storage = MemStorage::new();
+        let ttl = Duration::from_millis(50);
+        storage
+            .list_append_ttl(&KeyValue::new("l", "x"), ttl)
+            .await?;
+        storage.list_append(&KeyValue::new("l", "y")).await?;
+        storage.list_append(&KeyValue::new("m", "z")).await?;
+        assert_eq!(2, storage.list_len("l").await?);
+        assert_eq!(2, storage.list_keys(&Pattern::default()).await?.0.len());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(0, storage.list_get("l").await?.0.len());
+        assert_eq!(0, storage.list_range("l", 0, -1).await?.0.len());
+        assert_eq!(vec!["m"], storage.list_keys(&Pattern::default()).await?.0);
+        assert_eq!(
+            vec!["m"],
+            storage.list_scan(&Pattern::default(), "", 0).await?.keys.0
+        );
+        // appending to an expired list starts a new one without expiry
+        storage.list_append(&KeyValue::new("l", "w")).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(vec!["w"], storage.list_get("l").await?.0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_ttl_sweep() -> TribResult<()> {
+        let storage = MemStorage::new();
+        let ttl = Duration::from_millis(10);
+        storage.set_ttl(&KeyValue::new("a", "1"), ttl).await?;
+        storage
+            .list_append_ttl(&KeyValue::new("l", "x"), ttl)
+            .await?;
+        tokio::time::sleep(EXPIRY_SWEEP_INTERVAL * 2).await;
+        assert!(storage.kvs.read().unwrap().entries.is_empty());
+        assert!(storage.kv_list.read().unwrap().entries.is_empty());
+        assert!(storage.kvs.read().unwrap().expiring.is_empty());
+        assert!(storage.kvs.read().unwrap().positions.is_empty());
+        assert!(storage.kv_list.read().unwrap().positions.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_digest() -> TribResult<()> {
+        let storage = MemStorage::new();
+        for i in 0..40 {
+            storage
+                .set(&KeyValue::new(&format!("bin{}::k", i), "v"))
+                .await?;
+            storage
+                .list_append(&KeyValue::new(&format!("bin{}::l", i), "x"))
+                .await?;
+        }
+        storage.set(&KeyValue::new("bin3::k", "")).await?;
+        storage.list_remove(&KeyValue::new("bin4::l", "x")).await?;
+        let ttl = Duration::from_millis(10);
+        storage.set_ttl(&KeyValue::new("bin5::k", "w"), ttl).await?;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // the index gives the digests the keys and entries give
+        let expect = |range, parts, max_keys| digest::compute(&storage, range, parts, max_keys);
+        for range in HashRange::ALL.split(3) {
+            assert_eq!(
+                expect(range, 4, 5).await?,
+                storage.digest(range, 4, 5).await?
+            );
+        }
+        let whole = storage.digest(HashRange::ALL, 1, 100).await?;
+        assert_eq!(77, whole[0].count);
+        assert_eq!(expect(HashRange::ALL, 1, 100).await?, whole);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_list_range() -> TribResult<()> {
+        let storage = MemStorage::new();
+        for i in 0..10 {
+            storage
+                .list_append(&KeyValue::new("l", &i.to_string()))
+                .await?;
+        }
+        let range = |s: &[&str]| s.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        assert_eq!(
+            range(&["0", "1", "2"]),
+            storage.list_range("l", 0, 2).await?.0
+        );
+        assert_eq!(
+            range(&["7", "8", "9"]),
+            storage.list_range("l", -3, -1).await?.0
+        );
+        assert_eq!(range(&["8", "9"]), storage.list_range("l", 8, 100).await?.0);
+        assert_eq!(range(&["0"]), storage.list_range("l", -100, 0).await?.0);
+        assert_eq!(10, storage.list_range("l", 0, -1).await?.0.len());
+        assert_eq!(0, storage.list_range("l", 5, 4).await?.0.len());
+        assert_eq!(0, storage.list_range("l", 10, 20).await?.0.len());
+        assert_eq!(0, storage.list_range("l", -20, -11).await?.0.len());
+        assert_eq!(0, storage.list_range("none", 0, -1).await?.0.len());
+        assert_eq!(10, storage.list_len("l").await?);
+        assert_eq!(0, storage.list_len("none").await?);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn storage_get_empty() -> TribResult<()> {
         let storage = setup_test_storage().await;
@@ -283,22 +1549,27 @@ mod test {
         let p1 = Pattern {
             prefix: "test".to_string(),
             suffix: "test".to_string(),
+            ..Default::default()
         };
         let p2 = Pattern {
             prefix: "".to_string(),
             suffix: "test".to_string(),
+            ..Default::default()
         };
         let p3 = Pattern {
             prefix: "test".to_string(),
             suffix: "".to_string(),
+            ..Default::default()
         };
         let p4 = Pattern {
             prefix: "wrong".to_string(),
             suffix: "right".to_string(),
+            ..Default::default()
         };
         let p5 = Pattern {
             prefix: "".to_string(),
             suffix: "".to_string(),
+            ..Default::default()
         };
         assert_eq!(1, storage.keys(&p1).await.unwrap().0.len());
         assert_eq!(1, storage.keys(&p2).await.unwrap().0.len());
@@ -307,6 +1578,107 @@ mod test {
         assert_eq!(1, storage.keys(&p5).await.unwrap().0.len());
     }
 
+    #[test]
+    fn pattern_glob() {
+        let glob = |g: &str, k: &str| {
+            Pattern {
+                glob: g.to_string(),
+                ..Default::default()
+            }
+            .matches(k)
+        };
+        assert!(glob("*", ""));
+        assert!(glob("a*::follow*", "alice::follow::bob"));
+        assert!(!glob("a*::follow*", "bob::follow::alice"));
+        assert!(glob("a?c", "abc"));
+        assert!(!glob("a?c", "ac"));
+        assert!(glob("*x*y", "axbxcy"));
+        assert!(!glob("*x*y", "axbxcyz"));
+        assert!(glob("[a-c]1", "b1"));
+        assert!(!glob("[!a-c]1", "b1"));
+        assert!(glob("[]]", "]"));
+        assert!(glob("\\*", "*"));
+        assert!(!glob("\\*", "a"));
+        assert!(glob("[ab", "[ab"));
+    }
+
+    #[tokio::test]
+    async fn storage_keys_glob_regex() -> TribResult<()> {
+        let storage = MemStorage::new();
+        for k in [
+            "alice::follow",
+            "alice::tribs",
+            "amy::follow",
+            "bob::follow",
+        ] {
+            storage.set(&KeyValue::new(k, "1")).await?;
+            storage.list_append(&KeyValue::new(k, "1")).await?;
+        }
+        let p = Pattern {
+            prefix: "a".to_string(),
+            glob: "*::follow*".to_string(),
+            ..Default::default()
+        };
+        let mut keys = storage.keys(&p).await?.0;
+        keys.sort();
+        assert_eq!(vec!["alice::follow", "amy::follow"], keys);
+        let p = Pattern {
+            regex: "^(alice|bob)::f".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec!["alice::follow", "bob::follow"],
+            storage.list_keys(&p).await?.0
+        );
+        let p = Pattern {
+            regex: "(".to_string(),
+            ..Default::default()
+        };
+        assert!(storage.keys(&p).await.is_err());
+        assert!(storage.list_keys(&p).await.is_err());
+        assert!(storage.batch(&[Op::Keys(p)]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_scan() -> TribResult<()> {
+        let storage = MemStorage::new();
+        for i in 0..25 {
+            let k = format!("k{:02}", i);
+            storage.set(&KeyValue::new(&k, "v")).await?;
+            storage.list_append(&KeyValue::new(&k, "v")).await?;
+        }
+        storage.set(&KeyValue::new("a", "v")).await?;
+        storage.set(&KeyValue::new("z", "v")).await?;
+        let p = Pattern {
+            prefix: "k".to_string(),
+            ..Default::default()
+        };
+        let mut keys = vec![];
+        let mut cursor = String::new();
+        let mut pages = 0;
+        loop {
+            let page = storage.scan(&p, &cursor, 10).await?;
+            assert!(page.keys.0.len() <= 10);
+            keys.extend(page.keys.0);
+            pages += 1;
+            if page.cursor.is_empty() {
+                break;
+            }
+            cursor = page.cursor;
+        }
+        assert_eq!(3, pages);
+        assert_eq!(storage.keys(&p).await?.0, keys);
+
+        let page = storage.list_scan(&Pattern::default(), "", 0).await?;
+        assert_eq!(25, page.keys.0.len());
+        assert!(page.cursor.is_empty());
+        let page = storage.list_scan(&Pattern::default(), "", 25).await?;
+        assert!(page.cursor.is_empty());
+        assert!(storage.scan(&p, "garbage", 10).await.is_err());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn storage_keys_unset() {
         let s = setup_test_storage().await;
@@ -373,22 +1745,27 @@ mod test {
         let p1 = Pattern {
             prefix: "test".to_string(),
             suffix: "test".to_string(),
+            ..Default::default()
         };
         let p2 = Pattern {
             prefix: "".to_string(),
             suffix: "test".to_string(),
+            ..Default::default()
         };
         let p3 = Pattern {
             prefix: "test".to_string(),
             suffix: "".to_string(),
+            ..Default::default()
         };
         let p4 = Pattern {
             prefix: "wrong".to_string(),
             suffix: "right".to_string(),
+            ..Default::default()
         };
         let p5 = Pattern {
             prefix: "".to_string(),
             suffix: "".to_string(),
+            ..Default::default()
         };
         assert_eq!(1, storage.list_keys(&p1).await.unwrap().0.len());
         assert_eq!(1, storage.list_keys(&p2).await.unwrap().0.len());
@@ -403,6 +1780,22 @@ mod test {
         assert_eq!(1234, storage.clock(1234).await.unwrap());
     }
 
+    #[tokio::test]
+    async fn clock_hybrid() -> TribResult<()> {
+        let storage = MemStorage::new().with_hybrid_clock();
+        let before = hlc::now();
+        let c1 = storage.clock(0).await?;
+        let c2 = storage.clock(0).await?;
+        assert!(c1 >= before);
+        assert!(c2 > c1);
+        assert!(hlc::physical(c2) <= hlc::physical(hlc::now()));
+        // a clock from a node running ahead is still honoured
+        let ahead = hlc::from_parts(hlc::physical(before) + 60_000, 3);
+        assert_eq!(ahead, storage.clock(ahead).await?);
+        assert_eq!(ahead + 1, storage.clock(c2).await?);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn clock_ge() {
         let storage = setup_test_storage().await;